@@ -0,0 +1,775 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use bytes::Bytes;
+use core_protobuf_data_access::invehicle_digital_twin::v1 as invehicle_digital_twin;
+use core_protobuf_data_access::module::digital_twin_graph::v1 as digital_twin_graph;
+use core_protobuf_data_access::module::digital_twin_registry::v1 as digital_twin_registry;
+use core_protobuf_data_access::module::managed_subscribe::v1 as managed_subscribe;
+use log::{debug, info};
+use prost::Message;
+use regex::Regex;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use tonic::Status;
+
+use crate::grpc_interceptor::{AsyncGrpcInterceptor, GrpcCallContext};
+use crate::utils;
+
+/// The name of the metadata entry that carries the bearer token.
+const AUTHORIZATION_METADATA_KEY: &str = "authorization";
+
+/// The authorization scheme of a bearer token.
+const BEARER_SCHEME: &str = "bearer ";
+
+/// The fully qualified names of the services whose calls are exempt from auth when the settings do
+/// not list any, so that the service's health can be checked without a token.
+const DEFAULT_EXEMPT_SERVICES: &[&str] = &["grpc.health.v1.Health"];
+
+/// The settings for token-based authentication and authorization.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuthSettings {
+    /// The name of a file that lists tokens and the identities that they belong to, so that the
+    /// tokens do not need to be kept in the settings file. It has a `tokens` list, like the
+    /// `tokens` setting.
+    pub token_filename: Option<String>,
+    /// The tokens and the identities that they belong to.
+    pub tokens: Option<Vec<TokenSettings>>,
+    /// The identities and their roles.
+    pub identities: Vec<IdentitySettings>,
+    /// The roles and the calls that they allow.
+    pub roles: Vec<RoleSettings>,
+    /// The fully qualified names of the services whose calls are neither authenticated nor
+    /// authorized, e.g. "grpc.health.v1.Health". If it is not provided, then only the gRPC health
+    /// service is exempt.
+    pub exempt_services: Option<Vec<String>>,
+}
+
+/// A token and the identity that it belongs to.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TokenSettings {
+    /// The bearer token.
+    pub token: String,
+    /// The name of the identity that presents the token.
+    pub identity: String,
+}
+
+/// The content of a token file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TokenFileSettings {
+    /// The tokens and the identities that they belong to.
+    pub tokens: Vec<TokenSettings>,
+}
+
+/// An identity, e.g. a provider or a consumer, and its roles.
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdentitySettings {
+    /// The identity's name.
+    pub name: String,
+    /// The names of the identity's roles.
+    pub roles: Vec<String>,
+}
+
+/// A role and the calls that it allows.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RoleSettings {
+    /// The role's name.
+    pub name: String,
+    /// The rules that allow calls. A call that no rule allows is denied.
+    pub rules: Vec<AccessRuleSettings>,
+}
+
+/// A rule that allows calls to methods, optionally only for some resources.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AccessRuleSettings {
+    /// Patterns for the allowed methods, in the form "<service name>/<method name>", e.g.
+    /// "InvehicleDigitalTwin/Register" or "DigitalTwinGraph/*".
+    pub methods: Vec<String>,
+    /// Patterns for the ids (usually DTMIs) that the calls may refer to, e.g. "dtmi:sdv:hvac:*".
+    /// If it is not provided, then the calls may refer to any id.
+    pub resources: Option<Vec<String>>,
+}
+
+/// A compiled access rule.
+#[derive(Debug)]
+struct AccessRule {
+    method_patterns: Vec<Regex>,
+    resource_patterns: Option<Vec<Regex>>,
+}
+
+impl AccessRule {
+    /// Does the rule allow calls to a method?
+    ///
+    /// # Arguments
+    /// * `method` - The method, in the form "<service name>/<method name>".
+    fn allows_method(&self, method: &str) -> bool {
+        self.method_patterns.iter().any(|pattern| pattern.is_match(method))
+    }
+
+    /// Does the rule allow calls to a method that refer to any resource?
+    ///
+    /// # Arguments
+    /// * `method` - The method, in the form "<service name>/<method name>".
+    fn allows_all_resources(&self, method: &str) -> bool {
+        self.allows_method(method) && self.resource_patterns.is_none()
+    }
+
+    /// Does the rule allow calls to a method that refer to a resource?
+    ///
+    /// # Arguments
+    /// * `method` - The method, in the form "<service name>/<method name>".
+    /// * `resource` - The resource's id.
+    fn allows_resource(&self, method: &str, resource: &str) -> bool {
+        self.allows_method(method)
+            && self
+                .resource_patterns
+                .as_ref()
+                .map_or(true, |patterns| patterns.iter().any(|pattern| pattern.is_match(resource)))
+    }
+}
+
+/// The compiled auth settings.
+#[derive(Debug, Default)]
+struct AuthPolicy {
+    /// The identities' names, keyed by their tokens.
+    identity_map: HashMap<String, String>,
+    /// The access rules of the identities' roles, keyed by identity name.
+    access_rule_map: HashMap<String, Vec<Arc<AccessRule>>>,
+    /// The fully qualified names of the services whose calls are exempt from auth.
+    exempt_services: Vec<String>,
+}
+
+/// An interceptor that authenticates calls with bearer tokens and authorizes them with the
+/// access rules of the caller's roles. Unauthenticated calls are rejected with an unauthenticated
+/// status and unauthorized calls with a permission denied status.
+#[derive(Clone, Debug)]
+pub struct AuthGrpcInterceptor {
+    policy: Arc<AuthPolicy>,
+}
+
+impl AuthGrpcInterceptor {
+    /// Create the interceptor from its settings.
+    ///
+    /// # Arguments
+    /// * `settings` - The auth settings.
+    pub fn new(settings: &AuthSettings) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut role_map: HashMap<&str, Vec<Arc<AccessRule>>> = HashMap::new();
+        for role in &settings.roles {
+            let mut access_rules = Vec::new();
+            for rule in &role.rules {
+                access_rules.push(Arc::new(AccessRule {
+                    method_patterns: compile_patterns(&rule.methods)?,
+                    resource_patterns: rule
+                        .resources
+                        .as_deref()
+                        .map(compile_patterns)
+                        .transpose()?,
+                }));
+            }
+            role_map.insert(&role.name, access_rules);
+        }
+
+        let mut access_rule_map = HashMap::new();
+        for identity in &settings.identities {
+            let mut access_rules = Vec::new();
+            for role_name in &identity.roles {
+                let role_access_rules = role_map.get(role_name.as_str()).ok_or_else(|| {
+                    format!("The identity '{}' has an unknown role '{role_name}'", identity.name)
+                })?;
+                access_rules.extend(role_access_rules.iter().cloned());
+            }
+            access_rule_map.insert(identity.name.clone(), access_rules);
+        }
+
+        let mut tokens = settings.tokens.clone().unwrap_or_default();
+        if let Some(token_filename) = &settings.token_filename {
+            let token_file_settings: TokenFileSettings = utils::load_settings(token_filename)
+                .map_err(|error| {
+                    format!("Unable to load the token file '{token_filename}': {error}")
+                })?;
+            tokens.extend(token_file_settings.tokens);
+        }
+
+        let mut identity_map = HashMap::new();
+        for token in tokens {
+            if !access_rule_map.contains_key(&token.identity) {
+                return Err(
+                    format!("A token belongs to an unknown identity '{}'", token.identity).into()
+                );
+            }
+            identity_map.insert(token.token, token.identity);
+        }
+
+        info!(
+            "Loaded {} tokens for {} identities with {} roles.",
+            identity_map.len(),
+            access_rule_map.len(),
+            role_map.len()
+        );
+
+        let exempt_services = settings.exempt_services.clone().unwrap_or_else(|| {
+            DEFAULT_EXEMPT_SERVICES.iter().map(|service_name| service_name.to_string()).collect()
+        });
+
+        Ok(Self { policy: Arc::new(AuthPolicy { identity_map, access_rule_map, exempt_services }) })
+    }
+
+    /// Get the identity that presented a call's bearer token.
+    ///
+    /// # Arguments
+    /// * `context` - The gRPC call's context.
+    fn authenticate(&self, context: &GrpcCallContext) -> Result<&str, Status> {
+        let authorization = context
+            .request_metadata
+            .get(AUTHORIZATION_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("A bearer token is required"))?;
+
+        let token = authorization
+            .get(..BEARER_SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(BEARER_SCHEME))
+            .map(|_| authorization[BEARER_SCHEME.len()..].trim())
+            .ok_or_else(|| Status::unauthenticated("The authorization is not a bearer token"))?;
+
+        self.policy
+            .identity_map
+            .get(token)
+            .map(|identity| identity.as_str())
+            .ok_or_else(|| Status::unauthenticated("The bearer token is not valid"))
+    }
+
+    /// Is a call exempt from auth? The exempt services are matched by their fully qualified names,
+    /// so that a service in another package with the same name is not exempt.
+    ///
+    /// # Arguments
+    /// * `context` - The gRPC call's context.
+    fn is_exempt(&self, context: &GrpcCallContext) -> bool {
+        self.policy
+            .exempt_services
+            .iter()
+            .any(|exempt_service| *exempt_service == context.qualified_service_name)
+    }
+
+    /// Get the access rules of an identity's roles.
+    ///
+    /// # Arguments
+    /// * `identity` - The identity's name.
+    fn access_rules(&self, identity: &str) -> &[Arc<AccessRule>] {
+        self.policy.access_rule_map.get(identity).map(|rules| rules.as_slice()).unwrap_or_default()
+    }
+}
+
+/// Compile glob patterns, where `*` matches any sequence of characters, to regular expressions
+/// that must match the whole value.
+///
+/// # Arguments
+/// * `patterns` - The glob patterns.
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>, Box<dyn Error + Send + Sync>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            let regex_pattern = regex::escape(pattern).replace(r"\*", ".*");
+            Regex::new(&format!("^{regex_pattern}$")).map_err(|error| error.into())
+        })
+        .collect()
+}
+
+/// The resources that a request message refers to.
+#[derive(Debug, PartialEq)]
+enum RequestResources {
+    /// The ids (usually DTMIs) of the resources.
+    Ids(Vec<String>),
+    /// Any of the resources, e.g. when the request lists or watches all of the entities. Only a
+    /// rule without resources allows it.
+    All,
+}
+
+/// Get the resources that a request message refers to. Calls to methods that are not listed here
+/// do not refer to any resources.
+///
+/// # Arguments
+/// * `service_name` - The gRPC call's service name.
+/// * `method_name` - The gRPC call's method name.
+/// * `protobuf_message` - The request's protobuf message as bytes.
+fn get_request_resources(
+    service_name: &str,
+    method_name: &str,
+    protobuf_message: &[u8],
+) -> Result<RequestResources, prost::DecodeError> {
+    let resource_ids = match (service_name, method_name) {
+        ("InvehicleDigitalTwin", "FindById") => {
+            vec![invehicle_digital_twin::FindByIdRequest::decode(protobuf_message)?.id]
+        }
+        ("InvehicleDigitalTwin", "Register") => {
+            invehicle_digital_twin::RegisterRequest::decode(protobuf_message)?
+                .entity_access_info_list
+                .into_iter()
+                .map(|entity_access_info| entity_access_info.id)
+                .collect()
+        }
+        ("InvehicleDigitalTwin", "Unregister") => {
+            invehicle_digital_twin::UnregisterRequest::decode(protobuf_message)?
+                .unregister_entity_info_list
+                .into_iter()
+                .map(|unregister_entity_info| unregister_entity_info.id)
+                .collect()
+        }
+        ("InvehicleDigitalTwin", "RenewLease") => {
+            invehicle_digital_twin::RenewLeaseRequest::decode(protobuf_message)?.ids
+        }
+        // A watch without ids watches all of the entities, or all of those with its id prefix,
+        // which may include entities that are registered later.
+        ("InvehicleDigitalTwin", "WatchEntities") => {
+            let request = invehicle_digital_twin::WatchEntitiesRequest::decode(protobuf_message)?;
+            if request.ids.is_empty() {
+                return Ok(RequestResources::All);
+            }
+            request.ids
+        }
+        // A listing can return any of the entities, whatever its filters are.
+        ("InvehicleDigitalTwin", "ListEntities") => return Ok(RequestResources::All),
+        ("DigitalTwinGraph", "Find") => {
+            vec![digital_twin_graph::FindRequest::decode(protobuf_message)?.model_id]
+        }
+        // The call refers to the instance's member, whose id is "<instance id>/<member path>", where
+        // the member path is the DTMI of the member. Without one, it refers to the whole instance.
+        ("DigitalTwinGraph", "Get") => {
+            let request = digital_twin_graph::GetRequest::decode(protobuf_message)?;
+            vec![graph_resource_id(request.instance_id, request.member_path)]
+        }
+        ("DigitalTwinGraph", "Set") => {
+            let request = digital_twin_graph::SetRequest::decode(protobuf_message)?;
+            vec![graph_resource_id(request.instance_id, request.member_path)]
+        }
+        ("DigitalTwinGraph", "Invoke") => {
+            let request = digital_twin_graph::InvokeRequest::decode(protobuf_message)?;
+            vec![graph_resource_id(request.instance_id, request.member_path)]
+        }
+        ("DigitalTwinRegistry", "FindByModelId") => {
+            vec![digital_twin_registry::FindByModelIdRequest::decode(protobuf_message)?.model_id]
+        }
+        ("DigitalTwinRegistry", "FindByInstanceId") => {
+            vec![
+                digital_twin_registry::FindByInstanceIdRequest::decode(protobuf_message)?
+                    .instance_id,
+            ]
+        }
+        ("DigitalTwinRegistry", "Register") => {
+            digital_twin_registry::RegisterRequest::decode(protobuf_message)?
+                .entity_access_info_list
+                .into_iter()
+                .map(|entity_access_info| entity_access_info.model_id)
+                .collect()
+        }
+        ("ManagedSubscribe", "GetSubscriptionInfo") => {
+            vec![managed_subscribe::SubscriptionInfoRequest::decode(protobuf_message)?.entity_id]
+        }
+        _ => Vec::new(),
+    };
+
+    Ok(RequestResources::Ids(resource_ids))
+}
+
+/// Get the id of the resource that a Digital Twin Graph call refers to. A member's id includes the
+/// instance id, so that a rule cannot allow a member of one instance for every instance.
+///
+/// # Arguments
+/// * `instance_id` - The instance id.
+/// * `member_path` - The member path, which may be empty.
+fn graph_resource_id(instance_id: String, member_path: String) -> String {
+    if member_path.is_empty() {
+        instance_id
+    } else {
+        format!("{instance_id}/{member_path}")
+    }
+}
+
+#[tonic::async_trait]
+impl AsyncGrpcInterceptor for AuthGrpcInterceptor {
+    // The calls are applicable whatever their service is, because the exempt services are
+    // matched by the fully qualified names in the calls' contexts.
+    fn is_applicable(&self, _service_name: &str, _method_name: &str) -> bool {
+        true
+    }
+
+    fn must_handle_request(&self) -> bool {
+        true
+    }
+
+    fn must_handle_response(&self) -> bool {
+        false
+    }
+
+    async fn handle_call(&self, context: &mut GrpcCallContext) -> Result<(), Status> {
+        if self.is_exempt(context) {
+            return Ok(());
+        }
+
+        let identity = self.authenticate(context)?;
+        let method = format!("{}/{}", context.service_name, context.method_name);
+
+        if !self.access_rules(identity).iter().any(|rule| rule.allows_method(&method)) {
+            debug!("'{identity}' is not allowed to call {method}");
+            return Err(Status::permission_denied(format!(
+                "'{identity}' is not allowed to call {method}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn handle_request(
+        &self,
+        _service_name: &str,
+        _method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        Ok(protobuf_message)
+    }
+
+    async fn handle_response(
+        &self,
+        _service_name: &str,
+        _method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        Ok(protobuf_message)
+    }
+
+    async fn handle_request_message(
+        &self,
+        context: &GrpcCallContext,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        if self.is_exempt(context) {
+            return Ok(protobuf_message);
+        }
+
+        let identity = self.authenticate(context)?;
+        let method = format!("{}/{}", context.service_name, context.method_name);
+
+        let request_resources =
+            get_request_resources(&context.service_name, &context.method_name, &protobuf_message)
+                .map_err(|error| Status::invalid_argument(error.to_string()))?;
+
+        let access_rules = self.access_rules(identity);
+        let resource_ids = match request_resources {
+            RequestResources::Ids(resource_ids) => resource_ids,
+            RequestResources::All => {
+                if !access_rules.iter().any(|rule| rule.allows_all_resources(&method)) {
+                    debug!("'{identity}' is not allowed to call {method} for all resources");
+                    return Err(Box::new(Status::permission_denied(format!(
+                        "'{identity}' is not allowed to call {method} for all resources"
+                    ))));
+                }
+                return Ok(protobuf_message);
+            }
+        };
+        if let Some(resource_id) = resource_ids.iter().find(|resource_id| {
+            !access_rules.iter().any(|rule| rule.allows_resource(&method, resource_id))
+        }) {
+            debug!("'{identity}' is not allowed to call {method} for '{resource_id}'");
+            return Err(Box::new(Status::permission_denied(format!(
+                "'{identity}' is not allowed to call {method} for '{resource_id}'"
+            ))));
+        }
+
+        Ok(protobuf_message)
+    }
+}
+
+#[cfg(test)]
+mod auth_grpc_interceptor_tests {
+    use super::*;
+
+    /// Create an interceptor for an HVAC provider and a seat massager consumer.
+    fn create_interceptor() -> AuthGrpcInterceptor {
+        let settings = AuthSettings {
+            token_filename: None,
+            tokens: Some(vec![
+                TokenSettings { token: String::from("hvac_token"), identity: String::from("hvac") },
+                TokenSettings {
+                    token: String::from("consumer_token"),
+                    identity: String::from("consumer"),
+                },
+            ]),
+            identities: vec![
+                IdentitySettings {
+                    name: String::from("hvac"),
+                    roles: vec![String::from("hvac_provider")],
+                },
+                IdentitySettings {
+                    name: String::from("consumer"),
+                    roles: vec![String::from("seat_massager_reader")],
+                },
+            ],
+            roles: vec![
+                RoleSettings {
+                    name: String::from("hvac_provider"),
+                    rules: vec![AccessRuleSettings {
+                        methods: vec![
+                            String::from("InvehicleDigitalTwin/Register"),
+                            String::from("InvehicleDigitalTwin/WatchEntities"),
+                            String::from("InvehicleDigitalTwin/ListEntities"),
+                        ],
+                        resources: Some(vec![String::from("dtmi:sdv:hvac:*")]),
+                    }],
+                },
+                RoleSettings {
+                    name: String::from("seat_massager_reader"),
+                    rules: vec![
+                        AccessRuleSettings {
+                            methods: vec![String::from("DigitalTwinGraph/Get")],
+                            resources: Some(vec![String::from(
+                                "front_left_airbag_seat_massager/dtmi:sdv:airbag_seat_massager:*",
+                            )]),
+                        },
+                        AccessRuleSettings {
+                            methods: vec![
+                                String::from("InvehicleDigitalTwin/FindById"),
+                                String::from("InvehicleDigitalTwin/WatchEntities"),
+                                String::from("InvehicleDigitalTwin/ListEntities"),
+                            ],
+                            resources: None,
+                        },
+                    ],
+                },
+            ],
+            exempt_services: None,
+        };
+
+        AuthGrpcInterceptor::new(&settings).unwrap()
+    }
+
+    /// Create the context of a call with a bearer token.
+    ///
+    /// # Arguments
+    /// * `qualified_service_name` - The gRPC call's fully qualified service name.
+    /// * `method_name` - The gRPC call's method name.
+    /// * `token` - The bearer token, if any.
+    fn create_context(
+        qualified_service_name: &str,
+        method_name: &str,
+        token: Option<&str>,
+    ) -> GrpcCallContext {
+        let mut context = GrpcCallContext {
+            service_name: qualified_service_name.rsplit('.').next().unwrap().to_string(),
+            qualified_service_name: qualified_service_name.to_string(),
+            method_name: method_name.to_string(),
+            ..Default::default()
+        };
+        if let Some(token) = token {
+            context
+                .request_metadata
+                .insert(AUTHORIZATION_METADATA_KEY, format!("Bearer {token}").parse().unwrap());
+        }
+        context
+    }
+
+    #[tokio::test]
+    async fn handle_call_test() {
+        let interceptor = create_interceptor();
+
+        let mut context =
+            create_context("invehicle_digital_twin.InvehicleDigitalTwin", "Register", None);
+        let status = interceptor.handle_call(&mut context).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut context = create_context(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "Register",
+            Some("unknown"),
+        );
+        let status = interceptor.handle_call(&mut context).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut context = create_context(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "Register",
+            Some("hvac_token"),
+        );
+        assert!(interceptor.handle_call(&mut context).await.is_ok());
+
+        let mut context = create_context(
+            "digital_twin_graph.v1.digital_twin_graph.DigitalTwinGraph",
+            "Invoke",
+            Some("consumer_token"),
+        );
+        let status = interceptor.handle_call(&mut context).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn exempt_services_test() {
+        let interceptor = create_interceptor();
+
+        let mut context = create_context("grpc.health.v1.Health", "Check", None);
+        assert!(interceptor.handle_call(&mut context).await.is_ok());
+        assert!(interceptor.handle_request_message(&context, Bytes::new()).await.is_ok());
+
+        // Only the exempt service's fully qualified name is exempt, not another service that has
+        // the same name.
+        let mut context = create_context("sample.Health", "Check", None);
+        let status = interceptor.handle_call(&mut context).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut context =
+            create_context("grpc.reflection.v1.ServerReflection", "ServerReflectionInfo", None);
+        let status = interceptor.handle_call(&mut context).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn handle_request_message_test() {
+        let interceptor = create_interceptor();
+
+        let context = create_context(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "Register",
+            Some("hvac_token"),
+        );
+        let register_request = |id: &str| {
+            Bytes::from(
+                invehicle_digital_twin::RegisterRequest {
+                    entity_access_info_list: vec![invehicle_digital_twin::EntityAccessInfo {
+                        id: id.to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            )
+        };
+        let result = interceptor
+            .handle_request_message(
+                &context,
+                register_request("dtmi:sdv:hvac:ambient_air_temperature;1"),
+            )
+            .await;
+        assert!(result.is_ok());
+        let error = interceptor
+            .handle_request_message(
+                &context,
+                register_request("dtmi:sdv:obd:hybrid_battery_remaining;1"),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(Status::from_error(error).code(), tonic::Code::PermissionDenied);
+
+        let context = create_context(
+            "digital_twin_graph.v1.digital_twin_graph.DigitalTwinGraph",
+            "Get",
+            Some("consumer_token"),
+        );
+        let get_request = |instance_id: &str| {
+            Bytes::from(
+                digital_twin_graph::GetRequest {
+                    instance_id: instance_id.to_string(),
+                    member_path: String::from("dtmi:sdv:airbag_seat_massager:massage_airbags;1"),
+                }
+                .encode_to_vec(),
+            )
+        };
+        let result = interceptor
+            .handle_request_message(&context, get_request("front_left_airbag_seat_massager"))
+            .await;
+        assert!(result.is_ok());
+        // The same member of another instance is not allowed.
+        let error = interceptor
+            .handle_request_message(&context, get_request("front_right_airbag_seat_massager"))
+            .await
+            .unwrap_err();
+        assert_eq!(Status::from_error(error).code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn handle_list_entities_request_message_test() {
+        let interceptor = create_interceptor();
+        let list_entities_request = |id_prefix: &str| {
+            Bytes::from(
+                invehicle_digital_twin::ListEntitiesRequest {
+                    id_prefix: id_prefix.to_string(),
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            )
+        };
+
+        // A listing can return any entity, so a rule with resources does not allow it.
+        let context = create_context(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "ListEntities",
+            Some("hvac_token"),
+        );
+        let error = interceptor
+            .handle_request_message(&context, list_entities_request("dtmi:sdv:hvac:"))
+            .await
+            .unwrap_err();
+        assert_eq!(Status::from_error(error).code(), tonic::Code::PermissionDenied);
+
+        let context = create_context(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "ListEntities",
+            Some("consumer_token"),
+        );
+        let result = interceptor.handle_request_message(&context, list_entities_request("")).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn handle_watch_entities_request_message_test() {
+        let interceptor = create_interceptor();
+        let watch_entities_request = |ids: &[&str], id_prefix: &str| {
+            Bytes::from(
+                invehicle_digital_twin::WatchEntitiesRequest {
+                    ids: ids.iter().map(|id| id.to_string()).collect(),
+                    id_prefix: id_prefix.to_string(),
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            )
+        };
+
+        let context = create_context(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "WatchEntities",
+            Some("hvac_token"),
+        );
+        let result = interceptor
+            .handle_request_message(
+                &context,
+                watch_entities_request(&["dtmi:sdv:hvac:ambient_air_temperature;1"], ""),
+            )
+            .await;
+        assert!(result.is_ok());
+        let error = interceptor
+            .handle_request_message(
+                &context,
+                watch_entities_request(&["dtmi:sdv:obd:hybrid_battery_remaining;1"], ""),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(Status::from_error(error).code(), tonic::Code::PermissionDenied);
+
+        // A watch without ids watches all of the entities, even with an id prefix.
+        for (ids, id_prefix) in [(&[][..], ""), (&[][..], "dtmi:sdv:hvac:")] {
+            let error = interceptor
+                .handle_request_message(&context, watch_entities_request(ids, id_prefix))
+                .await
+                .unwrap_err();
+            assert_eq!(Status::from_error(error).code(), tonic::Code::PermissionDenied);
+        }
+
+        let context = create_context(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "WatchEntities",
+            Some("consumer_token"),
+        );
+        let result =
+            interceptor.handle_request_message(&context, watch_entities_request(&[], "")).await;
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use log::{debug, info};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use tonic::Status;
+
+// The digital twin operations.
+const GET_OPERATION: &str = "Get";
+const SET_OPERATION: &str = "Set";
+const SUBSCRIBE_OPERATION: &str = "Subscribe";
+const UNSUBSCRIBE_OPERATION: &str = "Unsubscribe";
+const INVOKE_OPERATION: &str = "Invoke";
+const STREAM_OPERATION: &str = "Stream";
+const MANAGED_SUBSCRIBE_OPERATION: &str = "ManagedSubscribe";
+
+const DTDL_FILE_EXTENSION: &str = "json";
+
+/// The kinds of DTDL elements that can be registered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DtdlElementKind {
+    Interface,
+    Property,
+    Telemetry,
+    Command,
+    Relationship,
+    Component,
+}
+
+impl DtdlElementKind {
+    /// Get the element kind that corresponds to a DTDL type, if it is one that can be registered.
+    ///
+    /// # Arguments
+    /// * `dtdl_type` - The DTDL type, e.g. "Property".
+    fn from_dtdl_type(dtdl_type: &str) -> Option<Self> {
+        match dtdl_type {
+            "Interface" => Some(DtdlElementKind::Interface),
+            "Property" => Some(DtdlElementKind::Property),
+            "Telemetry" => Some(DtdlElementKind::Telemetry),
+            "Command" => Some(DtdlElementKind::Command),
+            "Relationship" => Some(DtdlElementKind::Relationship),
+            "Component" => Some(DtdlElementKind::Component),
+            _ => None,
+        }
+    }
+
+    /// The operations that fit this kind of element.
+    pub fn supported_operations(&self) -> &'static [&'static str] {
+        match self {
+            DtdlElementKind::Interface => &[
+                GET_OPERATION,
+                SET_OPERATION,
+                SUBSCRIBE_OPERATION,
+                UNSUBSCRIBE_OPERATION,
+                INVOKE_OPERATION,
+                STREAM_OPERATION,
+                MANAGED_SUBSCRIBE_OPERATION,
+            ],
+            DtdlElementKind::Property => &[
+                GET_OPERATION,
+                SET_OPERATION,
+                SUBSCRIBE_OPERATION,
+                UNSUBSCRIBE_OPERATION,
+                STREAM_OPERATION,
+                MANAGED_SUBSCRIBE_OPERATION,
+            ],
+            DtdlElementKind::Telemetry => &[
+                SUBSCRIBE_OPERATION,
+                UNSUBSCRIBE_OPERATION,
+                STREAM_OPERATION,
+                MANAGED_SUBSCRIBE_OPERATION,
+            ],
+            DtdlElementKind::Command => &[INVOKE_OPERATION],
+            DtdlElementKind::Relationship | DtdlElementKind::Component => &[GET_OPERATION],
+        }
+    }
+
+    /// Is this a kind of element that a provider implements directly, i.e. an interface's content
+    /// that carries data or behavior?
+    pub fn is_providable_content(&self) -> bool {
+        matches!(
+            self,
+            DtdlElementKind::Property | DtdlElementKind::Telemetry | DtdlElementKind::Command
+        )
+    }
+}
+
+/// A catalog of the DTDL elements that are defined by a directory of DTDL files.
+/// It is used to validate that registrations refer to elements that exist, with operations that
+/// fit them.
+#[derive(Clone, Debug, Default)]
+pub struct DtdlModelCatalog {
+    /// The elements' kinds, keyed by their ids.
+    element_kind_map: HashMap<String, DtdlElementKind>,
+}
+
+impl DtdlModelCatalog {
+    /// Load the DTDL files (with a .json extension) in a directory and its subdirectories.
+    ///
+    /// # Arguments
+    /// * `directory` - The directory.
+    pub fn load(directory: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut catalog = DtdlModelCatalog::default();
+        catalog.load_directory(directory)?;
+
+        info!("Loaded {} DTDL elements from {directory:?}", catalog.element_kind_map.len());
+
+        Ok(catalog)
+    }
+
+    /// Load the DTDL files in a directory and its subdirectories into the catalog.
+    ///
+    /// # Arguments
+    /// * `directory` - The directory.
+    fn load_directory(&mut self, directory: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for dir_entry in fs::read_dir(directory)? {
+            let path = dir_entry?.path();
+            if path.is_dir() {
+                self.load_directory(&path)?;
+            } else if path.extension().is_some_and(|extension| extension == DTDL_FILE_EXTENSION) {
+                debug!("Loading the DTDL file {path:?}");
+                let content: Value = serde_json::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|error| format!("Unable to parse the DTDL file {path:?}: {error}"))?;
+                self.add_elements(&content);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the elements in a DTDL document, and all of the elements nested in them, to the catalog.
+    /// Only elements with both an id and a kind that can be registered are added.
+    ///
+    /// # Arguments
+    /// * `value` - The DTDL document, or a part of it.
+    fn add_elements(&mut self, value: &Value) {
+        match value {
+            Value::Array(values) => values.iter().for_each(|value| self.add_elements(value)),
+            Value::Object(object) => {
+                let id = object.get("@id").and_then(Value::as_str);
+                // The type can be an array when semantic types are used, e.g. ["Property", "Temperature"].
+                let kind = match object.get("@type") {
+                    Some(Value::String(dtdl_type)) => DtdlElementKind::from_dtdl_type(dtdl_type),
+                    Some(Value::Array(dtdl_types)) => dtdl_types
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .find_map(DtdlElementKind::from_dtdl_type),
+                    _ => None,
+                };
+
+                if let (Some(id), Some(kind)) = (id, kind) {
+                    self.element_kind_map.insert(id.to_string(), kind);
+                }
+
+                object.values().for_each(|value| self.add_elements(value));
+            }
+            _ => {}
+        }
+    }
+
+    /// Get the kind of the element with the provided id.
+    ///
+    /// # Arguments
+    /// * `id` - The element's id.
+    pub fn get_element_kind(&self, id: &str) -> Option<DtdlElementKind> {
+        self.element_kind_map.get(id).copied()
+    }
+
+    /// Validate that the element with the provided id exists and that the operations fit it.
+    /// Returns an invalid argument status that describes the problem when they do not.
+    ///
+    /// # Arguments
+    /// * `id` - The element's id.
+    /// * `operations` - The operations that the element is registered with.
+    pub fn validate(&self, id: &str, operations: &[String]) -> Result<(), Status> {
+        let kind = self.get_element_kind(id).ok_or_else(|| {
+            Status::invalid_argument(format!(
+                "'{id}' is not defined by any of the loaded DTDL models"
+            ))
+        })?;
+
+        let supported_operations = kind.supported_operations();
+        if let Some(operation) =
+            operations.iter().find(|operation| !supported_operations.contains(&operation.as_str()))
+        {
+            return Err(Status::invalid_argument(format!(
+                "The operation '{operation}' does not fit '{id}', which is a {kind:?}. The supported operations are {supported_operations:?}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Get the ids of the properties, telemetry and commands that are not provided, sorted by id.
+    ///
+    /// # Arguments
+    /// * `is_provided` - Is the element with the provided id provided?
+    pub fn get_unprovided_content_ids(&self, is_provided: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut unprovided_content_ids: Vec<String> = self
+            .element_kind_map
+            .iter()
+            .filter(|(id, kind)| kind.is_providable_content() && !is_provided(id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        unprovided_content_ids.sort();
+        unprovided_content_ids
+    }
+}
+
+#[cfg(test)]
+mod dtdl_model_catalog_tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Load the repository's vehicle model.
+    fn load_vehicle_model() -> DtdlModelCatalog {
+        let directory =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../digital-twin-model/dtdl");
+        DtdlModelCatalog::load(&directory).unwrap()
+    }
+
+    #[test]
+    fn load_test() {
+        let catalog = load_vehicle_model();
+
+        assert_eq!(catalog.get_element_kind("dtmi:sdv:hvac;1"), Some(DtdlElementKind::Interface));
+        assert_eq!(
+            catalog.get_element_kind("dtmi:sdv:hvac:ambient_air_temperature;1"),
+            Some(DtdlElementKind::Property)
+        );
+        assert_eq!(
+            catalog.get_element_kind("dtmi:sdv:hmi:show_notification;1"),
+            Some(DtdlElementKind::Command)
+        );
+        assert_eq!(
+            catalog.get_element_kind("dtmi:sdv:vehicle:cabin;1"),
+            Some(DtdlElementKind::Relationship)
+        );
+        // Schemas cannot be registered.
+        assert_eq!(catalog.get_element_kind("dtmi:sdv:show_notification:status;1"), None);
+    }
+
+    #[test]
+    fn validate_test() {
+        let catalog = load_vehicle_model();
+
+        let result = catalog.validate(
+            "dtmi:sdv:hvac:ambient_air_temperature;1",
+            &[String::from("Subscribe"), String::from("Unsubscribe")],
+        );
+        assert!(result.is_ok(), "validate result is not okay: {result:?}");
+
+        let result =
+            catalog.validate("dtmi:sdv:hmi:show_notification;1", &[String::from("Invoke")]);
+        assert!(result.is_ok(), "validate result is not okay: {result:?}");
+
+        let status = catalog
+            .validate("dtmi:sdv:hvac:ambient_air_temperature;1", &[String::from("Invoke")])
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().starts_with(
+            "The operation 'Invoke' does not fit 'dtmi:sdv:hvac:ambient_air_temperature;1', which is a Property."
+        ));
+
+        let status = catalog.validate("dtmi:sdv:hvac:unknown;1", &[]).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "'dtmi:sdv:hvac:unknown;1' is not defined by any of the loaded DTDL models"
+        );
+    }
+
+    #[test]
+    fn get_unprovided_content_ids_test() {
+        let catalog = load_vehicle_model();
+
+        let unprovided_content_ids = catalog
+            .get_unprovided_content_ids(|id| id != "dtmi:sdv:hvac:ambient_air_temperature;1");
+        assert_eq!(unprovided_content_ids, ["dtmi:sdv:hvac:ambient_air_temperature;1"]);
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Parsing and validation of Digital Twin Model Identifiers (DTMIs), following the DTDL v3 rules.
+//!
+//! A DTMI has the form `dtmi:<path>;<version>`, for example `dtmi:sdv:HVAC:AmbientAirTemperature;1`.
+//! - The path is one or more segments that are separated by colons. Each segment starts with a
+//!   letter, contains only letters, digits and underscores, and does not end with an underscore.
+//! - The version is a major version from 1 to 999999999, optionally followed by a period and a
+//!   minor version from 1 to 999999. Neither may have leading zeros. Ibeji requires the version.
+//! - The whole DTMI is at most 2048 characters long.
+
+use std::fmt;
+use std::str::FromStr;
+
+const DTMI_SCHEME: &str = "dtmi:";
+const MAX_DTMI_LENGTH: usize = 2048;
+const MAX_MAJOR_VERSION_DIGITS: usize = 9;
+const MAX_MINOR_VERSION_DIGITS: usize = 6;
+
+/// The reasons that a string is not a valid DTMI.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DtmiError {
+    /// The DTMI is longer than the maximum length.
+    TooLong { length: usize },
+    /// The DTMI does not start with the `dtmi:` scheme.
+    MissingScheme,
+    /// The DTMI does not have a version suffix.
+    MissingVersion,
+    /// One of the path's segments is empty.
+    EmptySegment { position: usize },
+    /// One of the path's segments breaks the segment rules.
+    InvalidSegment { segment: String },
+    /// The version suffix breaks the version rules.
+    InvalidVersion { version: String },
+}
+
+impl fmt::Display for DtmiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DtmiError::TooLong { length } => write!(
+                f,
+                "it is {length} characters long, but a DTMI can be at most {MAX_DTMI_LENGTH} characters long"
+            ),
+            DtmiError::MissingScheme => write!(f, "it does not start with '{DTMI_SCHEME}'"),
+            DtmiError::MissingVersion => {
+                write!(f, "it does not end with a version suffix, such as ';1'")
+            }
+            DtmiError::EmptySegment { position } => {
+                write!(f, "path segment {position} is empty")
+            }
+            DtmiError::InvalidSegment { segment } => write!(
+                f,
+                "path segment '{segment}' must start with a letter, contain only letters, digits and underscores, and not end with an underscore"
+            ),
+            DtmiError::InvalidVersion { version } => write!(
+                f,
+                "version '{version}' must be a major version from 1 to 999999999, optionally followed by '.' and a minor version from 1 to 999999, without leading zeros"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DtmiError {}
+
+/// A parsed Digital Twin Model Identifier.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Dtmi {
+    /// The path's segments.
+    segments: Vec<String>,
+    /// The major version.
+    major_version: u32,
+    /// The minor version, when there is one.
+    minor_version: Option<u32>,
+}
+
+impl Dtmi {
+    /// Parse and validate a DTMI.
+    ///
+    /// # Arguments
+    /// * `dtmi` - The DTMI.
+    pub fn parse(dtmi: &str) -> Result<Self, DtmiError> {
+        if dtmi.len() > MAX_DTMI_LENGTH {
+            return Err(DtmiError::TooLong { length: dtmi.len() });
+        }
+
+        let body = dtmi.strip_prefix(DTMI_SCHEME).ok_or(DtmiError::MissingScheme)?;
+        let (path, version) = body.split_once(';').ok_or(DtmiError::MissingVersion)?;
+
+        let segments = path
+            .split(':')
+            .enumerate()
+            .map(|(index, segment)| Self::parse_segment(index + 1, segment))
+            .collect::<Result<Vec<String>, DtmiError>>()?;
+
+        let invalid_version = || DtmiError::InvalidVersion { version: version.to_string() };
+        let (major_version, minor_version) = match version.split_once('.') {
+            Some((major_version, minor_version)) => (
+                Self::parse_version_number(major_version, MAX_MAJOR_VERSION_DIGITS)
+                    .ok_or_else(invalid_version)?,
+                Some(
+                    Self::parse_version_number(minor_version, MAX_MINOR_VERSION_DIGITS)
+                        .ok_or_else(invalid_version)?,
+                ),
+            ),
+            None => (
+                Self::parse_version_number(version, MAX_MAJOR_VERSION_DIGITS)
+                    .ok_or_else(invalid_version)?,
+                None,
+            ),
+        };
+
+        Ok(Dtmi { segments, major_version, minor_version })
+    }
+
+    /// The path's segments.
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// The major version.
+    pub fn major_version(&self) -> u32 {
+        self.major_version
+    }
+
+    /// The minor version, when there is one.
+    pub fn minor_version(&self) -> Option<u32> {
+        self.minor_version
+    }
+
+    /// The version, formatted as it appears in the DTMI, e.g. `1` or `1.2`.
+    pub fn version(&self) -> String {
+        match self.minor_version {
+            Some(minor_version) => format!("{}.{minor_version}", self.major_version),
+            None => self.major_version.to_string(),
+        }
+    }
+
+    /// Validate a path segment.
+    ///
+    /// # Arguments
+    /// * `position` - The segment's one-based position in the path.
+    /// * `segment` - The segment.
+    fn parse_segment(position: usize, segment: &str) -> Result<String, DtmiError> {
+        let first_char = segment.chars().next().ok_or(DtmiError::EmptySegment { position })?;
+
+        if !first_char.is_ascii_alphabetic()
+            || segment.ends_with('_')
+            || !segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(DtmiError::InvalidSegment { segment: segment.to_string() });
+        }
+
+        Ok(segment.to_string())
+    }
+
+    /// Parse a version number. Returns None when it is not a number from 1 to the largest number
+    /// with the provided number of digits, or when it has leading zeros.
+    ///
+    /// # Arguments
+    /// * `version_number` - The version number.
+    /// * `max_digits` - The maximum number of digits.
+    fn parse_version_number(version_number: &str, max_digits: usize) -> Option<u32> {
+        if version_number.is_empty()
+            || version_number.len() > max_digits
+            || version_number.starts_with('0')
+            || !version_number.chars().all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        version_number.parse().ok()
+    }
+}
+
+impl FromStr for Dtmi {
+    type Err = DtmiError;
+
+    fn from_str(dtmi: &str) -> Result<Self, Self::Err> {
+        Dtmi::parse(dtmi)
+    }
+}
+
+impl fmt::Display for Dtmi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{DTMI_SCHEME}{};{}", self.segments.join(":"), self.version())
+    }
+}
+
+#[cfg(test)]
+mod dtmi_tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid_dtmi_test() {
+        let dtmi = Dtmi::parse("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1").unwrap();
+        assert_eq!(dtmi.segments(), ["sdv", "Vehicle", "Cabin", "HVAC", "AmbientAirTemperature"]);
+        assert_eq!(dtmi.major_version(), 1);
+        assert_eq!(dtmi.minor_version(), None);
+        assert_eq!(dtmi.to_string(), "dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1");
+
+        let dtmi: Dtmi = "dtmi:sdv:hvac:ambient_air_temperature;12.3".parse().unwrap();
+        assert_eq!(dtmi.segments(), ["sdv", "hvac", "ambient_air_temperature"]);
+        assert_eq!(dtmi.major_version(), 12);
+        assert_eq!(dtmi.minor_version(), Some(3));
+        assert_eq!(dtmi.version(), "12.3");
+    }
+
+    #[test]
+    fn parse_invalid_dtmi_test() {
+        assert_eq!(Dtmi::parse("sdv:HVAC:AmbientAirTemperature;1"), Err(DtmiError::MissingScheme));
+        assert_eq!(
+            Dtmi::parse("dtmi:sdv:HVAC:AmbientAirTemperature"),
+            Err(DtmiError::MissingVersion)
+        );
+        assert_eq!(
+            Dtmi::parse("dtmi:sdv::AmbientAirTemperature;1"),
+            Err(DtmiError::EmptySegment { position: 2 })
+        );
+        assert_eq!(Dtmi::parse("dtmi:;1"), Err(DtmiError::EmptySegment { position: 1 }));
+        for segment in ["1HVAC", "_HVAC", "HVAC_", "HV-AC"] {
+            assert_eq!(
+                Dtmi::parse(&format!("dtmi:sdv:{segment};1")),
+                Err(DtmiError::InvalidSegment { segment: segment.to_string() })
+            );
+        }
+        for version in ["0", "01", "1.0", "1.", "1.2.3", "1234567890", "1.1234567", "a"] {
+            assert_eq!(
+                Dtmi::parse(&format!("dtmi:sdv:HVAC;{version}")),
+                Err(DtmiError::InvalidVersion { version: version.to_string() })
+            );
+        }
+        assert_eq!(
+            Dtmi::parse(&format!("dtmi:{};1", "a".repeat(MAX_DTMI_LENGTH))),
+            Err(DtmiError::TooLong { length: MAX_DTMI_LENGTH + 7 })
+        );
+    }
+}
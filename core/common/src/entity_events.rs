@@ -0,0 +1,39 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use core_protobuf_data_access::invehicle_digital_twin::v1::EntityAccessInfo;
+use tokio::sync::broadcast;
+
+// This module provides the events that the In-Vehicle Digital Twin Service publishes when the
+// registered entities change, so that modules can react to them.
+
+/// The capacity of the broadcast channel for entity events.
+pub const ENTITY_EVENT_CHANNEL_CAPACITY: usize = 100;
+
+/// The kind of change that was made to a registered entity.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EntityEventKind {
+    /// The entity was registered for the first time.
+    Added,
+    /// The registered entity's access info changed.
+    Updated,
+    /// The entity was unregistered or its lease expired.
+    Removed,
+}
+
+/// An event describing a change to a registered entity.
+#[derive(Clone, Debug)]
+pub struct EntityEvent {
+    /// The kind of change.
+    pub kind: EntityEventKind,
+    /// The entity's access info after the change. For a removal, it is the last registered value.
+    pub entity_access_info: EntityAccessInfo,
+}
+
+/// Creates the sender for the entity events broadcast channel.
+/// Receivers are created with `subscribe`.
+pub fn create_entity_event_sender() -> broadcast::Sender<EntityEvent> {
+    let (sender, _receiver) = broadcast::channel(ENTITY_EVENT_CHANNEL_CAPACITY);
+    sender
+}
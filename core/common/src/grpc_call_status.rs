@@ -0,0 +1,102 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use bytes::Bytes;
+use futures_core::ready;
+use futures_core::task::{Context, Poll};
+use http::HeaderMap;
+use http_body::Body;
+use std::pin::Pin;
+use tonic::body::BoxBody;
+use tonic::{Code, Status};
+
+// This module lets the tower layers observe how the gRPC calls that they wrap complete.
+
+/// The header that carries a gRPC call's status code.
+const GRPC_STATUS_HEADER: &str = "grpc-status";
+
+/// Observes a gRPC call's status code. The call has completed when the observer is dropped. A call
+/// whose status code was not observed, e.g. because it was cancelled, did not complete normally.
+pub(crate) trait GrpcCallStatusObserver: Send + 'static {
+    /// Observe the gRPC call's status code.
+    ///
+    /// # Arguments
+    /// * `code` - The status code.
+    fn observe_code(&mut self, code: Code);
+}
+
+/// Get a gRPC call's status code from the response's headers or trailers, if they carry it.
+///
+/// # Arguments
+/// * `headers` - The response's headers or trailers.
+pub(crate) fn get_grpc_code(headers: &HeaderMap) -> Option<Code> {
+    headers.get(GRPC_STATUS_HEADER).map(|value| Code::from_bytes(value.as_bytes()))
+}
+
+/// A response body that passes its gRPC call's status code, from its trailers, to an observer,
+/// which it drops once the body has been dropped.
+struct GrpcCallStatusBody<B, O> {
+    inner: B,
+    observer: O,
+}
+
+impl<B, O> Body for GrpcCallStatusBody<B, O>
+where
+    B: Body<Data = Bytes> + Unpin,
+    O: GrpcCallStatusObserver + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.get_mut().inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        let result = ready!(Pin::new(&mut this.inner).poll_trailers(cx));
+        if let Some(code) = result.as_ref().ok().and_then(Option::as_ref).and_then(get_grpc_code) {
+            this.observer.observe_code(code);
+        }
+
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+/// Observe the status code of a gRPC call's response. A trailers-only response carries it in its
+/// headers, the others carry it in their body's trailers.
+///
+/// # Arguments
+/// * `response` - The response.
+/// * `observer` - The observer.
+pub(crate) fn observe_response<B, O>(
+    response: http::Response<B>,
+    mut observer: O,
+) -> http::Response<BoxBody>
+where
+    B: Body<Data = Bytes> + Unpin + Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    O: GrpcCallStatusObserver + Unpin,
+{
+    let (parts, body) = response.into_parts();
+    if let Some(code) = get_grpc_code(&parts.headers) {
+        observer.observe_code(code);
+    }
+
+    let body = GrpcCallStatusBody { inner: body, observer }
+        .map_err(|error| Status::from_error(error.into()))
+        .boxed_unsync();
+
+    http::Response::from_parts(parts, body)
+}
@@ -0,0 +1,313 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use log::info;
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::sync::OnceLock;
+use tokio::net::UnixStream;
+use tonic::transport::{
+    Certificate, Channel, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig, Uri,
+};
+use tower::service_fn;
+
+use crate::grpc_tracing::TracedChannel;
+
+// This module creates the channels that all of the gRPC clients use, so that they share the
+// process's TLS settings.
+
+/// The scheme of the URIs that are connected to with TLS.
+const HTTPS_SCHEME: &str = "https";
+
+/// The prefix of the URIs of Unix domain sockets, e.g. "unix:///run/ibeji/provider.sock".
+pub const UNIX_SCHEME_PREFIX: &str = "unix://";
+
+/// The URI of the endpoints that connect to Unix domain sockets. It is not used to connect, but
+/// it is the authority of the requests.
+const UNIX_SOCKET_ENDPOINT_URI: &str = "http://localhost"; // Devskim: ignore DS137138
+
+/// The URI of the endpoints that connect to Unix domain sockets with TLS. The servers'
+/// certificates are verified for its host, unless the domain_name setting is provided.
+const UNIX_SOCKET_TLS_ENDPOINT_URI: &str = "https://localhost";
+
+/// The TLS settings that clients use. They are set once, when the process starts.
+static CLIENT_TLS_CONFIG: OnceLock<ClientTlsConfig> = OnceLock::new();
+
+/// How a server verifies the certificates of its clients.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum ClientCertificateVerification {
+    /// Clients are not asked for a certificate.
+    #[default]
+    None,
+    /// Clients may present a certificate. When they do, it must be signed by the CA.
+    Optional,
+    /// Clients must present a certificate that is signed by the CA (mutual TLS).
+    Required,
+}
+
+/// The TLS settings of a service.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TlsSettings {
+    /// The path of the service's PEM certificate (chain). The server presents it and, for mutual
+    /// TLS, the service's clients present it too.
+    pub cert_path: String,
+    /// The path of the PEM private key of the service's certificate.
+    pub key_path: String,
+    /// The path of the PEM certificate of the CA that signs the servers' certificates, which the
+    /// service's clients verify, and the clients' certificates, which the server verifies. If it is
+    /// not provided, then the clients use the system's trusted CAs.
+    pub ca_cert_path: Option<String>,
+    /// How the server verifies the certificates of its clients. If it is not provided, then
+    /// clients are not asked for a certificate.
+    pub client_certificate_verification: Option<ClientCertificateVerification>,
+    /// The name that the clients expect in the servers' certificates. If it is not provided, then
+    /// the host of the URI that is connected to is used, or "localhost" for a Unix domain socket.
+    pub domain_name: Option<String>,
+}
+
+impl TlsSettings {
+    /// Load the service's certificate and private key.
+    fn load_identity(&self) -> Result<Identity, Box<dyn Error + Send + Sync>> {
+        let cert = fs::read(&self.cert_path)
+            .map_err(|error| format!("Unable to read '{}': {error}", self.cert_path))?;
+        let key = fs::read(&self.key_path)
+            .map_err(|error| format!("Unable to read '{}': {error}", self.key_path))?;
+
+        Ok(Identity::from_pem(cert, key))
+    }
+
+    /// Load the CA's certificate, if it is provided.
+    fn load_ca_cert(&self) -> Result<Option<Certificate>, Box<dyn Error + Send + Sync>> {
+        self.ca_cert_path
+            .as_ref()
+            .map(|ca_cert_path| {
+                fs::read(ca_cert_path)
+                    .map(Certificate::from_pem)
+                    .map_err(|error| format!("Unable to read '{ca_cert_path}': {error}").into())
+            })
+            .transpose()
+    }
+}
+
+/// Create a server's TLS config.
+///
+/// # Arguments
+/// * `settings` - The service's TLS settings.
+pub fn create_server_tls_config(
+    settings: &TlsSettings,
+) -> Result<ServerTlsConfig, Box<dyn Error + Send + Sync>> {
+    let mut server_tls_config = ServerTlsConfig::new().identity(settings.load_identity()?);
+
+    let client_certificate_verification =
+        settings.client_certificate_verification.unwrap_or_default();
+    if client_certificate_verification != ClientCertificateVerification::None {
+        let ca_cert = settings.load_ca_cert()?.ok_or(
+            "The ca_cert_path setting is required to verify the certificates of the clients",
+        )?;
+        server_tls_config = server_tls_config.client_ca_root(ca_cert).client_auth_optional(
+            client_certificate_verification == ClientCertificateVerification::Optional,
+        );
+    }
+
+    Ok(server_tls_config)
+}
+
+/// Set the TLS config that all of the process's clients use to connect to https URIs and Unix
+/// domain sockets. The
+/// clients present the service's certificate, so that servers that require mutual TLS accept them.
+/// It can only be set once.
+///
+/// # Arguments
+/// * `settings` - The service's TLS settings.
+pub fn init_client_tls_config(settings: &TlsSettings) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut client_tls_config = ClientTlsConfig::new().identity(settings.load_identity()?);
+    if let Some(ca_cert) = settings.load_ca_cert()? {
+        client_tls_config = client_tls_config.ca_certificate(ca_cert);
+    }
+    if let Some(domain_name) = &settings.domain_name {
+        client_tls_config = client_tls_config.domain_name(domain_name);
+    }
+
+    CLIENT_TLS_CONFIG
+        .set(client_tls_config)
+        .map_err(|_| "The client TLS config has already been set")?;
+
+    info!(
+        "The gRPC clients connect to https URIs and Unix domain sockets with the configured TLS settings."
+    );
+
+    Ok(())
+}
+
+/// Create the endpoint for a URI. An https URI's endpoint uses the process's client TLS config.
+///
+/// # Arguments
+/// * `uri` - The URI.
+pub fn create_endpoint(uri: &str) -> Result<Endpoint, tonic::transport::Error> {
+    let endpoint = Endpoint::from_shared(uri.to_string())?;
+
+    if endpoint.uri().scheme_str() == Some(HTTPS_SCHEME) {
+        let client_tls_config = CLIENT_TLS_CONFIG.get().cloned().unwrap_or_default();
+        return endpoint.tls_config(client_tls_config);
+    }
+
+    Ok(endpoint)
+}
+
+/// Get the path of a Unix domain socket's URI, e.g. "/run/ibeji/provider.sock" for
+/// "unix:///run/ibeji/provider.sock". Returns None when the URI is not a Unix domain socket's URI or
+/// it does not have a path.
+///
+/// # Arguments
+/// * `uri` - The URI.
+pub fn get_unix_socket_path(uri: &str) -> Option<&str> {
+    uri.strip_prefix(UNIX_SCHEME_PREFIX).filter(|path| !path.is_empty())
+}
+
+/// Connect to a URI. It is used in place of a generated client's `connect` function, e.g.
+/// `PubSubClient::new(connect(uri).await?)`. A Unix domain socket's URI, e.g.
+/// "unix:///run/ibeji/provider.sock", is connected to over the socket, with TLS when the process's
+/// client TLS config is set. The calls that are made on the channel are traced.
+///
+/// # Arguments
+/// * `uri` - The URI.
+pub async fn connect(uri: &str) -> Result<TracedChannel, tonic::transport::Error> {
+    let channel = match get_unix_socket_path(uri) {
+        Some(unix_socket_path) => {
+            connect_unix_socket(unix_socket_path, CLIENT_TLS_CONFIG.get().cloned()).await?
+        }
+        None => create_endpoint(uri)?.connect().await?,
+    };
+
+    Ok(TracedChannel::new(channel))
+}
+
+/// Connect to a Unix domain socket. A server that is configured for TLS serves its Unix domain
+/// sockets with TLS too, so the connection uses TLS when a TLS config is provided.
+///
+/// # Arguments
+/// * `path` - The socket's path.
+/// * `tls_config` - The client TLS config. If it is not provided, then TLS is not used.
+async fn connect_unix_socket(
+    path: &str,
+    tls_config: Option<ClientTlsConfig>,
+) -> Result<Channel, tonic::transport::Error> {
+    let endpoint = match tls_config {
+        Some(tls_config) => {
+            Endpoint::from_static(UNIX_SOCKET_TLS_ENDPOINT_URI).tls_config(tls_config)?
+        }
+        None => Endpoint::from_static(UNIX_SOCKET_ENDPOINT_URI),
+    };
+
+    let path = path.to_string();
+    endpoint
+        .connect_with_connector(service_fn(move |_: Uri| UnixStream::connect(path.clone())))
+        .await
+}
+
+#[cfg(test)]
+mod grpc_channel_tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tokio::net::UnixListener;
+    use tokio_stream::wrappers::UnixListenerStream;
+    use tonic::transport::Server;
+    use tonic_health::pb::health_client::HealthClient;
+    use tonic_health::pb::HealthCheckRequest;
+
+    /// Get the TLS settings of the test certificate, which is issued for "localhost" by the test CA.
+    fn get_test_tls_settings() -> TlsSettings {
+        let test_data_directory =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data").join("tls");
+
+        TlsSettings {
+            cert_path: test_data_directory.join("server.pem").display().to_string(),
+            key_path: test_data_directory.join("server.key").display().to_string(),
+            ca_cert_path: Some(test_data_directory.join("ca.pem").display().to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn create_endpoint_test() {
+        let endpoint = create_endpoint("http://[::1]:5010").unwrap(); // Devskim: ignore DS137138
+        assert_eq!(endpoint.uri().scheme_str(), Some("http"));
+
+        let endpoint = create_endpoint("https://localhost:5010").unwrap();
+        assert_eq!(endpoint.uri().scheme_str(), Some("https"));
+
+        assert!(create_endpoint("not a uri").is_err());
+    }
+
+    #[test]
+    fn get_unix_socket_path_test() {
+        assert_eq!(
+            get_unix_socket_path("unix:///run/ibeji/provider.sock"),
+            Some("/run/ibeji/provider.sock")
+        );
+        assert_eq!(get_unix_socket_path("unix://"), None);
+        assert_eq!(get_unix_socket_path("http://[::1]:5010"), None); // Devskim: ignore DS137138
+    }
+
+    #[tokio::test]
+    async fn connect_unix_socket_test() {
+        let path =
+            std::env::temp_dir().join(format!("grpc_channel_test_{}.sock", std::process::id()));
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let accept_task = tokio::spawn(async move { listener.accept().await });
+
+        let uri = format!("{UNIX_SCHEME_PREFIX}{}", path.display());
+        assert!(connect(&uri).await.is_ok());
+        assert!(accept_task.await.unwrap().is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(connect(&uri).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connect_unix_socket_with_tls_test() {
+        let settings = get_test_tls_settings();
+        let path =
+            std::env::temp_dir().join(format!("grpc_channel_tls_test_{}.sock", std::process::id()));
+        let incoming = UnixListenerStream::new(UnixListener::bind(&path).unwrap());
+        let (_health_reporter, health_service) = tonic_health::server::health_reporter();
+        let server_task = tokio::spawn(
+            Server::builder()
+                .tls_config(create_server_tls_config(&settings).unwrap())
+                .unwrap()
+                .add_service(health_service)
+                .serve_with_incoming(incoming),
+        );
+        let path_str = path.to_str().unwrap();
+
+        let client_tls_config =
+            ClientTlsConfig::new().ca_certificate(settings.load_ca_cert().unwrap().unwrap());
+        let channel = connect_unix_socket(path_str, Some(client_tls_config)).await.unwrap();
+        let response = HealthClient::new(channel).check(HealthCheckRequest::default()).await;
+        assert!(response.is_ok());
+
+        // The server does not serve calls without TLS.
+        let is_served = match connect_unix_socket(path_str, None).await {
+            Ok(channel) => {
+                HealthClient::new(channel).check(HealthCheckRequest::default()).await.is_ok()
+            }
+            Err(_) => false,
+        };
+        assert!(!is_served);
+
+        server_task.abort();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn create_server_tls_config_test() {
+        let settings = TlsSettings {
+            cert_path: String::from("missing_cert.pem"),
+            key_path: String::from("missing_key.pem"),
+            ..Default::default()
+        };
+        assert!(create_server_tls_config(&settings).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use http::HeaderMap;
+use std::error::Error;
+use std::io::{Read, Write};
+
+// This module parses and builds the length-prefixed messages that make up a gRPC body.
+// See <https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md>
+
+/// The gRPC header represents the gRPC message's Compress-Flag and Message-Length.
+pub const GRPC_HEADER_LENGTH: usize = 5;
+
+/// The largest message that will be parsed. It matches tonic's default decoding limit.
+pub const MAX_GRPC_MESSAGE_LENGTH: usize = 4 * 1024 * 1024;
+
+/// The header that names the encoding of a gRPC call's compressed messages.
+pub const GRPC_ENCODING_HEADER: &str = "grpc-encoding";
+
+/// The only compressed encoding that is supported.
+const GZIP_ENCODING: &str = "gzip";
+
+/// The encoding that means that messages are not compressed.
+const IDENTITY_ENCODING: &str = "identity";
+
+/// One length-prefixed message in a gRPC body.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GrpcFrame {
+    /// Is the message compressed with the call's encoding?
+    pub compressed: bool,
+    /// The message, as it is carried in the body.
+    pub message: Bytes,
+}
+
+impl GrpcFrame {
+    /// Encode the frame, with a length prefix that matches its message.
+    pub fn encode(&self) -> Bytes {
+        let mut buffer = BytesMut::with_capacity(GRPC_HEADER_LENGTH + self.message.len());
+        buffer.put_u8(u8::from(self.compressed));
+        buffer.put_u32(self.message.len() as u32);
+        buffer.put_slice(&self.message);
+        buffer.freeze()
+    }
+}
+
+/// Splits the chunks of a gRPC body into frames. A frame may span several chunks and a chunk may
+/// hold several frames.
+#[derive(Debug, Default)]
+pub struct GrpcFrameDecoder {
+    buffer: BytesMut,
+}
+
+impl GrpcFrameDecoder {
+    /// Create a new, empty GrpcFrameDecoder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk of the body.
+    ///
+    /// # Arguments
+    /// * `chunk` - The chunk.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Take the next complete frame. Returns None when more of the body is needed.
+    pub fn next_frame(&mut self) -> Result<Option<GrpcFrame>, Box<dyn Error + Send + Sync>> {
+        if self.buffer.len() < GRPC_HEADER_LENGTH {
+            return Ok(None);
+        }
+
+        let compressed = match self.buffer[0] {
+            0 => false,
+            1 => true,
+            flag => return Err(format!("Invalid gRPC compress flag: {flag}").into()),
+        };
+        let message_length =
+            u32::from_be_bytes([self.buffer[1], self.buffer[2], self.buffer[3], self.buffer[4]])
+                as usize;
+        if message_length > MAX_GRPC_MESSAGE_LENGTH {
+            return Err(format!(
+                "The gRPC message's length {message_length} exceeds the limit of {MAX_GRPC_MESSAGE_LENGTH}"
+            )
+            .into());
+        }
+
+        if self.buffer.len() < GRPC_HEADER_LENGTH + message_length {
+            return Ok(None);
+        }
+
+        self.buffer.advance(GRPC_HEADER_LENGTH);
+        let message = self.buffer.split_to(message_length).freeze();
+
+        Ok(Some(GrpcFrame { compressed, message }))
+    }
+
+    /// Is there part of a frame that has not been taken?
+    pub fn has_remaining(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+}
+
+/// Get a gRPC call's message encoding from its headers. Returns None when it is not provided.
+///
+/// # Arguments
+/// * `headers` - The request's or response's headers.
+pub fn get_grpc_encoding(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(GRPC_ENCODING_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// Decompress a compressed message. The decompressed message is limited to the largest message
+/// that will be parsed, so that a small compressed message can not expand without bound.
+///
+/// # Arguments
+/// * `encoding` - The call's message encoding.
+/// * `message` - The compressed message.
+pub fn decompress(
+    encoding: Option<&str>,
+    message: &[u8],
+) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+    match encoding {
+        Some(GZIP_ENCODING) => {
+            let mut decompressed_message = Vec::new();
+            GzDecoder::new(message)
+                .take(MAX_GRPC_MESSAGE_LENGTH as u64 + 1)
+                .read_to_end(&mut decompressed_message)?;
+            if decompressed_message.len() > MAX_GRPC_MESSAGE_LENGTH {
+                return Err(format!(
+                    "The decompressed gRPC message's length exceeds the limit of {MAX_GRPC_MESSAGE_LENGTH}"
+                )
+                .into());
+            }
+            Ok(Bytes::from(decompressed_message))
+        }
+        Some(IDENTITY_ENCODING) | None => {
+            Err("The gRPC message is compressed, but no message encoding was provided".into())
+        }
+        Some(encoding) => {
+            Err(format!("The gRPC message encoding '{encoding}' is not supported").into())
+        }
+    }
+}
+
+/// Compress a message.
+///
+/// # Arguments
+/// * `encoding` - The call's message encoding.
+/// * `message` - The message.
+pub fn compress(
+    encoding: Option<&str>,
+    message: &[u8],
+) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+    match encoding {
+        Some(GZIP_ENCODING) => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(message)?;
+            Ok(Bytes::from(encoder.finish()?))
+        }
+        Some(encoding) => {
+            Err(format!("The gRPC message encoding '{encoding}' is not supported").into())
+        }
+        None => Err("No gRPC message encoding was provided".into()),
+    }
+}
+
+#[cfg(test)]
+mod grpc_frame_tests {
+    use super::*;
+
+    #[test]
+    fn decode_test() {
+        let first_frame = GrpcFrame { compressed: false, message: Bytes::from(vec![1, 2, 3]) };
+        let second_frame = GrpcFrame { compressed: true, message: Bytes::from(vec![4]) };
+        let body = [first_frame.encode(), second_frame.encode()].concat();
+        assert_eq!(body, [0, 0, 0, 0, 3, 1, 2, 3, 1, 0, 0, 0, 1, 4]);
+
+        // Split the body into chunks that do not line up with the frames.
+        let mut decoder = GrpcFrameDecoder::new();
+        decoder.push(&body[..3]);
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        decoder.push(&body[3..10]);
+        assert_eq!(decoder.next_frame().unwrap(), Some(first_frame));
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        assert!(decoder.has_remaining());
+        decoder.push(&body[10..]);
+        assert_eq!(decoder.next_frame().unwrap(), Some(second_frame));
+        assert!(!decoder.has_remaining());
+
+        let mut decoder = GrpcFrameDecoder::new();
+        decoder.push(&[2, 0, 0, 0, 0]);
+        assert!(decoder.next_frame().is_err());
+    }
+
+    #[test]
+    fn compression_test() {
+        let message = b"The quick brown fox jumps over the lazy dog";
+
+        let compressed_message = compress(Some("gzip"), message).unwrap();
+        assert_ne!(compressed_message, Bytes::from_static(message));
+        assert_eq!(
+            decompress(Some("gzip"), &compressed_message).unwrap(),
+            Bytes::from_static(message)
+        );
+
+        assert!(decompress(None, &compressed_message).is_err());
+        assert!(compress(Some("snappy"), message).is_err());
+    }
+
+    #[test]
+    fn decompress_limit_test() {
+        // A message of zeros compresses to a small fraction of the limit.
+        let message = vec![0; MAX_GRPC_MESSAGE_LENGTH + 1];
+        let compressed_message = compress(Some("gzip"), &message).unwrap();
+        assert!(compressed_message.len() < MAX_GRPC_MESSAGE_LENGTH);
+        assert!(decompress(Some("gzip"), &compressed_message).is_err());
+
+        let message = vec![0; MAX_GRPC_MESSAGE_LENGTH];
+        let compressed_message = compress(Some("gzip"), &message).unwrap();
+        assert_eq!(decompress(Some("gzip"), &compressed_message).unwrap().len(), message.len());
+    }
+}
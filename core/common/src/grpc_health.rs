@@ -0,0 +1,234 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use log::{debug, info};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tonic_health::pb::health_server::{Health, HealthServer};
+use tonic_health::server::{health_reporter, HealthReporter};
+use tonic_health::ServingStatus;
+
+use crate::grpc_module::GrpcModuleHealth;
+use crate::grpc_module_registry::LoadedGrpcModules;
+
+/// The service name that the health of the whole server is reported with.
+const SERVER_SERVICE_NAME: &str = "";
+
+/// Reports the health of a server's services with the standard gRPC health service,
+/// grpc.health.v1.Health. A module's services are serving while the module is healthy, and the
+/// server as a whole (the empty service name) is serving while all of its modules are healthy.
+#[derive(Clone)]
+pub struct GrpcHealthReporter {
+    /// The reporter that updates the health service's statuses.
+    reporter: HealthReporter,
+    /// The full names of the server's services that are not provided by modules.
+    service_names: Vec<String>,
+    /// The server's modules.
+    loaded_modules: LoadedGrpcModules,
+    /// The full names of each module's services, in the same order as the modules.
+    module_service_names: Vec<Vec<String>>,
+}
+
+impl GrpcHealthReporter {
+    /// Create the health reporter and the health service that it reports to. The services that
+    /// are not provided by modules are always serving. The modules' services are not serving until
+    /// their health is first reported.
+    ///
+    /// # Arguments
+    /// * `loaded_modules` - The server's modules.
+    /// * `service_names` - The full names of the server's services that are not provided by
+    ///                     modules, e.g. "invehicle_digital_twin.InvehicleDigitalTwin".
+    pub async fn new(
+        loaded_modules: LoadedGrpcModules,
+        service_names: &[&str],
+    ) -> (Self, HealthServer<impl Health>) {
+        let (mut reporter, health_service) = health_reporter();
+
+        for service_name in service_names {
+            reporter.set_service_status(service_name, ServingStatus::Serving).await;
+        }
+
+        let module_service_names: Vec<Vec<String>> = loaded_modules
+            .grpc_service_names()
+            .into_iter()
+            .map(|(_, module_service_names)| module_service_names)
+            .collect();
+        for service_name in module_service_names.iter().flatten() {
+            reporter.set_service_status(service_name, ServingStatus::NotServing).await;
+        }
+        reporter.set_service_status(SERVER_SERVICE_NAME, ServingStatus::NotServing).await;
+
+        let service_names =
+            service_names.iter().map(|service_name| service_name.to_string()).collect();
+
+        (Self { reporter, service_names, loaded_modules, module_service_names }, health_service)
+    }
+
+    /// Report the current health of the modules.
+    pub async fn report_health(&mut self) {
+        let module_health_list = self.loaded_modules.health().await;
+
+        let mut is_server_healthy = true;
+        for ((module_name, module_health), service_names) in
+            module_health_list.iter().zip(&self.module_service_names)
+        {
+            let status = match module_health {
+                GrpcModuleHealth::Healthy => ServingStatus::Serving,
+                GrpcModuleHealth::Unhealthy(reason) => {
+                    debug!("The {module_name} module is unhealthy: {reason}");
+                    is_server_healthy = false;
+                    ServingStatus::NotServing
+                }
+            };
+
+            for service_name in service_names {
+                self.reporter.set_service_status(service_name, status).await;
+            }
+        }
+
+        let server_status =
+            if is_server_healthy { ServingStatus::Serving } else { ServingStatus::NotServing };
+        self.reporter.set_service_status(SERVER_SERVICE_NAME, server_status).await;
+    }
+
+    /// Report all of the server's services as not serving, e.g. while the server drains its calls
+    /// before it shuts down, so that clients stop sending it new calls. The background task that
+    /// reports the modules' health should have been stopped first.
+    pub async fn report_not_serving(&mut self) {
+        for service_name in
+            self.service_names.iter().chain(self.module_service_names.iter().flatten())
+        {
+            self.reporter.set_service_status(service_name, ServingStatus::NotServing).await;
+        }
+        self.reporter.set_service_status(SERVER_SERVICE_NAME, ServingStatus::NotServing).await;
+    }
+
+    /// Start the background task that reports the modules' health now and then periodically. The
+    /// modules should have been started.
+    ///
+    /// # Arguments
+    /// * `report_interval` - The interval between reports.
+    pub fn start(&self, report_interval: Duration) -> JoinHandle<()> {
+        let mut health_reporter = self.clone();
+
+        info!("The health of the services is reported every {report_interval:?}.");
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(report_interval);
+            loop {
+                interval.tick().await;
+                health_reporter.report_health().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod grpc_health_tests {
+    use super::*;
+    use crate::grpc_frame::{GrpcFrame, GrpcFrameDecoder};
+    use crate::grpc_module::GrpcModule;
+    use crate::grpc_module_registry::{GrpcModuleRegistry, GrpcModuleResult};
+    use crate::grpc_server::GrpcServerAddress;
+    use bytes::Bytes;
+    use http_body::Body;
+    use prost::Message;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tonic::transport::server::RoutesBuilder;
+    use tonic_health::pb::{health_check_response, HealthCheckRequest, HealthCheckResponse};
+    use tower::ServiceExt;
+
+    /// A module whose health can be changed.
+    #[derive(Clone, Default)]
+    struct TestModule {
+        is_healthy: Arc<AtomicBool>,
+    }
+
+    #[tonic::async_trait]
+    impl GrpcModule for TestModule {
+        fn add_grpc_services(&self, _builder: &mut RoutesBuilder) {}
+
+        fn grpc_service_names(&self) -> Vec<String> {
+            vec![String::from("test.TestService")]
+        }
+
+        async fn health(&self) -> GrpcModuleHealth {
+            if self.is_healthy.load(Ordering::SeqCst) {
+                GrpcModuleHealth::Healthy
+            } else {
+                GrpcModuleHealth::Unhealthy(String::from("test"))
+            }
+        }
+    }
+
+    /// Call the health service's Check method and return the status.
+    ///
+    /// # Arguments
+    /// * `health_service` - The health service.
+    /// * `service_name` - The name of the service to check.
+    async fn check<S>(health_service: HealthServer<S>, service_name: &str) -> i32
+    where
+        S: Health,
+    {
+        let message = HealthCheckRequest { service: service_name.to_string() }.encode_to_vec();
+        let request = http::Request::builder()
+            .method("POST")
+            .uri("/grpc.health.v1.Health/Check")
+            .header("content-type", "application/grpc")
+            .body(tonic::transport::Body::from(
+                GrpcFrame { compressed: false, message: Bytes::from(message) }.encode(),
+            ))
+            .unwrap();
+
+        let mut body = health_service.oneshot(request).await.unwrap().into_body();
+        let mut decoder = GrpcFrameDecoder::new();
+        while let Some(chunk) = body.data().await {
+            decoder.push(&chunk.unwrap());
+        }
+        let frame = decoder.next_frame().unwrap().unwrap();
+        HealthCheckResponse::decode(frame.message).unwrap().status
+    }
+
+    #[tokio::test]
+    async fn report_health_test() {
+        let test_module = TestModule::default();
+        let factory_test_module = test_module.clone();
+        let mut registry = GrpcModuleRegistry::new();
+        registry.register_factory("test_module", move |_| {
+            let module: Box<dyn GrpcModule> = Box::new(factory_test_module.clone());
+            async move { GrpcModuleResult::Ok(module) }
+        });
+        let address = GrpcServerAddress::Tcp("[::1]:0".parse().unwrap());
+        let (_, loaded_modules) = registry
+            .build_server(vec![address], &registry.default_module_settings_list(), Vec::new())
+            .await
+            .unwrap();
+
+        let (mut health_reporter, health_service) =
+            GrpcHealthReporter::new(loaded_modules, &["test.BaseService"]).await;
+        let serving = health_check_response::ServingStatus::Serving as i32;
+        let not_serving = health_check_response::ServingStatus::NotServing as i32;
+        assert_eq!(check(health_service.clone(), "test.BaseService").await, serving);
+        assert_eq!(check(health_service.clone(), "test.TestService").await, not_serving);
+        assert_eq!(check(health_service.clone(), "").await, not_serving);
+
+        test_module.is_healthy.store(true, Ordering::SeqCst);
+        health_reporter.report_health().await;
+        assert_eq!(check(health_service.clone(), "test.TestService").await, serving);
+        assert_eq!(check(health_service.clone(), "").await, serving);
+
+        test_module.is_healthy.store(false, Ordering::SeqCst);
+        health_reporter.report_health().await;
+        assert_eq!(check(health_service.clone(), "test.TestService").await, not_serving);
+        assert_eq!(check(health_service.clone(), "").await, not_serving);
+
+        test_module.is_healthy.store(true, Ordering::SeqCst);
+        health_reporter.report_health().await;
+        health_reporter.report_not_serving().await;
+        assert_eq!(check(health_service.clone(), "test.BaseService").await, not_serving);
+        assert_eq!(check(health_service.clone(), "test.TestService").await, not_serving);
+        assert_eq!(check(health_service, "").await, not_serving);
+    }
+}
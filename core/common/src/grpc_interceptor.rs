@@ -5,21 +5,54 @@
 use bytes::Bytes;
 use core::future::Future;
 use dyn_clone::DynClone;
+use futures_core::ready;
 use futures_core::task::{Context, Poll};
 use http::uri::Uri;
+use http::HeaderMap;
 use http_body::Body;
 use hyper::Method;
 use log::warn;
 use regex::Regex;
 use std::error::Error;
+use std::net::SocketAddr;
 use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::unix::UCred;
+use tonic::metadata::MetadataMap;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo, UdsConnectInfo};
+use tonic::transport::Certificate;
+use tonic::Status;
+use tower::util::BoxCloneService;
 use tower::{Layer, Service};
 
+use crate::grpc_frame::{compress, decompress, get_grpc_encoding, GrpcFrame, GrpcFrameDecoder};
+
 // This module provides the gRPC Interceptor construct. It can be used to
 // intercept gRPC calls, and examine/modify their requests and responses.
 
-/// The gRPC header represents the gRPC call's Compress-Flag and Message-Length.
-const GRPC_HEADER_LENGTH: usize = 5;
+/// The context of a gRPC call, which gRPC Interceptors can examine before the call's messages are
+/// handled.
+#[derive(Clone, Debug, Default)]
+pub struct GrpcCallContext {
+    /// The gRPC call's service name, without its package, e.g. "InvehicleDigitalTwin".
+    pub service_name: String,
+    /// The gRPC call's fully qualified service name, e.g.
+    /// "invehicle_digital_twin.InvehicleDigitalTwin".
+    pub qualified_service_name: String,
+    /// The gRPC call's method name.
+    pub method_name: String,
+    /// The request's metadata. Changes to it are passed on to the service.
+    pub request_metadata: MetadataMap,
+    /// The address of the client that made the call, when it connected over TCP.
+    pub peer_address: Option<SocketAddr>,
+    /// The credentials of the process that made the call, when it connected over a Unix domain
+    /// socket.
+    pub peer_credentials: Option<UCred>,
+    /// The DER certificates that the client presented over TLS, leaf first, when it presented any.
+    pub peer_certificates: Option<Arc<Vec<Certificate>>>,
+    /// Metadata to add to the response.
+    pub response_metadata: MetadataMap,
+}
 
 /// This is the trait that a gRPC Interceptor needs to imnplement.
 pub trait GrpcInterceptor: Sync + DynClone {
@@ -36,12 +69,22 @@ pub trait GrpcInterceptor: Sync + DynClone {
     /// Indicates that the response must be handled.
     fn must_handle_response(&self) -> bool;
 
+    /// Handle the call's context, before any of its messages are handled. The request metadata
+    /// can be changed and response metadata can be added. Returning an error status rejects the
+    /// call without calling the service, and the status is returned to the client.
+    ///
+    /// # Arguments
+    /// * `context` - The gRPC call's context.
+    fn handle_call(&self, _context: &mut GrpcCallContext) -> Result<(), Status> {
+        Ok(())
+    }
+
     /// Handle request. Return the new new request.
     ///
     /// # Arguments
     /// * `service_name` - The gRPC call's service name.
     /// * `method_name` - The gRPC call's method name.
-    /// * `protobuf_message_bytes` - The request's protobuf messages as bytes.
+    /// * `protobuf_message_bytes` - One of the request's protobuf messages as bytes.
     fn handle_request(
         &self,
         service_name: &str,
@@ -54,7 +97,7 @@ pub trait GrpcInterceptor: Sync + DynClone {
     /// # Arguments
     /// * `service_name` - The gRPC call's service name.
     /// * `method_name` - The gRPC call's method name.
-    /// * `protobuf_message_bytes` - The response's protobuf messages as bytes.
+    /// * `protobuf_message_bytes` - One of the response's protobuf messages as bytes.
     fn handle_response(
         &self,
         service_name: &str,
@@ -66,10 +109,149 @@ pub trait GrpcInterceptor: Sync + DynClone {
 // Macro that allows for clonable dynamic traits.
 dyn_clone::clone_trait_object!(GrpcInterceptor);
 
+/// This is the trait that an asynchronous gRPC Interceptor needs to implement.
+/// Unlike a GrpcInterceptor, its handlers can await other work, such as network calls.
+#[tonic::async_trait]
+pub trait AsyncGrpcInterceptor: Send + Sync + DynClone {
+    /// Is this interceptor applicable?
+    ///
+    /// # Arguments
+    /// * `service_name` - The gRPC call's service name.
+    /// * `method_name` - The gRPC call's method name.
+    fn is_applicable(&self, service_name: &str, method_name: &str) -> bool;
+
+    /// Indicates that the request must be handled.
+    fn must_handle_request(&self) -> bool;
+
+    /// Indicates that the response must be handled.
+    fn must_handle_response(&self) -> bool;
+
+    /// Handle the call's context, before any of its messages are handled. The request metadata
+    /// can be changed and response metadata can be added. Returning an error status rejects the
+    /// call without calling the service, and the status is returned to the client.
+    ///
+    /// # Arguments
+    /// * `context` - The gRPC call's context.
+    async fn handle_call(&self, _context: &mut GrpcCallContext) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Handle request. Return the new request.
+    ///
+    /// # Arguments
+    /// * `service_name` - The gRPC call's service name.
+    /// * `method_name` - The gRPC call's method name.
+    /// * `protobuf_message_bytes` - One of the request's protobuf messages as bytes.
+    async fn handle_request(
+        &self,
+        service_name: &str,
+        method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>>;
+
+    /// Handle response. Return the new response.
+    ///
+    /// # Arguments
+    /// * `service_name` - The gRPC call's service name.
+    /// * `method_name` - The gRPC call's method name.
+    /// * `protobuf_message_bytes` - One of the response's protobuf messages as bytes.
+    async fn handle_response(
+        &self,
+        service_name: &str,
+        method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>>;
+
+    /// Handle one of the request's messages, with the call's context. Return the new message.
+    /// By default, it calls handle_request. Returning a tonic::Status rejects the call with it.
+    ///
+    /// # Arguments
+    /// * `context` - The gRPC call's context.
+    /// * `protobuf_message` - One of the request's protobuf messages as bytes.
+    async fn handle_request_message(
+        &self,
+        context: &GrpcCallContext,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.handle_request(&context.service_name, &context.method_name, protobuf_message).await
+    }
+
+    /// Handle one of the response's messages, with the call's context. Return the new message.
+    /// By default, it calls handle_response. Returning a tonic::Status ends the response with it.
+    ///
+    /// # Arguments
+    /// * `context` - The gRPC call's context.
+    /// * `protobuf_message` - One of the response's protobuf messages as bytes.
+    async fn handle_response_message(
+        &self,
+        context: &GrpcCallContext,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.handle_response(&context.service_name, &context.method_name, protobuf_message).await
+    }
+}
+
+// Macro that allows for clonable dynamic traits.
+dyn_clone::clone_trait_object!(AsyncGrpcInterceptor);
+
+/// Adapts a GrpcInterceptor to the AsyncGrpcInterceptor trait, so that it can be hosted with
+/// asynchronous gRPC Interceptors. Its handlers run synchronously, so they should not block.
+#[derive(Clone)]
+pub struct SyncGrpcInterceptorAdapter {
+    interceptor: Box<dyn GrpcInterceptor + Send>,
+}
+
+impl SyncGrpcInterceptorAdapter {
+    /// Create an adapter for a gRPC Interceptor.
+    ///
+    /// # Arguments
+    /// * `interceptor` - The boxed gRPC Interceptor.
+    pub fn new(interceptor: Box<dyn GrpcInterceptor + Send>) -> Self {
+        Self { interceptor }
+    }
+}
+
+#[tonic::async_trait]
+impl AsyncGrpcInterceptor for SyncGrpcInterceptorAdapter {
+    fn is_applicable(&self, service_name: &str, method_name: &str) -> bool {
+        self.interceptor.is_applicable(service_name, method_name)
+    }
+
+    fn must_handle_request(&self) -> bool {
+        self.interceptor.must_handle_request()
+    }
+
+    fn must_handle_response(&self) -> bool {
+        self.interceptor.must_handle_response()
+    }
+
+    async fn handle_call(&self, context: &mut GrpcCallContext) -> Result<(), Status> {
+        self.interceptor.handle_call(context)
+    }
+
+    async fn handle_request(
+        &self,
+        service_name: &str,
+        method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.interceptor.handle_request(service_name, method_name, protobuf_message)
+    }
+
+    async fn handle_response(
+        &self,
+        service_name: &str,
+        method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.interceptor.handle_response(service_name, method_name, protobuf_message)
+    }
+}
+
 /// The tower layer that hosts a service that hosts a gRPC Interceptor.
 #[derive(Clone)]
 pub struct GrpcInterceptorLayer {
-    interceptor: Box<dyn GrpcInterceptor + Send>,
+    interceptor: Box<dyn AsyncGrpcInterceptor>,
 }
 
 impl GrpcInterceptorLayer {
@@ -78,6 +260,14 @@ impl GrpcInterceptorLayer {
     /// # Arguments
     /// * `interceptor` - The boxed gRPC Interceptor.
     pub fn new(interceptor: Box<dyn GrpcInterceptor + Send>) -> Self {
+        Self::new_async(Box::new(SyncGrpcInterceptorAdapter::new(interceptor)))
+    }
+
+    /// Create the tower layer for an asynchronous gRPC Interceptor.
+    ///
+    /// # Arguments
+    /// * `interceptor` - The boxed asynchronous gRPC Interceptor.
+    pub fn new_async(interceptor: Box<dyn AsyncGrpcInterceptor>) -> Self {
         Self { interceptor }
     }
 }
@@ -90,39 +280,306 @@ impl<S> Layer<S> for GrpcInterceptorLayer {
     }
 }
 
+/// The type-erased service that a chain of gRPC Interceptor layers produces.
+pub type GrpcInterceptorChainService = BoxCloneService<
+    http::request::Request<tonic::transport::Body>,
+    http::response::Response<tonic::body::BoxBody>,
+    Box<dyn std::error::Error + Sync + Send>,
+>;
+
+/// The tower layer that applies a list of gRPC Interceptor layers, whose length is only known at
+/// runtime. The first layer in the list is the outermost, as it would be with
+/// `ServiceBuilder::layer`.
+#[derive(Clone, Default)]
+pub struct GrpcInterceptorChainLayer {
+    layers: Vec<GrpcInterceptorLayer>,
+}
+
+impl GrpcInterceptorChainLayer {
+    /// Create the tower layer for a list of gRPC Interceptor layers.
+    ///
+    /// # Arguments
+    /// * `layers` - The gRPC Interceptor layers, from the outermost to the innermost.
+    pub fn new(layers: Vec<GrpcInterceptorLayer>) -> Self {
+        Self { layers }
+    }
+}
+
+impl<S> Layer<S> for GrpcInterceptorChainLayer
+where
+    S: Service<
+            http::request::Request<tonic::transport::Body>,
+            Response = http::response::Response<tonic::body::BoxBody>,
+            Error = Box<dyn std::error::Error + Sync + Send>,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Service = GrpcInterceptorChainService;
+
+    fn layer(&self, service: S) -> Self::Service {
+        // Wrap the innermost layer first, so that the first layer ends up as the outermost.
+        self.layers.iter().rev().fold(BoxCloneService::new(service), |service, layer| {
+            BoxCloneService::new(layer.layer(service))
+        })
+    }
+}
+
+/// The body of a gRPC call that a GrpcInterceptorBody carries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum GrpcMessageDirection {
+    Request,
+    Response,
+}
+
+/// The future that handles one of a body's frames and returns the encoded frame that replaces it.
+type HandledGrpcFrameFuture =
+    Pin<Box<dyn Future<Output = Result<Bytes, Box<dyn Error + Send + Sync>>> + Send>>;
+
+/// A gRPC body whose messages are handled by a gRPC Interceptor, one at a time, as they arrive.
+/// This supports bodies with any number of messages, so that streaming calls can be intercepted.
+/// Compressed messages are decompressed before they are handled and recompressed afterwards.
+/// The body's trailers are passed through unchanged, unless a response message could not be
+/// handled. Then the response ends with trailers that carry the error's status.
+struct GrpcInterceptorBody<B> {
+    inner: B,
+    interceptor: Box<dyn AsyncGrpcInterceptor>,
+    direction: GrpcMessageDirection,
+    context: Arc<GrpcCallContext>,
+    encoding: Option<String>,
+    decoder: GrpcFrameDecoder,
+    handled_frame: Option<HandledGrpcFrameFuture>,
+    error_status: Option<Status>,
+    is_done: bool,
+    is_inner_done: bool,
+}
+
+impl<B> GrpcInterceptorBody<B> {
+    /// Create a body that intercepts the messages of another body.
+    ///
+    /// # Arguments
+    /// * `inner` - The body whose messages are intercepted.
+    /// * `interceptor` - The gRPC Interceptor.
+    /// * `direction` - Is it the request's body or the response's body?
+    /// * `context` - The gRPC call's context.
+    /// * `encoding` - The gRPC call's message encoding, from the grpc-encoding header.
+    fn new(
+        inner: B,
+        interceptor: Box<dyn AsyncGrpcInterceptor>,
+        direction: GrpcMessageDirection,
+        context: Arc<GrpcCallContext>,
+        encoding: Option<String>,
+    ) -> Self {
+        Self {
+            inner,
+            interceptor,
+            direction,
+            context,
+            encoding,
+            decoder: GrpcFrameDecoder::new(),
+            handled_frame: None,
+            error_status: None,
+            is_done: false,
+            is_inner_done: false,
+        }
+    }
+
+    /// Create the future that handles a frame's message with the gRPC Interceptor.
+    ///
+    /// # Arguments
+    /// * `frame` - The frame.
+    fn handle_frame(&self, frame: GrpcFrame) -> HandledGrpcFrameFuture {
+        let interceptor = self.interceptor.clone();
+        let direction = self.direction;
+        let context = self.context.clone();
+        let encoding = self.encoding.clone();
+
+        Box::pin(async move {
+            let protobuf_message = if frame.compressed {
+                decompress(encoding.as_deref(), &frame.message)?
+            } else {
+                frame.message
+            };
+
+            let new_protobuf_message = match direction {
+                GrpcMessageDirection::Request => {
+                    interceptor.handle_request_message(&context, protobuf_message).await?
+                }
+                GrpcMessageDirection::Response => {
+                    interceptor.handle_response_message(&context, protobuf_message).await?
+                }
+            };
+
+            let message = if frame.compressed {
+                compress(encoding.as_deref(), &new_protobuf_message)?
+            } else {
+                new_protobuf_message
+            };
+
+            Ok(GrpcFrame { compressed: frame.compressed, message }.encode())
+        })
+    }
+
+    /// Handle an error. A request's error is passed to the service, which returns its status to
+    /// the client. A response's error ends the response, with trailers that carry its status.
+    ///
+    /// # Arguments
+    /// * `error` - The error.
+    fn handle_error(
+        &mut self,
+        error: Box<dyn Error + Send + Sync>,
+    ) -> Poll<Option<Result<Bytes, Box<dyn Error + Send + Sync>>>> {
+        self.is_done = true;
+
+        match self.direction {
+            GrpcMessageDirection::Request => Poll::Ready(Some(Err(error))),
+            GrpcMessageDirection::Response => {
+                let status = Status::from_error(error);
+                warn!(
+                    "Unable to handle a response message for {}/{}: {status}",
+                    self.context.service_name, self.context.method_name
+                );
+                self.error_status = Some(status);
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl<B> Body for GrpcInterceptorBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    type Data = Bytes;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+
+        if this.is_done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            // Finish handling the current frame before the next one is taken, so that the
+            // messages keep their order.
+            if let Some(handled_frame) = this.handled_frame.as_mut() {
+                let result = ready!(handled_frame.as_mut().poll(cx));
+                this.handled_frame = None;
+                return match result {
+                    Ok(frame_bytes) => Poll::Ready(Some(Ok(frame_bytes))),
+                    Err(err) => this.handle_error(err),
+                };
+            }
+
+            match this.decoder.next_frame() {
+                Ok(Some(frame)) => {
+                    this.handled_frame = Some(this.handle_frame(frame));
+                    continue;
+                }
+                Ok(None) => {}
+                Err(err) => return this.handle_error(err),
+            }
+
+            if this.is_inner_done {
+                if this.decoder.has_remaining() {
+                    return this
+                        .handle_error("The gRPC body ended with an incomplete message".into());
+                }
+                this.is_done = true;
+                return Poll::Ready(None);
+            }
+
+            match ready!(Pin::new(&mut this.inner).poll_data(cx)) {
+                Some(Ok(chunk)) => this.decoder.push(&chunk),
+                Some(Err(err)) => {
+                    this.is_done = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                None => this.is_inner_done = true,
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+
+        if let Some(status) = this.error_status.take() {
+            let mut trailers = HeaderMap::new();
+            status.add_header(&mut trailers)?;
+            return Poll::Ready(Ok(Some(trailers)));
+        }
+
+        Pin::new(&mut this.inner).poll_trailers(cx).map_err(Into::into)
+    }
+}
+
 #[derive(Clone)]
 /// The tower service that hosts a gRPC Interceptor.
 pub struct GrpcInterceptorService<S> {
     service: S,
-    interceptor: Box<dyn GrpcInterceptor + Send>,
+    interceptor: Box<dyn AsyncGrpcInterceptor>,
 }
 
 impl<S> GrpcInterceptorService<S> {
-    /// Retrieve the gRPC service name and method name from a URI.
-    /// If it cannot succesfully be parsed, then an empty service name and/or method name will be returned.
+    /// Retrieve the gRPC fully qualified service name, service name and method name from a URI.
+    /// If it cannot succesfully be parsed, then empty names will be returned.
     ///
     /// * `uri` - The uri used for the gRPC call.
-    fn retrieve_grpc_names_from_uri(uri: &Uri) -> (String, String) {
+    fn retrieve_grpc_names_from_uri(uri: &Uri) -> (String, String, String) {
+        let mut qualified_service_name = String::new();
         let mut service_name = String::new();
         let mut method_name = String::new();
         // A gRPC URI path looks like this "/invehicle_digital_twin.InvehicleDigitalTwin/FindById".
-        match Regex::new(r"^/[^/\.]+\.([^/]+)/(.+)$") {
+        // The package name can have several parts, e.g. "/digital_twin_graph.v1.digital_twin_graph.DigitalTwinGraph/Get",
+        // and the service name is the part after the last one.
+        match Regex::new(r"^/((?:[^/]+\.)?([^/\.]+))/(.+)$") {
             Ok(regex_pattern) => {
                 if let Some(caps) = regex_pattern.captures(uri.path()) {
                     // Note: caps.get(0) represents the entire string that matched.
                     //       In the earlier gRPC URI path example it would be
                     //       "/invehicle_digital_twin.InvehicleDigitalTwin/FindById".
-                    //       caps.get(1) and caps.get(2) represent the sub-parts that matched.
-                    if caps.len() == 3 {
-                        service_name = caps.get(1).unwrap().as_str().to_string();
-                        method_name = caps.get(2).unwrap().as_str().to_string();
+                    //       caps.get(1), caps.get(2) and caps.get(3) represent the sub-parts that
+                    //       matched, where caps.get(2) is the last part of caps.get(1).
+                    if caps.len() == 4 {
+                        qualified_service_name = caps.get(1).unwrap().as_str().to_string();
+                        service_name = caps.get(2).unwrap().as_str().to_string();
+                        method_name = caps.get(3).unwrap().as_str().to_string();
                     }
                 }
             }
             Err(err) => warn!("Regex pattern for gRPC names is not valid: {err}"),
         }
 
-        (service_name, method_name)
+        (qualified_service_name, service_name, method_name)
+    }
+
+    /// Retrieve the client's address, credentials and certificates from a request's extensions
+    /// into a call's context. A connection that uses TLS wraps the TCP or Unix domain socket
+    /// connection's info.
+    ///
+    /// * `extensions` - The request's extensions.
+    /// * `context` - The call's context.
+    fn retrieve_peer_info(extensions: &http::Extensions, context: &mut GrpcCallContext) {
+        if let Some(info) = extensions.get::<TlsConnectInfo<TcpConnectInfo>>() {
+            context.peer_address = info.get_ref().remote_addr();
+            context.peer_certificates = info.peer_certs();
+        } else if let Some(info) = extensions.get::<TlsConnectInfo<UdsConnectInfo>>() {
+            context.peer_credentials = info.get_ref().peer_cred;
+            context.peer_certificates = info.peer_certs();
+        } else if let Some(info) = extensions.get::<TcpConnectInfo>() {
+            context.peer_address = info.remote_addr();
+        } else if let Some(info) = extensions.get::<UdsConnectInfo>() {
+            context.peer_credentials = info.peer_cred;
+        }
     }
 }
 
@@ -132,7 +589,9 @@ where
             http::request::Request<tonic::transport::Body>,
             Response = http::response::Response<tonic::body::BoxBody>,
             Error = Box<dyn std::error::Error + Sync + Send>,
-        > + Send,
+        > + Clone
+        + Send
+        + 'static,
     S::Future: Send + 'static,
 {
     type Response = S::Response;
@@ -148,88 +607,86 @@ where
 
     /// Implementation of tower's Service trait's call method.
     /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
-    fn call(
-        &mut self,
-        mut request: http::request::Request<tonic::transport::Body>,
-    ) -> Self::Future {
+    fn call(&mut self, request: http::request::Request<tonic::transport::Body>) -> Self::Future {
         let interceptor = self.interceptor.clone();
 
-        let (service_name, method_name) = Self::retrieve_grpc_names_from_uri(request.uri());
+        // The request is handled asynchronously, so the inner service is moved into the future.
+        // It is the instance that poll_ready was called on, so it is ready to be called. The clone
+        // that replaces it will be made ready by the next call to poll_ready.
+        // See <https://docs.rs/tower/latest/tower/trait.Service.html#be-careful-when-cloning-inner-services>
+        let clone = self.service.clone();
+        let mut service = std::mem::replace(&mut self.service, clone);
+
+        let (qualified_service_name, service_name, method_name) =
+            Self::retrieve_grpc_names_from_uri(request.uri());
         let is_applicable = interceptor.is_applicable(&service_name, &method_name)
             && (request.method() == Method::POST);
 
-        if is_applicable && interceptor.must_handle_request() {
-            let (parts, body) = request.into_parts();
-
-            // There is a known issue where hyper::body::to_bytes sometimes hangs in the code below.
-            // We will use a timeout to break out when this happens.  This fix is a bandaid.  We will
-            // implement a better fix after we have upgraded to the latest major version of the hyper crate.
-            let mut body_bytes: Bytes = match futures::executor::block_on(async {
-                async_std::future::timeout(
-                    core::time::Duration::from_secs(5),
-                    hyper::body::to_bytes(body),
-                )
-                .await
-            }) {
-                Ok(Ok(bytes)) => bytes,
-                Ok(Err(err)) => {
-                    return Box::pin(async move {
-                        Err(Box::new(err) as Box<dyn std::error::Error + Sync + Send>)
-                    });
-                }
-                Err(err) => {
-                    return Box::pin(async move {
-                        Err(Box::new(err) as Box<dyn std::error::Error + Sync + Send>)
-                    });
-                }
-            };
+        Box::pin(async move {
+            if !is_applicable {
+                return service.call(request).await;
+            }
+
+            let (mut parts, body) = request.into_parts();
 
-            let protobuf_message_bytes: Bytes = body_bytes.split_off(GRPC_HEADER_LENGTH);
-            let grpc_header_bytes = body_bytes;
-            let new_protobuf_message_bytes: Bytes = match interceptor.handle_request(
-                &service_name,
-                &method_name,
-                protobuf_message_bytes,
-            ) {
-                Ok(bytes) => bytes,
-                Err(err) => return Box::pin(async move { Err(err) }),
+            let mut context = GrpcCallContext {
+                service_name,
+                qualified_service_name,
+                method_name,
+                request_metadata: MetadataMap::from_headers(parts.headers),
+                ..Default::default()
             };
-            let new_body_chunks: Vec<Result<_, std::io::Error>> =
-                vec![Ok(grpc_header_bytes), Ok(new_protobuf_message_bytes)];
-            let stream = futures_util::stream::iter(new_body_chunks);
-            let new_body = tonic::transport::Body::wrap_stream(stream);
-            request = http::request::Request::from_parts(parts, new_body);
-        }
+            Self::retrieve_peer_info(&parts.extensions, &mut context);
+            let result = interceptor.handle_call(&mut context).await;
+            parts.headers = context.request_metadata.clone().into_headers();
+            let response_metadata = context.response_metadata.clone().into_headers();
 
-        let fut = self.service.call(request);
+            if let Err(status) = result {
+                // Short-circuit the call with a trailers-only response that carries the status.
+                let mut response = status.to_http();
+                response.headers_mut().extend(response_metadata);
+                return Ok(response);
+            }
 
-        Box::pin(async move {
-            let mut response = fut.await?;
-
-            if is_applicable && interceptor.must_handle_response() {
-                let (parts, body) = response.into_parts();
-                let mut body_bytes = match hyper::body::to_bytes(body).await {
-                    Ok(bytes) => bytes,
-                    Err(err) => {
-                        return Err(Box::new(err) as Box<dyn std::error::Error + Sync + Send>)
-                    }
-                };
-                let protobuf_message_bytes = body_bytes.split_off(GRPC_HEADER_LENGTH);
-                let grpc_header_bytes = body_bytes;
-                let new_protobuf_message_bytes = match interceptor.handle_response(
-                    &service_name,
-                    &method_name,
-                    protobuf_message_bytes,
-                ) {
-                    Ok(bytes) => bytes,
-                    Err(err) => return Err(err),
-                };
-                let new_body_chunks: Vec<Result<_, std::io::Error>> =
-                    vec![Ok(grpc_header_bytes), Ok(new_protobuf_message_bytes)];
-                let stream = futures_util::stream::iter(new_body_chunks);
+            let context = Arc::new(context);
+
+            let request = if interceptor.must_handle_request() {
+                let encoding = get_grpc_encoding(&parts.headers);
+                // The intercepted messages can have different lengths.
+                parts.headers.remove(http::header::CONTENT_LENGTH);
+                let mut intercepted_body = GrpcInterceptorBody::new(
+                    body,
+                    interceptor.clone(),
+                    GrpcMessageDirection::Request,
+                    context.clone(),
+                    encoding,
+                );
+                let stream = futures_util::stream::poll_fn(move |cx| {
+                    Pin::new(&mut intercepted_body).poll_data(cx)
+                });
                 let new_body = tonic::transport::Body::wrap_stream(stream);
+                http::request::Request::from_parts(parts, new_body)
+            } else {
+                http::request::Request::from_parts(parts, body)
+            };
+
+            let mut response = service.call(request).await?;
+            response.headers_mut().extend(response_metadata);
+
+            if interceptor.must_handle_response() {
+                let (mut parts, body) = response.into_parts();
+                let encoding = get_grpc_encoding(&parts.headers);
+                // The intercepted messages can have different lengths.
+                parts.headers.remove(http::header::CONTENT_LENGTH);
+                let intercepted_body = GrpcInterceptorBody::new(
+                    body,
+                    interceptor,
+                    GrpcMessageDirection::Response,
+                    context,
+                    encoding,
+                );
                 let new_box_body =
-                    new_body.map_err(|e| tonic::Status::from_error(Box::new(e))).boxed_unsync();
+                    intercepted_body.map_err(tonic::Status::from_error).boxed_unsync();
                 response = http::response::Response::from_parts(parts, new_box_body);
             }
 
@@ -237,3 +694,256 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod grpc_interceptor_tests {
+    use super::*;
+    use crate::grpc_frame::GRPC_ENCODING_HEADER;
+    use tower::ServiceExt;
+
+    /// An interceptor that reverses the bytes of the request's protobuf message.
+    #[derive(Clone)]
+    struct ReversingGrpcInterceptor {}
+
+    impl GrpcInterceptor for ReversingGrpcInterceptor {
+        fn is_applicable(&self, service_name: &str, method_name: &str) -> bool {
+            service_name == "InvehicleDigitalTwin" && method_name == "Register"
+        }
+
+        fn must_handle_request(&self) -> bool {
+            true
+        }
+
+        fn must_handle_response(&self) -> bool {
+            true
+        }
+
+        fn handle_request(
+            &self,
+            _service_name: &str,
+            _method_name: &str,
+            protobuf_message: Bytes,
+        ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+            Ok(protobuf_message.iter().rev().copied().collect())
+        }
+
+        fn handle_response(
+            &self,
+            _service_name: &str,
+            _method_name: &str,
+            protobuf_message: Bytes,
+        ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+            Ok(protobuf_message)
+        }
+    }
+
+    /// An interceptor that rejects calls without an API key and all of their responses.
+    #[derive(Clone)]
+    struct ApiKeyGrpcInterceptor {}
+
+    #[tonic::async_trait]
+    impl AsyncGrpcInterceptor for ApiKeyGrpcInterceptor {
+        fn is_applicable(&self, _service_name: &str, _method_name: &str) -> bool {
+            true
+        }
+
+        fn must_handle_request(&self) -> bool {
+            false
+        }
+
+        fn must_handle_response(&self) -> bool {
+            true
+        }
+
+        async fn handle_call(&self, context: &mut GrpcCallContext) -> Result<(), Status> {
+            if context.request_metadata.get("x-api-key").is_none() {
+                return Err(Status::unauthenticated("An API key is required"));
+            }
+
+            context.response_metadata.insert("x-api-key-checked", "true".parse().unwrap());
+            Ok(())
+        }
+
+        async fn handle_request(
+            &self,
+            _service_name: &str,
+            _method_name: &str,
+            protobuf_message: Bytes,
+        ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+            Ok(protobuf_message)
+        }
+
+        async fn handle_response(
+            &self,
+            _service_name: &str,
+            _method_name: &str,
+            _protobuf_message: Bytes,
+        ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+            Err(Box::new(Status::permission_denied("The response is not allowed")))
+        }
+    }
+
+    /// A service that echoes the request's body and message encoding in its response.
+    ///
+    /// # Arguments
+    /// * `request` - The request.
+    async fn echo(
+        request: http::request::Request<tonic::transport::Body>,
+    ) -> Result<http::response::Response<tonic::body::BoxBody>, Box<dyn Error + Send + Sync>> {
+        let (parts, body) = request.into_parts();
+        let body_bytes = hyper::body::to_bytes(body).await?;
+        let mut response = http::response::Response::new(tonic::body::boxed(
+            tonic::transport::Body::from(body_bytes),
+        ));
+        if let Some(encoding) = parts.headers.get(GRPC_ENCODING_HEADER) {
+            response.headers_mut().insert(GRPC_ENCODING_HEADER, encoding.clone());
+        }
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn sync_grpc_interceptor_adapter_test() {
+        let echo_service = tower::service_fn(echo);
+
+        let layer = GrpcInterceptorLayer::new(Box::new(ReversingGrpcInterceptor {}));
+        let service = layer.layer(echo_service);
+
+        let request = http::request::Request::builder()
+            .method(Method::POST)
+            .uri("/invehicle_digital_twin.InvehicleDigitalTwin/Register")
+            .body(tonic::transport::Body::from(vec![0, 0, 0, 0, 3, 1, 2, 3]))
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        let body_bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body_bytes, Bytes::from(vec![0, 0, 0, 0, 3, 3, 2, 1]));
+    }
+
+    #[tokio::test]
+    async fn multiple_compressed_messages_test() {
+        let echo_service = tower::service_fn(echo);
+
+        let layer = GrpcInterceptorLayer::new(Box::new(ReversingGrpcInterceptor {}));
+        let service = layer.layer(echo_service);
+
+        let first_frame = GrpcFrame { compressed: false, message: Bytes::from(vec![1, 2, 3]) };
+        let second_frame =
+            GrpcFrame { compressed: true, message: compress(Some("gzip"), &[4, 5, 6, 7]).unwrap() };
+        let body_bytes = [first_frame.encode(), second_frame.encode()].concat();
+
+        // Send the body in chunks that do not line up with the frames, like a stream would.
+        let body_chunks: Vec<Result<_, std::io::Error>> =
+            body_bytes.chunks(4).map(|chunk| Ok(Bytes::copy_from_slice(chunk))).collect();
+        let request = http::request::Request::builder()
+            .method(Method::POST)
+            .uri("/invehicle_digital_twin.InvehicleDigitalTwin/Register")
+            .header(GRPC_ENCODING_HEADER, "gzip")
+            .body(tonic::transport::Body::wrap_stream(futures_util::stream::iter(body_chunks)))
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        let body_bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        let mut decoder = GrpcFrameDecoder::new();
+        decoder.push(&body_bytes);
+        assert_eq!(
+            decoder.next_frame().unwrap(),
+            Some(GrpcFrame { compressed: false, message: Bytes::from(vec![3, 2, 1]) })
+        );
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert!(frame.compressed);
+        assert_eq!(
+            decompress(Some("gzip"), &frame.message).unwrap(),
+            Bytes::from(vec![7, 6, 5, 4])
+        );
+        assert!(!decoder.has_remaining());
+    }
+
+    #[tokio::test]
+    async fn grpc_call_context_test() {
+        let layer = GrpcInterceptorLayer::new_async(Box::new(ApiKeyGrpcInterceptor {}));
+        let service = layer.layer(tower::service_fn(echo));
+
+        // A call without an API key is rejected before it reaches the service.
+        let request = http::request::Request::builder()
+            .method(Method::POST)
+            .uri("/invehicle_digital_twin.InvehicleDigitalTwin/FindById")
+            .body(tonic::transport::Body::from(vec![0, 0, 0, 0, 1, 1]))
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["grpc-status"], "16");
+        let body_bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(body_bytes.is_empty());
+
+        // A call with an API key reaches the service, but its response is rejected.
+        let request = http::request::Request::builder()
+            .method(Method::POST)
+            .uri("/invehicle_digital_twin.InvehicleDigitalTwin/FindById")
+            .header("x-api-key", "key")
+            .body(tonic::transport::Body::from(vec![0, 0, 0, 0, 1, 1]))
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["x-api-key-checked"], "true");
+        let mut body = response.into_body();
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!(trailers["grpc-status"], "7");
+    }
+
+    /// Retrieve the gRPC names from a URI path.
+    ///
+    /// # Arguments
+    /// * `path` - The URI path.
+    fn retrieve_grpc_names(path: &'static str) -> (String, String, String) {
+        GrpcInterceptorService::<()>::retrieve_grpc_names_from_uri(&Uri::from_static(path))
+    }
+
+    #[test]
+    fn retrieve_grpc_names_from_uri_test() {
+        assert_eq!(
+            retrieve_grpc_names("/invehicle_digital_twin.InvehicleDigitalTwin/FindById"),
+            (
+                String::from("invehicle_digital_twin.InvehicleDigitalTwin"),
+                String::from("InvehicleDigitalTwin"),
+                String::from("FindById")
+            )
+        );
+        assert_eq!(
+            retrieve_grpc_names("/digital_twin_graph.v1.digital_twin_graph.DigitalTwinGraph/Get"),
+            (
+                String::from("digital_twin_graph.v1.digital_twin_graph.DigitalTwinGraph"),
+                String::from("DigitalTwinGraph"),
+                String::from("Get")
+            )
+        );
+        assert_eq!(
+            retrieve_grpc_names("/grpc.health.v1.Health/Check"),
+            (String::from("grpc.health.v1.Health"), String::from("Health"), String::from("Check"))
+        );
+    }
+
+    #[test]
+    fn retrieve_grpc_names_from_uri_without_package_test() {
+        assert_eq!(
+            retrieve_grpc_names("/InvehicleDigitalTwin/FindById"),
+            (
+                String::from("InvehicleDigitalTwin"),
+                String::from("InvehicleDigitalTwin"),
+                String::from("FindById")
+            )
+        );
+    }
+
+    #[test]
+    fn retrieve_grpc_names_from_invalid_uri_test() {
+        let empty_names = (String::new(), String::new(), String::new());
+        assert_eq!(retrieve_grpc_names("/"), empty_names);
+        assert_eq!(
+            retrieve_grpc_names("/invehicle_digital_twin.InvehicleDigitalTwin"),
+            empty_names
+        );
+        assert_eq!(retrieve_grpc_names("/invehicle_digital_twin./FindById"), empty_names);
+        assert_eq!(
+            retrieve_grpc_names("/invehicle_digital_twin.InvehicleDigitalTwin/"),
+            empty_names
+        );
+    }
+}
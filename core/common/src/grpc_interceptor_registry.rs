@@ -0,0 +1,314 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use bytes::Bytes;
+use log::info;
+use regex::Regex;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use tonic::Status;
+
+use crate::grpc_interceptor::{AsyncGrpcInterceptor, GrpcCallContext, GrpcInterceptorLayer};
+
+/// The settings for one of the interceptors in a service's `interceptors` setting.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GrpcInterceptorSettings {
+    /// The name that the interceptor's factory is registered with.
+    pub name: String,
+    /// Is the interceptor enabled? If it is not provided, then the interceptor is enabled.
+    pub enabled: Option<bool>,
+    /// A regular expression that the call's service name must fully match, e.g.
+    /// "InvehicleDigitalTwin". If it is not provided, then all services match.
+    pub service_pattern: Option<String>,
+    /// A regular expression that the call's method name must fully match, e.g. "Register|Unregister".
+    /// If it is not provided, then all methods match.
+    pub method_pattern: Option<String>,
+}
+
+impl GrpcInterceptorSettings {
+    /// Create the settings for an enabled interceptor that applies to all services and methods.
+    ///
+    /// # Arguments
+    /// * `name` - The name that the interceptor's factory is registered with.
+    pub fn new(name: &str) -> Self {
+        GrpcInterceptorSettings {
+            name: name.to_string(),
+            enabled: None,
+            service_pattern: None,
+            method_pattern: None,
+        }
+    }
+
+    /// Is the interceptor enabled?
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+/// A function that creates an interceptor.
+pub type GrpcInterceptorFactory = Box<dyn Fn() -> Box<dyn AsyncGrpcInterceptor> + Send + Sync>;
+
+/// Narrows an interceptor to the calls whose service and method names match the configured
+/// patterns. The interceptor is only applied to the calls that it is applicable to itself.
+#[derive(Clone)]
+struct ScopedGrpcInterceptor {
+    interceptor: Box<dyn AsyncGrpcInterceptor>,
+    service_pattern: Option<Regex>,
+    method_pattern: Option<Regex>,
+}
+
+impl ScopedGrpcInterceptor {
+    /// Compile a pattern, so that it must match the whole name.
+    ///
+    /// # Arguments
+    /// * `pattern` - The pattern.
+    fn compile_pattern(pattern: &Option<String>) -> Result<Option<Regex>, regex::Error> {
+        pattern.as_ref().map(|pattern| Regex::new(&format!("^(?:{pattern})$"))).transpose()
+    }
+}
+
+#[tonic::async_trait]
+impl AsyncGrpcInterceptor for ScopedGrpcInterceptor {
+    fn is_applicable(&self, service_name: &str, method_name: &str) -> bool {
+        self.service_pattern.as_ref().map_or(true, |pattern| pattern.is_match(service_name))
+            && self.method_pattern.as_ref().map_or(true, |pattern| pattern.is_match(method_name))
+            && self.interceptor.is_applicable(service_name, method_name)
+    }
+
+    fn must_handle_request(&self) -> bool {
+        self.interceptor.must_handle_request()
+    }
+
+    fn must_handle_response(&self) -> bool {
+        self.interceptor.must_handle_response()
+    }
+
+    async fn handle_call(&self, context: &mut GrpcCallContext) -> Result<(), Status> {
+        self.interceptor.handle_call(context).await
+    }
+
+    async fn handle_request(
+        &self,
+        service_name: &str,
+        method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.interceptor.handle_request(service_name, method_name, protobuf_message).await
+    }
+
+    async fn handle_response(
+        &self,
+        service_name: &str,
+        method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.interceptor.handle_response(service_name, method_name, protobuf_message).await
+    }
+
+    async fn handle_request_message(
+        &self,
+        context: &GrpcCallContext,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.interceptor.handle_request_message(context, protobuf_message).await
+    }
+
+    async fn handle_response_message(
+        &self,
+        context: &GrpcCallContext,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.interceptor.handle_response_message(context, protobuf_message).await
+    }
+}
+
+/// A registry of the interceptors that are compiled into a service. The interceptors that are
+/// actually applied, their order and the calls that they apply to are chosen at runtime from the
+/// service's settings.
+#[derive(Default)]
+pub struct GrpcInterceptorRegistry {
+    /// The interceptor factories, keyed by interceptor name.
+    factories: HashMap<String, GrpcInterceptorFactory>,
+    /// The interceptor names, in the order that their factories were registered.
+    interceptor_names: Vec<String>,
+}
+
+impl GrpcInterceptorRegistry {
+    /// Creates a new, empty GrpcInterceptorRegistry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the factory for an interceptor. A factory that is registered with the same name as
+    /// an earlier one replaces it.
+    ///
+    /// # Arguments
+    /// * `name` - The interceptor's name, which is used to refer to it in the settings.
+    /// * `factory` - The function that creates the interceptor.
+    pub fn register_factory<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> Box<dyn AsyncGrpcInterceptor> + Send + Sync + 'static,
+    {
+        if self.factories.insert(name.to_string(), Box::new(factory)).is_none() {
+            self.interceptor_names.push(name.to_string());
+        }
+    }
+
+    /// The names of the registered interceptors, in the order that they were registered.
+    pub fn interceptor_names(&self) -> &[String] {
+        &self.interceptor_names
+    }
+
+    /// Create the layers for the enabled interceptors, in the order that they are listed. The
+    /// first layer is the outermost.
+    ///
+    /// # Arguments
+    /// * `interceptor_settings_list` - The settings for the interceptors.
+    pub fn create_layers(
+        &self,
+        interceptor_settings_list: &[GrpcInterceptorSettings],
+    ) -> Result<Vec<GrpcInterceptorLayer>, Box<dyn Error + Send + Sync>> {
+        let mut layers = Vec::new();
+
+        for interceptor_settings in interceptor_settings_list {
+            if !interceptor_settings.is_enabled() {
+                info!("The {} interceptor is disabled.", interceptor_settings.name);
+                continue;
+            }
+
+            let factory = self.factories.get(&interceptor_settings.name).ok_or_else(|| {
+                format!(
+                    "Unable to find the {} interceptor. The available interceptors are {:?}",
+                    interceptor_settings.name, self.interceptor_names
+                )
+            })?;
+
+            let service_pattern =
+                ScopedGrpcInterceptor::compile_pattern(&interceptor_settings.service_pattern)
+                    .map_err(|error| {
+                        format!(
+                            "Invalid service pattern for the {} interceptor: {error}",
+                            interceptor_settings.name
+                        )
+                    })?;
+            let method_pattern =
+                ScopedGrpcInterceptor::compile_pattern(&interceptor_settings.method_pattern)
+                    .map_err(|error| {
+                        format!(
+                            "Invalid method pattern for the {} interceptor: {error}",
+                            interceptor_settings.name
+                        )
+                    })?;
+
+            let interceptor =
+                ScopedGrpcInterceptor { interceptor: factory(), service_pattern, method_pattern };
+            layers.push(GrpcInterceptorLayer::new_async(Box::new(interceptor)));
+
+            info!("Applying the {} interceptor.", interceptor_settings.name);
+        }
+
+        Ok(layers)
+    }
+}
+
+#[cfg(test)]
+mod grpc_interceptor_registry_tests {
+    use super::*;
+    use crate::grpc_interceptor::{GrpcInterceptor, SyncGrpcInterceptorAdapter};
+
+    /// An interceptor that is applicable to all calls and leaves them unchanged.
+    #[derive(Clone)]
+    struct PassThroughGrpcInterceptor {}
+
+    impl GrpcInterceptor for PassThroughGrpcInterceptor {
+        fn is_applicable(&self, _service_name: &str, _method_name: &str) -> bool {
+            true
+        }
+
+        fn must_handle_request(&self) -> bool {
+            false
+        }
+
+        fn must_handle_response(&self) -> bool {
+            false
+        }
+
+        fn handle_request(
+            &self,
+            _service_name: &str,
+            _method_name: &str,
+            protobuf_message: Bytes,
+        ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+            Ok(protobuf_message)
+        }
+
+        fn handle_response(
+            &self,
+            _service_name: &str,
+            _method_name: &str,
+            protobuf_message: Bytes,
+        ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+            Ok(protobuf_message)
+        }
+    }
+
+    /// Create a registry with a pass-through interceptor.
+    fn create_registry() -> GrpcInterceptorRegistry {
+        let mut registry = GrpcInterceptorRegistry::new();
+        registry.register_factory("pass_through", || {
+            Box::new(SyncGrpcInterceptorAdapter::new(Box::new(PassThroughGrpcInterceptor {})))
+        });
+        registry
+    }
+
+    #[test]
+    fn create_layers_test() {
+        let registry = create_registry();
+        assert_eq!(registry.interceptor_names(), ["pass_through"]);
+
+        let layers = registry
+            .create_layers(&[
+                GrpcInterceptorSettings::new("pass_through"),
+                GrpcInterceptorSettings {
+                    enabled: Some(false),
+                    ..GrpcInterceptorSettings::new("pass_through")
+                },
+            ])
+            .unwrap();
+        assert_eq!(layers.len(), 1);
+
+        assert!(registry.create_layers(&[GrpcInterceptorSettings::new("unknown")]).is_err());
+
+        let invalid_pattern_settings = GrpcInterceptorSettings {
+            service_pattern: Some(String::from("(")),
+            ..GrpcInterceptorSettings::new("pass_through")
+        };
+        assert!(registry.create_layers(&[invalid_pattern_settings]).is_err());
+    }
+
+    #[test]
+    fn scoped_grpc_interceptor_test() {
+        let interceptor = ScopedGrpcInterceptor {
+            interceptor: Box::new(SyncGrpcInterceptorAdapter::new(Box::new(
+                PassThroughGrpcInterceptor {},
+            ))),
+            service_pattern: ScopedGrpcInterceptor::compile_pattern(&Some(String::from(
+                "InvehicleDigitalTwin",
+            )))
+            .unwrap(),
+            method_pattern: ScopedGrpcInterceptor::compile_pattern(&Some(String::from(
+                "Register|Unregister",
+            )))
+            .unwrap(),
+        };
+
+        assert!(interceptor.is_applicable("InvehicleDigitalTwin", "Register"));
+        assert!(interceptor.is_applicable("InvehicleDigitalTwin", "Unregister"));
+        assert!(!interceptor.is_applicable("InvehicleDigitalTwin", "FindById"));
+        // The patterns must match the whole name.
+        assert!(!interceptor.is_applicable("InvehicleDigitalTwinV2", "Register"));
+        assert!(!interceptor.is_applicable("ManagedSubscribe", "Register"));
+    }
+}
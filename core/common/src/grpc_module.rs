@@ -2,14 +2,77 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+use serde_json::Value;
+use std::error::Error;
 use tonic::transport::server::RoutesBuilder;
 
+use crate::grpc_interceptor::AsyncGrpcInterceptor;
+
+/// The health of a module.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GrpcModuleHealth {
+    /// The module is able to serve requests.
+    Healthy,
+    /// The module is not able to serve requests, for the provided reason.
+    Unhealthy(String),
+}
+
 /// Trait that must be implemented for a module to add one or more grpc services to the hosted
 /// server. A GrpcModule may also implement one or more GrpcInterceptor objects and share state.
-pub trait GrpcModule {
+///
+/// The hosting service calls the module's hooks in this order:
+/// 1. `init` - once, before anything else.
+/// 2. `interceptors`, `add_grpc_services` and `grpc_service_names` - once each, while the server
+///    is built.
+/// 3. `start` - once, before the server starts serving requests.
+/// 4. `health` and `state` - any number of times, while the server is serving requests.
+/// 5. `shutdown` - once, after the server has stopped serving requests.
+#[tonic::async_trait]
+pub trait GrpcModule: Send + Sync {
+    /// Initialize the module, e.g. load its settings. An error stops the hosting service from
+    /// starting.
+    async fn init(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
     /// Function to add necessary services to the server builder.
     ///
     /// # Arguments
     /// * `builder` - A tonic::RoutesBuilder that contains the grpc services to build.
     fn add_grpc_services(&self, builder: &mut RoutesBuilder);
+
+    /// The full names of the grpc services that the module adds, e.g.
+    /// "digital_twin_registry.v1.digital_twin_registry.DigitalTwinRegistry". The health of each of
+    /// them is reported as the module's health.
+    fn grpc_service_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The module's interceptors. They are applied to all of the hosted server's services, in the
+    /// order that they are returned. A GrpcInterceptor can be returned by wrapping it in a
+    /// SyncGrpcInterceptorAdapter.
+    fn interceptors(&self) -> Vec<Box<dyn AsyncGrpcInterceptor>> {
+        Vec::new()
+    }
+
+    /// Start the module's background tasks.
+    async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Stop the module's background tasks and release its resources.
+    async fn shutdown(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Get the module's health.
+    async fn health(&self) -> GrpcModuleHealth {
+        GrpcModuleHealth::Healthy
+    }
+
+    /// Get the module's state as JSON, e.g. the contents of its stores, for debugging. A module
+    /// that has no state to report returns None.
+    async fn state(&self) -> Option<Value> {
+        None
+    }
 }
@@ -0,0 +1,380 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use core::future::Future;
+use log::{info, warn};
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::time::Instant;
+use tower::layer::util::{Identity, Stack};
+
+use crate::grpc_interceptor::{GrpcInterceptorChainLayer, GrpcInterceptorLayer};
+use crate::grpc_module::{GrpcModule, GrpcModuleHealth};
+use crate::grpc_server::{GrpcServer, GrpcServerAddress};
+use crate::grpc_tracing::GrpcTracingLayer;
+use crate::logging::GrpcLogContextLayer;
+use crate::metrics::GrpcMetricsLayer;
+
+/// The settings for one of the modules in a service's `modules` setting.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GrpcModuleSettings {
+    /// The name that the module's factory is registered with.
+    pub name: String,
+    /// Is the module enabled? If it is not provided, then the module is enabled.
+    pub enabled: Option<bool>,
+    /// The name of the module's config file. If it is not provided, then the module's default
+    /// config file is used. It is ignored by modules that do not have a config file.
+    pub config_filename: Option<String>,
+}
+
+impl GrpcModuleSettings {
+    /// Create the settings for an enabled module that uses its default config file.
+    ///
+    /// # Arguments
+    /// * `name` - The name that the module's factory is registered with.
+    pub fn new(name: &str) -> Self {
+        GrpcModuleSettings { name: name.to_string(), enabled: None, config_filename: None }
+    }
+
+    /// Is the module enabled?
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+/// The result of creating a module.
+pub type GrpcModuleResult = Result<Box<dyn GrpcModule>, Box<dyn Error + Send + Sync>>;
+
+/// The future that a module factory returns.
+pub type GrpcModuleFuture = Pin<Box<dyn Future<Output = GrpcModuleResult> + Send>>;
+
+/// A function that creates a module from its settings.
+pub type GrpcModuleFactory = Box<dyn Fn(GrpcModuleSettings) -> GrpcModuleFuture + Send + Sync>;
+
+/// The modules that a server was built with. It drives the modules' lifecycle hooks.
+#[derive(Clone, Default)]
+pub struct LoadedGrpcModules {
+    /// The modules and their names, in the order that they were created.
+    modules: Vec<(String, Arc<dyn GrpcModule>)>,
+}
+
+impl LoadedGrpcModules {
+    /// The names of the modules, in the order that they were created.
+    pub fn module_names(&self) -> Vec<String> {
+        self.modules.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Start the modules, in the order that they were created.
+    pub async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for (name, module) in &self.modules {
+            module
+                .start()
+                .await
+                .map_err(|error| format!("Unable to start the {name} module: {error}"))?;
+
+            info!("Started the {name} module.");
+        }
+
+        Ok(())
+    }
+
+    /// Shut down the modules, in the reverse of the order that they were created. A module that
+    /// fails to shut down does not stop the other modules from shutting down. The modules that
+    /// have not shut down by the deadline are left as they are.
+    ///
+    /// # Arguments
+    /// * `deadline` - When to stop waiting for the modules to shut down.
+    pub async fn shutdown(&self, deadline: Instant) {
+        for (name, module) in self.modules.iter().rev() {
+            match tokio::time::timeout_at(deadline, module.shutdown()).await {
+                Ok(Ok(())) => info!("Shut down the {name} module."),
+                Ok(Err(error)) => warn!("Unable to shut down the {name} module: {error}"),
+                Err(_) => warn!("The {name} module did not shut down in time."),
+            }
+        }
+    }
+
+    /// Get the full names of each module's grpc services, keyed by module name.
+    pub fn grpc_service_names(&self) -> Vec<(String, Vec<String>)> {
+        self.modules
+            .iter()
+            .map(|(name, module)| (name.clone(), module.grpc_service_names()))
+            .collect()
+    }
+
+    /// Get the health of each module, keyed by module name.
+    pub async fn health(&self) -> Vec<(String, GrpcModuleHealth)> {
+        let mut module_health_list = Vec::new();
+        for (name, module) in &self.modules {
+            module_health_list.push((name.clone(), module.health().await));
+        }
+
+        module_health_list
+    }
+
+    /// Get the state of each module that reports its state, keyed by module name.
+    pub async fn state(&self) -> Vec<(String, Value)> {
+        let mut module_state_list = Vec::new();
+        for (name, module) in &self.modules {
+            if let Some(state) = module.state().await {
+                module_state_list.push((name.clone(), state));
+            }
+        }
+
+        module_state_list
+    }
+}
+
+/// A registry of the modules that are compiled into a service. The modules that are actually
+/// hosted are chosen at runtime from the service's settings.
+#[derive(Default)]
+pub struct GrpcModuleRegistry {
+    /// The module factories, keyed by module name.
+    factories: HashMap<String, GrpcModuleFactory>,
+    /// The module names, in the order that their factories were registered.
+    module_names: Vec<String>,
+}
+
+impl GrpcModuleRegistry {
+    /// Creates a new, empty GrpcModuleRegistry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the factory for a module. A factory that is registered with the same name as an
+    /// earlier one replaces it.
+    ///
+    /// # Arguments
+    /// * `name` - The module's name, which is used to refer to it in the settings.
+    /// * `factory` - The function that creates the module from its settings.
+    pub fn register_factory<F, Fut>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(GrpcModuleSettings) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = GrpcModuleResult> + Send + 'static,
+    {
+        let factory: GrpcModuleFactory = Box::new(move |settings| Box::pin(factory(settings)));
+        if self.factories.insert(name.to_string(), factory).is_none() {
+            self.module_names.push(name.to_string());
+        }
+    }
+
+    /// The names of the registered modules, in the order that they were registered.
+    pub fn module_names(&self) -> &[String] {
+        &self.module_names
+    }
+
+    /// Create the settings that enable all of the registered modules with their default config
+    /// files. They are used when a service's settings do not list its modules.
+    pub fn default_module_settings_list(&self) -> Vec<GrpcModuleSettings> {
+        self.module_names.iter().map(|name| GrpcModuleSettings::new(name)).collect()
+    }
+
+    /// Create and initialize the enabled modules and add them, with their interceptors, to a new
+    /// server. Returns the server and the modules, which must be started before the server serves
+    /// requests. The modules are created in the order that they are listed. The interceptors of
+    /// the modules that are listed first are the outermost. The configured interceptors are
+    /// outside of all of the modules' interceptors. The metrics of the server's calls are recorded
+    /// outside of all of the interceptors, so that the calls that they reject are recorded too. The
+    /// fields of the calls are made available to the log lines outside of the metrics, and the calls
+    /// are traced outside of everything else, so that everything that handles them is in the call's
+    /// trace context and logs with its correlation id.
+    ///
+    /// # Arguments
+    /// * `addresses` - The addresses the server will be hosted on.
+    /// * `module_settings_list` - The settings for the modules.
+    /// * `interceptor_layers` - The layers of the configured interceptors, from the outermost to
+    ///                          the innermost.
+    pub async fn build_server(
+        &self,
+        addresses: Vec<GrpcServerAddress>,
+        module_settings_list: &[GrpcModuleSettings],
+        interceptor_layers: Vec<GrpcInterceptorLayer>,
+    ) -> Result<
+        (
+            GrpcServer<
+                Stack<
+                    GrpcInterceptorChainLayer,
+                    Stack<
+                        GrpcMetricsLayer,
+                        Stack<GrpcLogContextLayer, Stack<GrpcTracingLayer, Identity>>,
+                    >,
+                >,
+            >,
+            LoadedGrpcModules,
+        ),
+        Box<dyn Error + Send + Sync>,
+    > {
+        let mut server = GrpcServer::new(addresses);
+        let mut interceptor_layers = interceptor_layers;
+        let mut loaded_modules = LoadedGrpcModules::default();
+
+        for module_settings in module_settings_list {
+            if !module_settings.is_enabled() {
+                info!("The {} module is disabled.", module_settings.name);
+                continue;
+            }
+
+            let factory = self.factories.get(&module_settings.name).ok_or_else(|| {
+                format!(
+                    "Unable to find the {} module. The available modules are {:?}",
+                    module_settings.name, self.module_names
+                )
+            })?;
+
+            let mut module = factory(module_settings.clone()).await.map_err(|error| {
+                format!("Unable to create the {} module: {error}", module_settings.name)
+            })?;
+
+            module.init().await.map_err(|error| {
+                format!("Unable to initialize the {} module: {error}", module_settings.name)
+            })?;
+
+            interceptor_layers
+                .extend(module.interceptors().into_iter().map(GrpcInterceptorLayer::new_async));
+            module.add_grpc_services(&mut server.modules);
+
+            loaded_modules.modules.push((module_settings.name.clone(), Arc::from(module)));
+
+            info!("Initialized the {} module.", module_settings.name);
+        }
+
+        let middleware = server
+            .middleware
+            .clone()
+            .layer(GrpcTracingLayer::new())
+            .layer(GrpcLogContextLayer::new())
+            .layer(GrpcMetricsLayer::new())
+            .layer(GrpcInterceptorChainLayer::new(interceptor_layers));
+
+        Ok((server.with_middleware(middleware), loaded_modules))
+    }
+}
+
+#[cfg(test)]
+mod grpc_module_registry_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tonic::transport::server::RoutesBuilder;
+
+    /// A module that counts how often its lifecycle hooks are called.
+    #[derive(Clone, Default)]
+    struct TestModule {
+        init_count: Arc<AtomicUsize>,
+        start_count: Arc<AtomicUsize>,
+        shutdown_count: Arc<AtomicUsize>,
+    }
+
+    #[tonic::async_trait]
+    impl GrpcModule for TestModule {
+        async fn init(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.init_count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn add_grpc_services(&self, _builder: &mut RoutesBuilder) {}
+
+        async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.start_count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn shutdown(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.shutdown_count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn health(&self) -> GrpcModuleHealth {
+            GrpcModuleHealth::Unhealthy(String::from("test"))
+        }
+
+        async fn state(&self) -> Option<Value> {
+            Some(serde_json::json!({ "start_count": self.start_count.load(Ordering::SeqCst) }))
+        }
+    }
+
+    /// Create a registry with a test module that shares its counters with the returned module.
+    ///
+    /// # Arguments
+    /// * `name` - The test module's name.
+    fn create_registry(name: &str) -> (GrpcModuleRegistry, TestModule) {
+        let test_module = TestModule::default();
+        let factory_test_module = test_module.clone();
+
+        let mut registry = GrpcModuleRegistry::new();
+        registry.register_factory(name, move |_| {
+            let module: Box<dyn GrpcModule> = Box::new(factory_test_module.clone());
+            async move { GrpcModuleResult::Ok(module) }
+        });
+
+        (registry, test_module)
+    }
+
+    #[tokio::test]
+    async fn build_server_test() {
+        let (registry, test_module) = create_registry("test_module");
+        let address = GrpcServerAddress::Tcp("[::1]:0".parse().unwrap());
+
+        assert_eq!(registry.module_names(), ["test_module"]);
+
+        let (_, loaded_modules) = registry
+            .build_server(
+                vec![address.clone()],
+                &registry.default_module_settings_list(),
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(loaded_modules.module_names(), ["test_module"]);
+        assert_eq!(test_module.init_count.load(Ordering::SeqCst), 1);
+
+        let disabled_module_settings =
+            GrpcModuleSettings { enabled: Some(false), ..GrpcModuleSettings::new("test_module") };
+        let (_, loaded_modules) = registry
+            .build_server(vec![address.clone()], &[disabled_module_settings], Vec::new())
+            .await
+            .unwrap();
+        assert!(loaded_modules.module_names().is_empty());
+        assert_eq!(test_module.init_count.load(Ordering::SeqCst), 1);
+
+        let result = registry
+            .build_server(
+                vec![address.clone()],
+                &[GrpcModuleSettings::new("unknown_module")],
+                Vec::new(),
+            )
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn lifecycle_test() {
+        let (registry, test_module) = create_registry("test_module");
+        let address = GrpcServerAddress::Tcp("[::1]:0".parse().unwrap());
+
+        let (_, loaded_modules) = registry
+            .build_server(vec![address], &registry.default_module_settings_list(), Vec::new())
+            .await
+            .unwrap();
+
+        loaded_modules.start().await.unwrap();
+        assert_eq!(test_module.start_count.load(Ordering::SeqCst), 1);
+
+        assert_eq!(
+            loaded_modules.health().await,
+            [(String::from("test_module"), GrpcModuleHealth::Unhealthy(String::from("test")))]
+        );
+
+        assert_eq!(
+            loaded_modules.state().await,
+            [(String::from("test_module"), serde_json::json!({ "start_count": 1 }))]
+        );
+
+        loaded_modules.shutdown(Instant::now() + tokio::time::Duration::from_secs(5)).await;
+        assert_eq!(test_module.shutdown_count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use futures_core::task::{Context, Poll};
+use log::warn;
+use tonic::server::NamedService;
+use tower::Service;
+
+pub use tonic_reflection::server::{ServerReflection, ServerReflectionServer};
+
+/// The full name of the v1 server reflection service.
+pub(crate) const SERVER_REFLECTION_V1_SERVICE_NAME: &str = "grpc.reflection.v1.ServerReflection";
+
+/// The full name of the v1alpha server reflection service.
+pub(crate) const SERVER_REFLECTION_V1ALPHA_SERVICE_NAME: &str =
+    "grpc.reflection.v1alpha.ServerReflection";
+
+/// Create the v1alpha server reflection service, grpc.reflection.v1alpha.ServerReflection. It
+/// describes the services in the file descriptor sets, as well as the health and reflection
+/// services. Wrap it in a ServerReflectionV1Service to also host it as grpc.reflection.v1.
+///
+/// # Arguments
+/// * `file_descriptor_sets` - The encoded file descriptor sets of the hosted services.
+pub fn create_reflection_service(
+    file_descriptor_sets: &[&[u8]],
+) -> Result<ServerReflectionServer<impl ServerReflection>, tonic_reflection::server::Error> {
+    let mut builder = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_reflection::pb::FILE_DESCRIPTOR_SET);
+    for file_descriptor_set in file_descriptor_sets {
+        builder = builder.register_encoded_file_descriptor_set(file_descriptor_set);
+    }
+
+    builder.build()
+}
+
+/// Hosts a v1alpha server reflection service as the v1 server reflection service,
+/// grpc.reflection.v1.ServerReflection. The two versions only differ in their package name, so
+/// the v1 calls are forwarded to the v1alpha service.
+#[derive(Clone)]
+pub struct ServerReflectionV1Service<S> {
+    service: S,
+}
+
+impl<S> ServerReflectionV1Service<S> {
+    /// Creates a new ServerReflectionV1Service.
+    ///
+    /// # Arguments
+    /// * `service` - The v1alpha server reflection service.
+    pub fn new(service: S) -> Self {
+        Self { service }
+    }
+}
+
+impl<S> NamedService for ServerReflectionV1Service<S> {
+    const NAME: &'static str = SERVER_REFLECTION_V1_SERVICE_NAME;
+}
+
+impl<S, B> Service<http::Request<B>> for ServerReflectionV1Service<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        let v1_path_prefix = format!("/{SERVER_REFLECTION_V1_SERVICE_NAME}/");
+        if let Some(method_name) = request.uri().path().strip_prefix(&v1_path_prefix) {
+            let v1alpha_path = format!("/{SERVER_REFLECTION_V1ALPHA_SERVICE_NAME}/{method_name}");
+            match v1alpha_path.parse() {
+                Ok(uri) => *request.uri_mut() = uri,
+                Err(error) => {
+                    warn!("Unable to forward the reflection call to {v1alpha_path}: {error}")
+                }
+            }
+        }
+
+        self.service.call(request)
+    }
+}
+
+#[cfg(test)]
+mod grpc_reflection_tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn server_reflection_v1_service_test() {
+        // A service that responds with the path that it was called with.
+        let service = tower::service_fn(|request: http::Request<()>| async move {
+            Ok::<_, Infallible>(request.uri().path().to_string())
+        });
+        let service = ServerReflectionV1Service::new(service);
+
+        let request = http::Request::builder()
+            .uri("/grpc.reflection.v1.ServerReflection/ServerReflectionInfo")
+            .body(())
+            .unwrap();
+        let path = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(path, "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo");
+
+        let request = http::Request::builder()
+            .uri("/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo")
+            .body(())
+            .unwrap();
+        let path = service.oneshot(request).await.unwrap();
+        assert_eq!(path, "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo");
+    }
+
+    #[test]
+    fn create_reflection_service_test() {
+        assert!(create_reflection_service(&[]).is_ok());
+        assert!(create_reflection_service(&[&[0xff, 0xff]]).is_err());
+    }
+}
@@ -2,58 +2,289 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::net::SocketAddr;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
+use log::warn;
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::wrappers::UnixListenerStream;
+use tokio_stream::Stream;
 use tonic::transport::server::Router;
-use tonic::transport::{server::RoutesBuilder, Server};
+use tonic::transport::{server::RoutesBuilder, Server, ServerTlsConfig};
 use tower::layer::util::{Identity, Stack};
 use tower::ServiceBuilder;
 
-use crate::grpc_module::GrpcModule;
+use crate::grpc_channel::{get_unix_socket_path, UNIX_SCHEME_PREFIX};
+
+/// An address that a server can be hosted on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GrpcServerAddress {
+    /// A TCP socket address, e.g. "0.0.0.0:5010".
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket, e.g. "/run/ibeji/invehicle_digital_twin.sock".
+    Unix(PathBuf),
+}
+
+impl GrpcServerAddress {
+    /// Parse an address. A Unix domain socket is given as a URI, e.g.
+    /// "unix:///run/ibeji/invehicle_digital_twin.sock", and a TCP socket address as an authority,
+    /// e.g. "0.0.0.0:5010".
+    ///
+    /// # Arguments
+    /// * `address` - The address.
+    pub fn parse(address: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if address.starts_with(UNIX_SCHEME_PREFIX) {
+            let path = get_unix_socket_path(address)
+                .ok_or_else(|| format!("The Unix domain socket URI '{address}' has no path"))?;
+            return Ok(GrpcServerAddress::Unix(PathBuf::from(path)));
+        }
+
+        let socket_address = address
+            .parse()
+            .map_err(|error| format!("Unable to parse the address '{address}': {error}"))?;
+        Ok(GrpcServerAddress::Tcp(socket_address))
+    }
+
+    /// Is the address only reachable from the local machine? That is a loopback TCP address or a
+    /// Unix domain socket.
+    pub fn is_local(&self) -> bool {
+        match self {
+            GrpcServerAddress::Tcp(socket_address) => socket_address.ip().is_loopback(),
+            GrpcServerAddress::Unix(_) => true,
+        }
+    }
+}
+
+impl fmt::Display for GrpcServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrpcServerAddress::Tcp(socket_address) => write!(f, "{socket_address}"),
+            GrpcServerAddress::Unix(path) => write!(f, "{UNIX_SCHEME_PREFIX}{}", path.display()),
+        }
+    }
+}
+
+/// The connections to a Unix domain socket that a server's router is served on. The socket's file
+/// is removed when it is dropped, i.e. once the server has stopped serving it.
+#[derive(Debug)]
+pub struct UnixSocketIncoming {
+    /// The connections to the socket.
+    inner: UnixListenerStream,
+    /// The socket's path.
+    path: PathBuf,
+}
+
+impl Stream for UnixSocketIncoming {
+    type Item = Result<UnixStream, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl Drop for UnixSocketIncoming {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            warn!("Unable to remove the Unix domain socket '{}': {error}", self.path.display());
+        }
+    }
+}
+
+/// Bind a Unix domain socket to serve a server's router on, with `Router::serve_with_incoming`.
+/// A socket that was left behind at the path, e.g. by a process that did not shut down cleanly, is
+/// removed first, but a socket that another process still serves is an error. Access to the socket
+/// is controlled by the file system permissions of the path, which are set to the mode, when it is
+/// provided. The socket is bound in a private directory next to the path, given its mode and only
+/// then moved to the path, so that it is never reachable with the mode that the umask allows. The
+/// socket is removed once it is no longer served.
+///
+/// # Arguments
+/// * `path` - The socket's path.
+/// * `mode` - The socket's file mode, e.g. 0o660. If it is not provided, then the mode that the
+///            process's umask allows is kept.
+pub fn bind_unix_socket(path: &Path, mode: Option<u32>) -> Result<UnixSocketIncoming, io::Error> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("The Unix domain socket '{}' is in use", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+    }
+
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The Unix domain socket path '{}' has no file name", path.display()),
+        )
+    })?;
+    let bind_directory = path.with_file_name(format!(
+        ".{}.{}.bind",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&bind_directory);
+    fs::DirBuilder::new().mode(0o700).create(&bind_directory)?;
+
+    let bind_path = bind_directory.join(file_name);
+    let result = UnixListener::bind(&bind_path).and_then(|listener| {
+        if let Some(mode) = mode {
+            fs::set_permissions(&bind_path, fs::Permissions::from_mode(mode))?;
+        }
+        fs::rename(&bind_path, path)?;
+        Ok(listener)
+    });
+    if let Err(error) = fs::remove_dir_all(&bind_directory) {
+        warn!("Unable to remove the directory '{}': {error}", bind_directory.display());
+    }
+
+    Ok(UnixSocketIncoming { inner: UnixListenerStream::new(result?), path: path.to_path_buf() })
+}
 
 /// Grpc Server struct that builds multiple services and layers.
 pub struct GrpcServer<L> {
-    address: SocketAddr,
+    /// The addresses the server will be hosted on.
+    addresses: Vec<GrpcServerAddress>,
     pub modules: RoutesBuilder,
     pub middleware: ServiceBuilder<L>,
+    /// The server's TLS config. If it is not provided, then the server does not use TLS.
+    pub tls_config: Option<ServerTlsConfig>,
 }
 
 impl GrpcServer<Identity> {
     /// Creates a new GrpcServer
     ///
     /// # Arguments
-    /// * `address` - The address the server will be hosted on.
-    pub fn new(address: SocketAddr) -> Self {
-        GrpcServer { address, modules: RoutesBuilder::default(), middleware: ServiceBuilder::new() }
+    /// * `addresses` - The addresses the server will be hosted on.
+    pub fn new(addresses: Vec<GrpcServerAddress>) -> Self {
+        GrpcServer {
+            addresses,
+            modules: RoutesBuilder::default(),
+            middleware: ServiceBuilder::new(),
+            tls_config: None,
+        }
     }
 }
 
 impl<L> GrpcServer<L> {
-    /// Adds a module (collection of grpc services and grpc interceptors) for the server to host.
-    /// Returns a newly decorated GrpcServer with the added module.
+    /// The addresses the server will be hosted on. Each of them is served by its own router from
+    /// `construct_server`.
+    pub fn addresses(&self) -> &[GrpcServerAddress] {
+        &self.addresses
+    }
+
+    /// Replaces the server's middleware, e.g. to add interceptors that apply to all of its modules.
+    /// Returns a newly decorated GrpcServer with the new middleware.
     ///
     /// # Arguments
-    /// * `middleware` - The middleware from the current server + any interceptors for the module
-    ///                  added with `.layer()`.
-    /// * `module` - Boxed GrpcModule to be added to the server.
-    pub fn add_module<S>(
-        &mut self,
-        middleware: ServiceBuilder<S>,
-        module: Box<dyn GrpcModule>,
-    ) -> GrpcServer<S> {
-        module.add_grpc_services(&mut self.modules);
-
-        GrpcServer { address: self.address, modules: self.modules.clone(), middleware }
+    /// * `middleware` - The middleware from the current server + any interceptors added with
+    ///                  `.layer()`.
+    pub fn with_middleware<S>(&self, middleware: ServiceBuilder<S>) -> GrpcServer<S> {
+        GrpcServer {
+            addresses: self.addresses.clone(),
+            modules: self.modules.clone(),
+            middleware,
+            tls_config: self.tls_config.clone(),
+        }
     }
 
     /// Constructs the added modules and layers into a server to host.
-    pub fn construct_server(&self) -> Router<Stack<L, Identity>>
+    /// Returns an error if the server's TLS config is not valid.
+    pub fn construct_server(&self) -> Result<Router<Stack<L, Identity>>, tonic::transport::Error>
     where
         L: Clone,
     {
+        let mut builder = Server::builder();
+        if let Some(tls_config) = &self.tls_config {
+            builder = builder.tls_config(tls_config.clone())?;
+        }
+
         // Construct the server.
-        Server::builder()
+        Ok(builder
             .layer(self.middleware.clone().into_inner())
-            .add_routes(self.modules.clone().routes())
+            .add_routes(self.modules.clone().routes()))
+    }
+}
+
+#[cfg(test)]
+mod grpc_server_tests {
+    use super::*;
+
+    #[test]
+    fn grpc_server_address_test() {
+        let address = GrpcServerAddress::parse("0.0.0.0:5010").unwrap();
+        assert_eq!(address, GrpcServerAddress::Tcp("0.0.0.0:5010".parse().unwrap()));
+        assert_eq!(address.to_string(), "0.0.0.0:5010");
+
+        let address =
+            GrpcServerAddress::parse("unix:///run/ibeji/invehicle_digital_twin.sock").unwrap();
+        assert_eq!(
+            address,
+            GrpcServerAddress::Unix(PathBuf::from("/run/ibeji/invehicle_digital_twin.sock"))
+        );
+        assert_eq!(address.to_string(), "unix:///run/ibeji/invehicle_digital_twin.sock");
+
+        assert!(GrpcServerAddress::parse("unix://").is_err());
+        assert!(GrpcServerAddress::parse("localhost").is_err());
+    }
+
+    #[test]
+    fn is_local_test() {
+        assert!(GrpcServerAddress::parse("127.0.0.1:5011").unwrap().is_local());
+        assert!(GrpcServerAddress::parse("[::1]:5011").unwrap().is_local());
+        assert!(GrpcServerAddress::parse("unix:///run/ibeji/invehicle_digital_twin_admin.sock")
+            .unwrap()
+            .is_local());
+        assert!(!GrpcServerAddress::parse("0.0.0.0:5011").unwrap().is_local());
+        assert!(!GrpcServerAddress::parse("192.168.1.10:5011").unwrap().is_local());
+    }
+
+    #[tokio::test]
+    async fn bind_unix_socket_test() {
+        let path =
+            std::env::temp_dir().join(format!("grpc_server_test_{}.sock", std::process::id()));
+
+        let incoming = bind_unix_socket(&path, Some(0o660)).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o660);
+
+        // The socket is removed once it is no longer served.
+        drop(incoming);
+        assert!(!path.exists());
+
+        // A socket that was left behind, e.g. by a process that was killed, is replaced.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let incoming = bind_unix_socket(&path, None).unwrap();
+        drop(incoming);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn bind_unix_socket_in_use_test() {
+        let path = std::env::temp_dir()
+            .join(format!("grpc_server_in_use_test_{}.sock", std::process::id()));
+
+        // The socket is served at its path once it has been moved there.
+        let incoming = bind_unix_socket(&path, Some(0o600)).unwrap();
+        assert!(UnixStream::connect(&path).await.is_ok());
+
+        // A socket that is still served is not replaced.
+        let error = bind_unix_socket(&path, Some(0o600)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(UnixStream::connect(&path).await.is_ok());
+
+        drop(incoming);
+        assert!(!path.exists());
     }
 }
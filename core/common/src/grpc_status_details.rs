@@ -0,0 +1,102 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use bytes::Bytes;
+use prost::Message;
+use prost_types::Any;
+use tonic::{Code, Status};
+
+// The details of a gRPC status follow the standard google.rpc.Status convention, so that any gRPC
+// client can decode them: the details are an encoded google.rpc.Status, whose details field holds
+// typed messages, each packed in a google.protobuf.Any.
+
+/// The prefix of the type url of a packed message.
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// The google.rpc.Status message, which is carried in a gRPC status's details.
+#[derive(Clone, PartialEq, Message)]
+struct RpcStatus {
+    /// The status code.
+    #[prost(int32, tag = "1")]
+    code: i32,
+    /// The error message.
+    #[prost(string, tag = "2")]
+    message: String,
+    /// The typed details.
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<Any>,
+}
+
+/// Create a status whose details carry a typed message.
+///
+/// # Arguments
+/// * `code` - The status code.
+/// * `message` - The error message.
+/// * `type_name` - The fully qualified protobuf name of the message's type,
+///                 e.g. "invehicle_digital_twin.RegisterResponse".
+/// * `details` - The message.
+pub fn status_with_typed_details<M: Message>(
+    code: Code,
+    message: String,
+    type_name: &str,
+    details: &M,
+) -> Status {
+    let rpc_status = RpcStatus {
+        code: code as i32,
+        message: message.clone(),
+        details: vec![Any {
+            type_url: format!("{TYPE_URL_PREFIX}{type_name}"),
+            value: details.encode_to_vec(),
+        }],
+    };
+
+    Status::with_details(code, message, Bytes::from(rpc_status.encode_to_vec()))
+}
+
+/// Get the typed message that a status's details carry. Returns None when the details do not
+/// carry a message of this type.
+///
+/// # Arguments
+/// * `status` - The status.
+/// * `type_name` - The fully qualified protobuf name of the message's type.
+pub fn get_typed_details<M: Message + Default>(status: &Status, type_name: &str) -> Option<M> {
+    let rpc_status = RpcStatus::decode(status.details()).ok()?;
+    let type_url = format!("{TYPE_URL_PREFIX}{type_name}");
+
+    rpc_status
+        .details
+        .iter()
+        .find(|any| any.type_url == type_url)
+        .and_then(|any| M::decode(any.value.as_slice()).ok())
+}
+
+#[cfg(test)]
+mod grpc_status_details_tests {
+    use super::*;
+
+    #[test]
+    fn typed_details_test() {
+        let details = Any { type_url: String::from("test"), value: vec![1, 2, 3] };
+
+        let status = status_with_typed_details(
+            Code::InvalidArgument,
+            String::from("Instance id is required"),
+            "google.protobuf.Any",
+            &details,
+        );
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "Instance id is required");
+
+        let rpc_status = RpcStatus::decode(status.details()).unwrap();
+        assert_eq!(rpc_status.code, Code::InvalidArgument as i32);
+        assert_eq!(rpc_status.message, "Instance id is required");
+
+        assert_eq!(get_typed_details::<Any>(&status, "google.protobuf.Any"), Some(details));
+        assert_eq!(get_typed_details::<Any>(&status, "google.protobuf.Empty"), None);
+        assert_eq!(
+            get_typed_details::<Any>(&Status::internal("error"), "google.protobuf.Any"),
+            None
+        );
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use core::future::Future;
+use futures_core::task::{Context, Poll};
+use http::header::{HeaderName, HeaderValue};
+use http::HeaderMap;
+use log::{info, warn};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{FutureExt, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::pin::Pin;
+use tonic::body::BoxBody;
+use tonic::transport::Channel;
+use tonic::Code;
+use tower::{Layer, Service};
+
+use crate::grpc_call_status::{observe_response, GrpcCallStatusObserver};
+use crate::logging;
+
+// This module traces the gRPC calls that the servers handle and that the clients make. The trace
+// context is carried in the calls' metadata, in the W3C Trace Context format (the traceparent and
+// tracestate headers), so that a trace follows a request across the services that it reaches.
+
+/// The name of the tracer that the calls' spans are created with.
+pub const TRACER_NAME: &str = "ibeji";
+
+/// The OTLP endpoint that is used when the tracing settings do not provide one.
+const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4317"; // Devskim: ignore DS137138
+
+/// Where the traces are exported to.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum TraceExporter {
+    /// An OpenTelemetry collector's OTLP/gRPC endpoint.
+    Otlp,
+    /// A local file, with the spans in JSON.
+    JsonFile,
+}
+
+/// The tracing settings of a service.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TracingSettings {
+    /// Where the traces are exported to.
+    pub exporter: TraceExporter,
+    /// The OTLP/gRPC endpoint that the traces are exported to by the Otlp exporter. If it is not
+    /// provided, then "http://localhost:4317" is used.
+    pub otlp_endpoint: Option<String>,
+    /// The path of the file that the JsonFile exporter appends the spans to. It is required for the
+    /// JsonFile exporter.
+    pub json_file_path: Option<String>,
+}
+
+/// Set up the process's tracing, so that the spans of its gRPC calls are exported and the trace
+/// context is propagated in the calls' metadata. Until it is set up, the calls are not traced, but
+/// the trace context that they carry is still passed on. It must be called in a tokio runtime.
+///
+/// # Arguments
+/// * `service_name` - The name of the service that the spans are reported for.
+/// * `settings` - The tracing settings.
+pub fn init_tracing(
+    service_name: &str,
+    settings: &TracingSettings,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let trace_config =
+        opentelemetry_sdk::trace::config().with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]));
+
+    match settings.exporter {
+        TraceExporter::Otlp => {
+            let otlp_endpoint =
+                settings.otlp_endpoint.as_deref().unwrap_or(DEFAULT_OTLP_ENDPOINT).to_string();
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter().tonic().with_endpoint(&otlp_endpoint),
+                )
+                .with_trace_config(trace_config)
+                .install_batch(runtime::Tokio)?;
+            info!("The traces are exported to the OTLP endpoint '{otlp_endpoint}'.");
+        }
+        TraceExporter::JsonFile => {
+            let json_file_path = settings.json_file_path.as_ref().ok_or(
+                "The json_file_path setting is required to export the traces to a JSON file",
+            )?;
+            let json_file =
+                OpenOptions::new().create(true).append(true).open(json_file_path).map_err(
+                    |error| format!("Unable to open the trace file '{json_file_path}': {error}"),
+                )?;
+            let exporter =
+                opentelemetry_stdout::SpanExporter::builder().with_writer(json_file).build();
+            let tracer_provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_config(trace_config)
+                .build();
+            global::set_tracer_provider(tracer_provider);
+            info!("The traces are exported to the JSON file '{json_file_path}'.");
+        }
+    }
+
+    Ok(())
+}
+
+/// Export the spans that have not been exported yet and stop exporting spans. It is called when
+/// the process shuts down.
+pub async fn shutdown_tracing() {
+    // Shutting down the tracer provider blocks until the spans have been exported.
+    if let Err(error) = tokio::task::spawn_blocking(global::shutdown_tracer_provider).await {
+        warn!("Unable to shut down the tracing: {error}");
+    }
+}
+
+/// Injects the trace context into the headers of a call's metadata.
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value))
+        {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Extracts the trace context from the headers of a call's metadata.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+/// Inject a trace context into the headers of a call's metadata.
+///
+/// # Arguments
+/// * `context` - The trace context.
+/// * `headers` - The headers.
+pub fn inject_trace_context(context: &opentelemetry::Context, headers: &mut HeaderMap) {
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(context, &mut HeaderInjector(headers))
+    });
+}
+
+/// Extract the trace context from the headers of a call's metadata. The context has no span when
+/// the headers do not carry a trace context.
+///
+/// # Arguments
+/// * `headers` - The headers.
+pub fn extract_trace_context(headers: &HeaderMap) -> opentelemetry::Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+/// Start the span of a gRPC call. Returns the trace context with the span.
+///
+/// # Arguments
+/// * `path` - The gRPC call's path, e.g. "/invehicle_digital_twin.InvehicleDigitalTwin/FindById".
+/// * `kind` - Is the span the server's or the client's?
+/// * `parent_context` - The trace context that the span is a child of.
+fn start_call_span(
+    path: &str,
+    kind: SpanKind,
+    parent_context: &opentelemetry::Context,
+) -> opentelemetry::Context {
+    let name = path.trim_start_matches('/');
+    let (service_name, method_name) = name.split_once('/').unwrap_or_default();
+
+    let tracer = global::tracer(TRACER_NAME);
+    let span = tracer
+        .span_builder(name.to_string())
+        .with_kind(kind)
+        .with_attributes(vec![
+            KeyValue::new("rpc.system", "grpc"),
+            KeyValue::new("rpc.service", service_name.to_string()),
+            KeyValue::new("rpc.method", method_name.to_string()),
+        ])
+        .start_with_context(&tracer, parent_context);
+
+    parent_context.with_span(span)
+}
+
+/// Ends the span of a gRPC call once it has completed, when it is dropped, with the call's status
+/// code. A call whose status code was not seen, e.g. because it was cancelled, ends as Unknown.
+struct GrpcCallSpan {
+    context: opentelemetry::Context,
+    code: Option<Code>,
+}
+
+impl GrpcCallStatusObserver for GrpcCallSpan {
+    fn observe_code(&mut self, code: Code) {
+        self.code = Some(code);
+    }
+}
+
+impl Drop for GrpcCallSpan {
+    fn drop(&mut self) {
+        let span = self.context.span();
+        let code = self.code.unwrap_or(Code::Unknown);
+        span.set_attribute(KeyValue::new("rpc.grpc.status_code", i64::from(code as i32)));
+        if code != Code::Ok {
+            span.set_status(Status::error(format!("{code:?}")));
+        }
+        span.end();
+    }
+}
+
+/// The tower layer that traces a server's gRPC calls. Each call's span is a child of the trace
+/// context in the call's metadata, and it is the current span while the call is handled, so that
+/// the calls that the handler makes are its children.
+#[derive(Clone, Default)]
+pub struct GrpcTracingLayer {}
+
+impl GrpcTracingLayer {
+    /// Create the tower layer that traces a server's gRPC calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for GrpcTracingLayer {
+    type Service = GrpcTracingService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        GrpcTracingService { service }
+    }
+}
+
+/// The tower service that traces a server's gRPC calls.
+#[derive(Clone)]
+pub struct GrpcTracingService<S> {
+    service: S,
+}
+
+impl<S> Service<http::request::Request<tonic::transport::Body>> for GrpcTracingService<S>
+where
+    S: Service<
+        http::request::Request<tonic::transport::Body>,
+        Response = http::response::Response<BoxBody>,
+    >,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    /// Implementation of tower's Service trait's poll_ready method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    /// Implementation of tower's Service trait's call method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn call(&mut self, request: http::request::Request<tonic::transport::Body>) -> Self::Future {
+        let parent_context = extract_trace_context(request.headers());
+        let context = start_call_span(request.uri().path(), SpanKind::Server, &parent_context);
+
+        // This block controls the lifetime of the context's guard.
+        let response_future = {
+            let _guard = context.clone().attach();
+            self.service.call(request)
+        };
+
+        let call_span = GrpcCallSpan { context: context.clone(), code: None };
+        Box::pin(
+            async move { Ok(observe_response(response_future.await?, call_span)) }
+                .with_context(context),
+        )
+    }
+}
+
+/// A channel that traces the gRPC calls that are made on it. Each call's span is a child of the
+/// current trace context, and its trace context is carried in the call's metadata, along with the
+/// correlation id of the gRPC call that is being handled, if there is one.
+#[derive(Clone, Debug)]
+pub struct TracedChannel {
+    channel: Channel,
+}
+
+impl TracedChannel {
+    /// Create a channel that traces the gRPC calls that are made on another channel.
+    ///
+    /// # Arguments
+    /// * `channel` - The channel.
+    pub fn new(channel: Channel) -> Self {
+        Self { channel }
+    }
+}
+
+impl Service<http::Request<BoxBody>> for TracedChannel {
+    type Response = http::Response<BoxBody>;
+    type Error = tonic::transport::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    /// Implementation of tower's Service trait's poll_ready method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.channel.poll_ready(cx)
+    }
+
+    /// Implementation of tower's Service trait's call method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn call(&mut self, mut request: http::Request<BoxBody>) -> Self::Future {
+        let context = start_call_span(
+            request.uri().path(),
+            SpanKind::Client,
+            &opentelemetry::Context::current(),
+        );
+        inject_trace_context(&context, request.headers_mut());
+        logging::inject_correlation_id(request.headers_mut());
+
+        let call_span = GrpcCallSpan { context, code: None };
+        let response_future = self.channel.call(request);
+
+        Box::pin(async move { Ok(observe_response(response_future.await?, call_span)) })
+    }
+}
+
+#[cfg(test)]
+mod grpc_tracing_tests {
+    use super::*;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn inject_and_extract_trace_context_test() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let span_context = SpanContext::new(
+            TraceId::from_hex(TRACE_ID).unwrap(),
+            SpanId::from_hex(SPAN_ID).unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let context = opentelemetry::Context::new().with_remote_span_context(span_context);
+
+        let mut headers = HeaderMap::new();
+        inject_trace_context(&context, &mut headers);
+        assert_eq!(headers.get("traceparent").unwrap(), TRACEPARENT);
+
+        let extracted_context = extract_trace_context(&headers);
+        let extracted_span = extracted_context.span();
+        assert_eq!(extracted_span.span_context().trace_id().to_string(), TRACE_ID);
+        assert_eq!(extracted_span.span_context().span_id().to_string(), SPAN_ID);
+
+        let extracted_context = extract_trace_context(&HeaderMap::new());
+        assert!(!extracted_context.span().span_context().is_valid());
+    }
+
+    #[tokio::test]
+    async fn grpc_tracing_service_test() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        // A service that responds with the trace id of the current trace context.
+        let service = tower::service_fn(
+            |_request: http::request::Request<tonic::transport::Body>| async move {
+                let trace_id =
+                    opentelemetry::Context::current().span().span_context().trace_id().to_string();
+                let mut response = tonic::Status::ok("").to_http();
+                response
+                    .headers_mut()
+                    .insert("trace-id", HeaderValue::from_str(&trace_id).unwrap());
+                Ok::<_, Infallible>(response)
+            },
+        );
+        let service = GrpcTracingLayer::new().layer(service);
+
+        let request = http::Request::builder()
+            .uri("/test.TestService/Test")
+            .header("traceparent", TRACEPARENT)
+            .body(tonic::transport::Body::empty())
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+
+        // The call is handled in the trace that the call's metadata carries.
+        assert_eq!(response.headers().get("trace-id").unwrap(), TRACE_ID);
+    }
+}
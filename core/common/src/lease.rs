@@ -0,0 +1,46 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use tokio::time::{Duration, Instant};
+
+/// A lease on a registration. The registration should be evicted once its lease has expired,
+/// unless the lease was renewed before then.
+#[derive(Clone, Copy, Debug)]
+pub struct Lease {
+    /// The lease's time-to-live. Each renewal extends the lease by this duration.
+    pub ttl: Duration,
+    /// The instant at which the lease expires.
+    pub expires_at: Instant,
+}
+
+impl Lease {
+    /// Creates a new lease that expires after the provided time-to-live.
+    ///
+    /// # Arguments
+    /// * `ttl` - The lease's time-to-live.
+    pub fn new(ttl: Duration) -> Self {
+        Lease { ttl, expires_at: Instant::now() + ttl }
+    }
+
+    /// Renews the lease, so that it expires one time-to-live from now.
+    pub fn renew(&mut self) {
+        self.expires_at = Instant::now() + self.ttl;
+    }
+
+    /// Has the lease expired?
+    ///
+    /// # Arguments
+    /// * `now` - The instant to compare the lease's expiry with.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+
+    /// How long until the lease expires. It is zero once the lease has expired.
+    ///
+    /// # Arguments
+    /// * `now` - The instant to measure the lease's remaining time-to-live from.
+    pub fn remaining_ttl(&self, now: Instant) -> Duration {
+        self.expires_at.saturating_duration_since(now)
+    }
+}
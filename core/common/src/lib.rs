@@ -2,8 +2,24 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+pub mod auth_grpc_interceptor;
+pub mod dtdl_model_catalog;
+pub mod dtmi;
+pub mod entity_events;
+mod grpc_call_status;
+pub mod grpc_channel;
+pub mod grpc_frame;
+pub mod grpc_health;
 pub mod grpc_interceptor;
+pub mod grpc_interceptor_registry;
 pub mod grpc_module;
+pub mod grpc_module_registry;
+pub mod grpc_reflection;
 pub mod grpc_server;
+pub mod grpc_status_details;
+pub mod grpc_tracing;
+pub mod lease;
+pub mod logging;
+pub mod metrics;
 pub mod sample_grpc_interceptor;
 pub mod utils;
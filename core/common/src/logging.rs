@@ -0,0 +1,491 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use core::future::Future;
+use env_logger::fmt::Formatter;
+use env_logger::{Builder, Logger, Target};
+use futures_core::task::{Context, Poll};
+use http::header::HeaderValue;
+use http::HeaderMap;
+use log::{LevelFilter, Log, Metadata, Record};
+use parking_lot::RwLock;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::error::Error;
+use std::io::Write;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+// This module sets up the process's logging. The log lines can be written as JSON objects, which
+// carry the fields of the gRPC call that they were logged for, so that the lines of concurrent
+// calls can be told apart.
+
+/// The metadata key that carries a gRPC call's correlation id.
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+/// The longest correlation id that is taken from a call's metadata. A call with a longer one is
+/// given a new correlation id.
+const MAX_CORRELATION_ID_LENGTH: usize = 128;
+
+/// The format of the log lines.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Plain text lines.
+    #[default]
+    Text,
+    /// A JSON object per line.
+    Json,
+}
+
+/// The log level of a module path.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModuleLogLevel {
+    /// The module path, e.g. "common::grpc_interceptor". It applies to its submodules too.
+    pub module: String,
+    /// The log level, e.g. "debug".
+    pub level: String,
+}
+
+/// The logging settings of a service.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LoggingSettings {
+    /// The format of the log lines. If it is not provided, then the lines are plain text.
+    pub format: Option<LogFormat>,
+    /// The log levels of module paths, which override the service's log level for them.
+    pub module_levels: Option<Vec<ModuleLogLevel>>,
+}
+
+/// The fields of the gRPC call that is being handled, which the JSON log lines carry.
+#[derive(Clone, Debug, Default)]
+struct LogContext {
+    correlation_id: String,
+    service_name: String,
+    method_name: String,
+    entity_id: RefCell<Option<String>>,
+    instance_id: RefCell<Option<String>>,
+}
+
+tokio::task_local! {
+    static LOG_CONTEXT: LogContext;
+}
+
+/// Get the correlation id of the gRPC call that is being handled, if there is one.
+pub fn correlation_id() -> Option<String> {
+    LOG_CONTEXT.try_with(|log_context| log_context.correlation_id.clone()).ok()
+}
+
+/// Set the id of the entity that the gRPC call that is being handled refers to, so that the log
+/// lines that follow carry it. It does nothing outside of a gRPC call.
+///
+/// # Arguments
+/// * `entity_id` - The entity id.
+pub fn set_entity_id(entity_id: &str) {
+    let _ = LOG_CONTEXT.try_with(|log_context| {
+        *log_context.entity_id.borrow_mut() = Some(entity_id.to_string());
+    });
+}
+
+/// Set the id of the instance that the gRPC call that is being handled refers to, so that the log
+/// lines that follow carry it. It does nothing outside of a gRPC call.
+///
+/// # Arguments
+/// * `instance_id` - The instance id.
+pub fn set_instance_id(instance_id: &str) {
+    let _ = LOG_CONTEXT.try_with(|log_context| {
+        *log_context.instance_id.borrow_mut() = Some(instance_id.to_string());
+    });
+}
+
+/// Add the correlation id of the gRPC call that is being handled to an outgoing call's metadata,
+/// so that the call is correlated with the call that made it.
+///
+/// # Arguments
+/// * `headers` - The outgoing call's headers.
+pub fn inject_correlation_id(headers: &mut HeaderMap) {
+    if let Some(value) = correlation_id().and_then(|id| HeaderValue::from_str(&id).ok()) {
+        headers.insert(CORRELATION_ID_HEADER, value);
+    }
+}
+
+/// The process's logger. Its filter can be changed while the process is running.
+struct ReloadableLogger {
+    /// The format of the log lines.
+    format: LogFormat,
+    /// The logger that applies the current filter, and the filter.
+    logger: RwLock<(Logger, String)>,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.logger.read().0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.logger.read().0.log(record)
+    }
+
+    fn flush(&self) {
+        self.logger.read().0.flush()
+    }
+}
+
+static LOGGER: OnceLock<ReloadableLogger> = OnceLock::new();
+
+/// Parse a log filter: a comma separated list of log levels, each of which is either the default
+/// level, e.g. "info", or the level of a module path and its submodules, e.g.
+/// "digital_twin_graph=debug". Returns the levels, with the module paths that they apply to.
+///
+/// # Arguments
+/// * `filter` - The log filter.
+fn parse_log_filter(
+    filter: &str,
+) -> Result<Vec<(Option<String>, LevelFilter)>, Box<dyn Error + Send + Sync>> {
+    let mut directives = Vec::new();
+    for directive in filter.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+        let (module, level) = match directive.split_once('=') {
+            Some((module, level)) => (Some(module.trim().to_string()), level.trim()),
+            None => (None, directive),
+        };
+        let level = LevelFilter::from_str(level).map_err(|error| {
+            format!("Invalid log level '{level}' in the log filter '{filter}': {error}")
+        })?;
+        directives.push((module, level));
+    }
+
+    if directives.is_empty() {
+        return Err(format!("The log filter '{filter}' has no log levels").into());
+    }
+
+    Ok(directives)
+}
+
+/// Create a logger that writes the log lines to stdout.
+///
+/// # Arguments
+/// * `format` - The format of the log lines.
+/// * `filter` - The log filter, see `parse_log_filter`.
+fn create_logger(format: LogFormat, filter: &str) -> Result<Logger, Box<dyn Error + Send + Sync>> {
+    let mut builder = Builder::new();
+    builder.target(Target::Stdout);
+    for (module, level) in parse_log_filter(filter)? {
+        builder.filter(module.as_deref(), level);
+    }
+
+    if format == LogFormat::Json {
+        builder.format(format_json_log_line);
+    }
+
+    Ok(builder.build())
+}
+
+/// Set up the process's logging. The log lines are written to stdout.
+///
+/// # Arguments
+/// * `log_level` - The log level of the modules whose level is not in the settings.
+/// * `settings` - The logging settings, if they were provided.
+pub fn init_logging(
+    log_level: LevelFilter,
+    settings: Option<&LoggingSettings>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut filter = log_level.to_string().to_lowercase();
+    let mut format = LogFormat::default();
+
+    if let Some(settings) = settings {
+        for module_log_level in settings.module_levels.iter().flatten() {
+            filter.push_str(&format!(",{}={}", module_log_level.module, module_log_level.level));
+        }
+
+        format = settings.format.unwrap_or_default();
+    }
+
+    let logger = create_logger(format, &filter)?;
+    let max_level = logger.filter();
+    LOGGER
+        .set(ReloadableLogger { format, logger: RwLock::new((logger, filter)) })
+        .map_err(|_| "The logging has already been set up")?;
+    log::set_logger(LOGGER.get().expect("The logger has not been set"))?;
+    log::set_max_level(max_level);
+
+    Ok(())
+}
+
+/// Get the process's current log filter, see `set_log_filter`. Returns None if the logging has
+/// not been set up.
+pub fn log_filter() -> Option<String> {
+    LOGGER.get().map(|logger| logger.logger.read().1.clone())
+}
+
+/// Change the process's log filter while it is running.
+///
+/// # Arguments
+/// * `filter` - The log filter: a comma separated list of log levels, each of which is either the
+///              default level, e.g. "info", or the level of a module path and its submodules,
+///              e.g. "digital_twin_graph=debug".
+pub fn set_log_filter(filter: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let reloadable_logger = LOGGER.get().ok_or("The logging has not been set up")?;
+    let logger = create_logger(reloadable_logger.format, filter)?;
+    let max_level = logger.filter();
+
+    // This block controls the lifetime of the lock.
+    {
+        *reloadable_logger.logger.write() = (logger, filter.to_string());
+    }
+
+    log::set_max_level(max_level);
+
+    Ok(())
+}
+
+/// Create the JSON object of a log line. It has the fields of the gRPC call that is being
+/// handled, if there is one.
+///
+/// # Arguments
+/// * `timestamp` - The log line's timestamp.
+/// * `record` - The log record.
+fn create_json_log_entry(timestamp: &str, record: &Record) -> Value {
+    let mut entry = json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str(),
+        "module": record.module_path().unwrap_or_else(|| record.target()),
+        "message": record.args().to_string(),
+    });
+
+    let _ = LOG_CONTEXT.try_with(|log_context| {
+        entry["correlation_id"] = json!(log_context.correlation_id);
+        entry["service"] = json!(log_context.service_name);
+        entry["method"] = json!(log_context.method_name);
+        if let Some(entity_id) = log_context.entity_id.borrow().as_ref() {
+            entry["entity_id"] = json!(entity_id);
+        }
+        if let Some(instance_id) = log_context.instance_id.borrow().as_ref() {
+            entry["instance_id"] = json!(instance_id);
+        }
+    });
+
+    entry
+}
+
+/// Write a log line as a JSON object.
+///
+/// # Arguments
+/// * `formatter` - The log line's formatter.
+/// * `record` - The log record.
+fn format_json_log_line(formatter: &mut Formatter, record: &Record) -> std::io::Result<()> {
+    let timestamp = formatter.timestamp().to_string();
+    writeln!(formatter, "{}", create_json_log_entry(&timestamp, record))
+}
+
+/// Get a gRPC call's correlation id from its metadata, or create one if it does not carry a valid
+/// one.
+///
+/// # Arguments
+/// * `headers` - The call's headers.
+fn get_or_create_correlation_id(headers: &HeaderMap) -> String {
+    headers
+        .get(CORRELATION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_CORRELATION_ID_LENGTH)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// The tower layer that gives each of a server's gRPC calls a correlation id, from the call's
+/// metadata or a new one, and makes the call's fields available to the log lines while the call is
+/// handled. The correlation id is returned in the response's metadata.
+#[derive(Clone, Default)]
+pub struct GrpcLogContextLayer {}
+
+impl GrpcLogContextLayer {
+    /// Create the tower layer that makes the fields of a server's gRPC calls available to the log
+    /// lines.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for GrpcLogContextLayer {
+    type Service = GrpcLogContextService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        GrpcLogContextService { service }
+    }
+}
+
+/// The tower service that makes the fields of a server's gRPC calls available to the log lines.
+#[derive(Clone)]
+pub struct GrpcLogContextService<S> {
+    service: S,
+}
+
+impl<S> Service<http::request::Request<tonic::transport::Body>> for GrpcLogContextService<S>
+where
+    S: Service<
+        http::request::Request<tonic::transport::Body>,
+        Response = http::response::Response<tonic::body::BoxBody>,
+    >,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    /// Implementation of tower's Service trait's poll_ready method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    /// Implementation of tower's Service trait's call method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn call(
+        &mut self,
+        mut request: http::request::Request<tonic::transport::Body>,
+    ) -> Self::Future {
+        let correlation_id = get_or_create_correlation_id(request.headers());
+        // The correlation id is either a visible ASCII header value or a UUID.
+        let correlation_id_value =
+            HeaderValue::from_str(&correlation_id).expect("Invalid correlation id");
+        request.headers_mut().insert(CORRELATION_ID_HEADER, correlation_id_value.clone());
+
+        let (service_name, method_name) =
+            request.uri().path().trim_start_matches('/').split_once('/').unwrap_or_default();
+        let log_context = LogContext {
+            correlation_id,
+            service_name: service_name.to_string(),
+            method_name: method_name.to_string(),
+            ..Default::default()
+        };
+
+        let response_future =
+            LOG_CONTEXT.sync_scope(log_context.clone(), || self.service.call(request));
+
+        Box::pin(LOG_CONTEXT.scope(log_context, async move {
+            let mut response = response_future.await?;
+            response.headers_mut().insert(CORRELATION_ID_HEADER, correlation_id_value);
+            Ok(response)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod logging_tests {
+    use super::*;
+    use log::Level;
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn grpc_log_context_service_test() {
+        // A service that responds with the correlation id that it sees, in its body.
+        let service = tower::service_fn(
+            |_request: http::request::Request<tonic::transport::Body>| async move {
+                let body = tonic::body::boxed(http_body::Full::from(correlation_id().unwrap()));
+                Ok::<_, Infallible>(http::response::Response::new(body))
+            },
+        );
+        let service = GrpcLogContextLayer::new().layer(service);
+
+        let request = http::Request::builder()
+            .uri("/test.TestService/Test")
+            .header(CORRELATION_ID_HEADER, "test-correlation-id")
+            .body(tonic::transport::Body::empty())
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers().get(CORRELATION_ID_HEADER).unwrap(), "test-correlation-id");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "test-correlation-id");
+
+        let request = http::Request::builder()
+            .uri("/test.TestService/Test")
+            .body(tonic::transport::Body::empty())
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        let correlation_id = response.headers().get(CORRELATION_ID_HEADER).unwrap().clone();
+        assert!(Uuid::parse_str(correlation_id.to_str().unwrap()).is_ok());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, correlation_id.as_bytes());
+    }
+
+    #[test]
+    fn create_json_log_entry_test() {
+        let entry = create_json_log_entry(
+            "2024-01-01T00:00:00Z",
+            &Record::builder()
+                .args(format_args!("test message"))
+                .level(Level::Info)
+                .module_path(Some("common::logging"))
+                .build(),
+        );
+        assert_eq!(entry["level"], "INFO");
+        assert_eq!(entry["module"], "common::logging");
+        assert_eq!(entry["message"], "test message");
+        assert!(entry.get("correlation_id").is_none());
+
+        let log_context = LogContext {
+            correlation_id: String::from("test-correlation-id"),
+            service_name: String::from("test.TestService"),
+            method_name: String::from("Test"),
+            ..Default::default()
+        };
+        let entry = LOG_CONTEXT.sync_scope(log_context, || {
+            set_entity_id("dtmi:sdv:Vehicle;1");
+            create_json_log_entry(
+                "2024-01-01T00:00:00Z",
+                &Record::builder().args(format_args!("test message")).level(Level::Info).build(),
+            )
+        });
+        assert_eq!(entry["correlation_id"], "test-correlation-id");
+        assert_eq!(entry["service"], "test.TestService");
+        assert_eq!(entry["method"], "Test");
+        assert_eq!(entry["entity_id"], "dtmi:sdv:Vehicle;1");
+        assert!(entry.get("instance_id").is_none());
+    }
+
+    #[test]
+    fn parse_log_filter_test() {
+        let directives = parse_log_filter("warn, digital_twin_graph=debug,").unwrap();
+        assert_eq!(
+            directives,
+            vec![
+                (None, LevelFilter::Warn),
+                (Some(String::from("digital_twin_graph")), LevelFilter::Debug)
+            ]
+        );
+
+        assert!(parse_log_filter("loud").is_err());
+        assert!(parse_log_filter("digital_twin_graph=loud").is_err());
+        assert!(parse_log_filter(" , ").is_err());
+    }
+
+    #[test]
+    fn create_logger_test() {
+        let logger = create_logger(LogFormat::Json, "warn,digital_twin_graph=debug").unwrap();
+        assert_eq!(logger.filter(), LevelFilter::Debug);
+
+        let metadata =
+            Metadata::builder().level(log::Level::Debug).target("digital_twin_graph::test").build();
+        assert!(logger.enabled(&metadata));
+        let metadata = Metadata::builder().level(log::Level::Debug).target("common").build();
+        assert!(!logger.enabled(&metadata));
+    }
+
+    #[test]
+    fn inject_correlation_id_test() {
+        let mut headers = HeaderMap::new();
+        inject_correlation_id(&mut headers);
+        assert!(headers.get(CORRELATION_ID_HEADER).is_none());
+
+        let log_context = LogContext {
+            correlation_id: String::from("test-correlation-id"),
+            ..Default::default()
+        };
+        LOG_CONTEXT.sync_scope(log_context, || inject_correlation_id(&mut headers));
+        assert_eq!(headers.get(CORRELATION_ID_HEADER).unwrap(), "test-correlation-id");
+    }
+}
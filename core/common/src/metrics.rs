@@ -0,0 +1,525 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use core::future::Future;
+use core_protobuf_data_access::HOSTABLE_FILE_DESCRIPTOR_SETS;
+use futures_core::task::{Context, Poll};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Method, StatusCode};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use prost::Message;
+use prost_types::FileDescriptorSet;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tonic::Code;
+use tower::{Layer, Service};
+
+use crate::grpc_call_status::{observe_response, GrpcCallStatusObserver};
+use crate::grpc_reflection::{
+    SERVER_REFLECTION_V1ALPHA_SERVICE_NAME, SERVER_REFLECTION_V1_SERVICE_NAME,
+};
+
+// This module provides the metrics of the process, in the Prometheus text format. The metrics are
+// registered with the default Prometheus registry, so that the modules can add their own.
+
+/// The path that the metrics are served on.
+pub const METRICS_PATH: &str = "/metrics";
+
+/// The label value of the service or method of a gRPC call that the servers can not host, so that
+/// the calls' labels only take a bounded set of values.
+const UNKNOWN_LABEL_VALUE: &str = "unknown";
+
+/// The metrics of the servers' gRPC calls.
+struct GrpcServerMetrics {
+    started_total: IntCounterVec,
+    handled_total: IntCounterVec,
+    handling_seconds: HistogramVec,
+    in_flight_calls: IntGauge,
+    in_flight_streaming_calls: IntGauge,
+}
+
+impl GrpcServerMetrics {
+    /// Create the metrics of the servers' gRPC calls and register them with a registry.
+    ///
+    /// # Arguments
+    /// * `registry` - The registry.
+    fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let started_total = IntCounterVec::new(
+            Opts::new(
+                "grpc_server_started_total",
+                "The number of gRPC calls that were started on the server.",
+            ),
+            &["grpc_service", "grpc_method"],
+        )?;
+        let handled_total = IntCounterVec::new(
+            Opts::new(
+                "grpc_server_handled_total",
+                "The number of gRPC calls that were completed on the server, by status code.",
+            ),
+            &["grpc_service", "grpc_method", "grpc_code"],
+        )?;
+        let handling_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "grpc_server_handling_seconds",
+                "How long the gRPC calls took to complete on the server, in seconds.",
+            ),
+            &["grpc_service", "grpc_method"],
+        )?;
+        let in_flight_calls = IntGauge::new(
+            "grpc_server_in_flight_calls",
+            "The number of gRPC calls that the server has started and not completed yet.",
+        )?;
+        let in_flight_streaming_calls = IntGauge::new(
+            "grpc_server_in_flight_streaming_calls",
+            "The number of streaming gRPC calls that the server has started and not completed yet.",
+        )?;
+
+        registry.register(Box::new(started_total.clone()))?;
+        registry.register(Box::new(handled_total.clone()))?;
+        registry.register(Box::new(handling_seconds.clone()))?;
+        registry.register(Box::new(in_flight_calls.clone()))?;
+        registry.register(Box::new(in_flight_streaming_calls.clone()))?;
+
+        Ok(Self {
+            started_total,
+            handled_total,
+            handling_seconds,
+            in_flight_calls,
+            in_flight_streaming_calls,
+        })
+    }
+}
+
+/// Get the methods of each service that the servers can host, keyed by the service's full name,
+/// from the services' file descriptor sets. Each method's name is mapped to whether it streams,
+/// i.e. the client or the server sends a stream of messages.
+fn get_known_grpc_methods() -> HashMap<String, HashMap<String, bool>> {
+    let mut known_grpc_methods: HashMap<String, HashMap<String, bool>> = HashMap::new();
+
+    let file_descriptor_sets = HOSTABLE_FILE_DESCRIPTOR_SETS
+        .iter()
+        .copied()
+        .chain([tonic_health::pb::FILE_DESCRIPTOR_SET, tonic_reflection::pb::FILE_DESCRIPTOR_SET]);
+    for file_descriptor_set in file_descriptor_sets {
+        let Ok(file_descriptor_set) = FileDescriptorSet::decode(file_descriptor_set) else {
+            warn!("Unable to decode a file descriptor set for the metrics");
+            continue;
+        };
+        for file in &file_descriptor_set.file {
+            for service in &file.service {
+                known_grpc_methods
+                    .entry(format!("{}.{}", file.package(), service.name()))
+                    .or_default()
+                    .extend(service.method.iter().map(|method| {
+                        (
+                            method.name().to_string(),
+                            method.client_streaming() || method.server_streaming(),
+                        )
+                    }));
+            }
+        }
+    }
+
+    // The v1 server reflection service is hosted by forwarding its calls to the v1alpha service.
+    if let Some(methods) = known_grpc_methods.get(SERVER_REFLECTION_V1ALPHA_SERVICE_NAME).cloned() {
+        known_grpc_methods.insert(SERVER_REFLECTION_V1_SERVICE_NAME.to_string(), methods);
+    }
+
+    known_grpc_methods
+}
+
+lazy_static! {
+    static ref GRPC_SERVER_METRICS: Arc<GrpcServerMetrics> = Arc::new(
+        GrpcServerMetrics::new(prometheus::default_registry())
+            .expect("Unable to register the gRPC server metrics")
+    );
+    static ref KNOWN_GRPC_METHODS: HashMap<String, HashMap<String, bool>> =
+        get_known_grpc_methods();
+}
+
+/// Get the service and method labels of a gRPC call. The service or method that the servers can
+/// not host is labeled as unknown, so that a client can not create new label values.
+///
+/// # Arguments
+/// * `path` - The gRPC call's path, e.g. "/invehicle_digital_twin.InvehicleDigitalTwin/FindById".
+fn get_grpc_call_labels(path: &str) -> (&str, &str) {
+    let (service_name, method_name) =
+        path.trim_start_matches('/').split_once('/').unwrap_or_default();
+
+    match KNOWN_GRPC_METHODS.get(service_name) {
+        Some(methods) if methods.contains_key(method_name) => (service_name, method_name),
+        Some(_) => (service_name, UNKNOWN_LABEL_VALUE),
+        None => (UNKNOWN_LABEL_VALUE, UNKNOWN_LABEL_VALUE),
+    }
+}
+
+/// Is a gRPC call to a streaming method? A call to a method that the servers can not host is not.
+///
+/// # Arguments
+/// * `service_name` - The gRPC call's service name.
+/// * `method_name` - The gRPC call's method name.
+fn is_streaming_grpc_call(service_name: &str, method_name: &str) -> bool {
+    KNOWN_GRPC_METHODS
+        .get(service_name)
+        .and_then(|methods| methods.get(method_name))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Get the number of unary gRPC calls that the servers have started and not completed yet, e.g. to
+/// wait for them to complete before shutting down. The streaming calls are left out, as a stream,
+/// like the one that WatchEntities returns, can stay open for as long as its client wants.
+pub fn grpc_server_in_flight_unary_calls() -> i64 {
+    let in_flight_streaming_calls = GRPC_SERVER_METRICS.in_flight_streaming_calls.get();
+    GRPC_SERVER_METRICS.in_flight_calls.get() - in_flight_streaming_calls
+}
+
+/// A gauge whose value is read from a function whenever the metrics are gathered, e.g. the number
+/// of entries in a map.
+struct IntGaugeFn {
+    gauge: IntGauge,
+    value_fn: Box<dyn Fn() -> i64 + Send + Sync>,
+}
+
+impl Collector for IntGaugeFn {
+    fn desc(&self) -> Vec<&Desc> {
+        self.gauge.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.gauge.set((self.value_fn)());
+        self.gauge.collect()
+    }
+}
+
+/// Register a gauge whose value is read from a function whenever the metrics are gathered.
+/// A gauge that was registered with the same name before is replaced, so that the gauge reads
+/// from the latest instance of what it measures.
+///
+/// # Arguments
+/// * `registry` - The registry to register the gauge in, usually `prometheus::default_registry()`,
+///                whose metrics are served.
+/// * `name` - The gauge's name.
+/// * `help` - The gauge's description.
+/// * `value_fn` - The function that returns the gauge's value.
+pub fn register_int_gauge_fn<F>(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    value_fn: F,
+) -> Result<(), prometheus::Error>
+where
+    F: Fn() -> i64 + Send + Sync + 'static,
+{
+    let gauge = IntGauge::new(name, help)?;
+    // Unregistering fails when no gauge has been registered with the name yet.
+    let _ = registry.unregister(Box::new(gauge.clone()));
+
+    registry.register(Box::new(IntGaugeFn { gauge, value_fn: Box::new(value_fn) }))
+}
+
+/// Encode the metrics of a registry in the Prometheus text format.
+///
+/// # Arguments
+/// * `registry` - The registry.
+pub fn encode_metrics(registry: &Registry) -> Result<String, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&registry.gather(), &mut buffer)?;
+
+    String::from_utf8(buffer).map_err(|error| prometheus::Error::Msg(error.to_string()))
+}
+
+/// Handle a request to the metrics server.
+///
+/// # Arguments
+/// * `request` - The request.
+async fn handle_metrics_request(
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, Infallible> {
+    let mut response = hyper::Response::new(hyper::Body::empty());
+
+    if request.method() != Method::GET || request.uri().path() != METRICS_PATH {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(response);
+    }
+
+    match encode_metrics(prometheus::default_registry()) {
+        Ok(metrics) => {
+            response.headers_mut().insert(
+                http::header::CONTENT_TYPE,
+                http::HeaderValue::from_static(prometheus::TEXT_FORMAT),
+            );
+            *response.body_mut() = hyper::Body::from(metrics);
+        }
+        Err(error) => {
+            warn!("Unable to encode the metrics: {error}");
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    Ok(response)
+}
+
+/// Serve the metrics over HTTP at `/metrics`, for Prometheus to scrape. It serves until it fails.
+///
+/// # Arguments
+/// * `address` - The address the metrics are served on.
+pub async fn serve_metrics(address: SocketAddr) -> Result<(), hyper::Error> {
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_metrics_request)) });
+
+    let server = hyper::Server::try_bind(&address)?.serve(make_service);
+    info!("The metrics are served on 'http://{}{METRICS_PATH}'", server.local_addr()); // Devskim: ignore DS137138
+
+    server.await
+}
+
+/// Records the metrics of a gRPC call once it has completed, when it is dropped. A call whose
+/// status code was not seen, e.g. because the client cancelled it, is recorded as Unknown.
+struct GrpcCallMetricsRecorder {
+    metrics: Arc<GrpcServerMetrics>,
+    service_name: String,
+    method_name: String,
+    is_streaming: bool,
+    start: Instant,
+    code: Option<Code>,
+}
+
+impl GrpcCallMetricsRecorder {
+    /// Start recording a gRPC call.
+    ///
+    /// # Arguments
+    /// * `metrics` - The metrics that the call is recorded in.
+    /// * `path` - The gRPC call's path, e.g. "/invehicle_digital_twin.InvehicleDigitalTwin/FindById".
+    fn start(metrics: Arc<GrpcServerMetrics>, path: &str) -> Self {
+        let (service_name, method_name) = get_grpc_call_labels(path);
+        metrics.started_total.with_label_values(&[service_name, method_name]).inc();
+        metrics.in_flight_calls.inc();
+        let is_streaming = is_streaming_grpc_call(service_name, method_name);
+        if is_streaming {
+            metrics.in_flight_streaming_calls.inc();
+        }
+
+        Self {
+            metrics,
+            service_name: service_name.to_string(),
+            method_name: method_name.to_string(),
+            is_streaming,
+            start: Instant::now(),
+            code: None,
+        }
+    }
+}
+
+impl GrpcCallStatusObserver for GrpcCallMetricsRecorder {
+    fn observe_code(&mut self, code: Code) {
+        self.code = Some(code);
+    }
+}
+
+impl Drop for GrpcCallMetricsRecorder {
+    fn drop(&mut self) {
+        let code = format!("{:?}", self.code.unwrap_or(Code::Unknown));
+        self.metrics
+            .handled_total
+            .with_label_values(&[&self.service_name, &self.method_name, &code])
+            .inc();
+        self.metrics
+            .handling_seconds
+            .with_label_values(&[&self.service_name, &self.method_name])
+            .observe(self.start.elapsed().as_secs_f64());
+        if self.is_streaming {
+            self.metrics.in_flight_streaming_calls.dec();
+        }
+        self.metrics.in_flight_calls.dec();
+    }
+}
+
+/// The tower layer that records the metrics of a server's gRPC calls: how many were started, how
+/// many were completed with each status code, and how long they took.
+#[derive(Clone)]
+pub struct GrpcMetricsLayer {
+    metrics: Arc<GrpcServerMetrics>,
+}
+
+impl GrpcMetricsLayer {
+    /// Create the tower layer that records the metrics of a server's gRPC calls in the default
+    /// registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for GrpcMetricsLayer {
+    fn default() -> Self {
+        Self { metrics: GRPC_SERVER_METRICS.clone() }
+    }
+}
+
+impl<S> Layer<S> for GrpcMetricsLayer {
+    type Service = GrpcMetricsService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        GrpcMetricsService { service, metrics: self.metrics.clone() }
+    }
+}
+
+/// The tower service that records the metrics of a server's gRPC calls.
+#[derive(Clone)]
+pub struct GrpcMetricsService<S> {
+    service: S,
+    metrics: Arc<GrpcServerMetrics>,
+}
+
+impl<S> Service<http::request::Request<tonic::transport::Body>> for GrpcMetricsService<S>
+where
+    S: Service<
+        http::request::Request<tonic::transport::Body>,
+        Response = http::response::Response<tonic::body::BoxBody>,
+    >,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    /// Implementation of tower's Service trait's poll_ready method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    /// Implementation of tower's Service trait's call method.
+    /// See <https://docs.rs/tower/latest/tower/trait.Service.html>
+    fn call(&mut self, request: http::request::Request<tonic::transport::Body>) -> Self::Future {
+        let recorder = GrpcCallMetricsRecorder::start(self.metrics.clone(), request.uri().path());
+        let response_future = self.service.call(request);
+
+        Box::pin(async move { Ok(observe_response(response_future.await?, recorder)) })
+    }
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+    use http::HeaderMap;
+    use http_body::Body;
+    use tower::ServiceExt;
+
+    #[test]
+    fn get_grpc_call_labels_test() {
+        assert_eq!(
+            get_grpc_call_labels("/invehicle_digital_twin.InvehicleDigitalTwin/FindById"),
+            ("invehicle_digital_twin.InvehicleDigitalTwin", "FindById")
+        );
+        assert_eq!(
+            get_grpc_call_labels("/grpc.reflection.v1.ServerReflection/ServerReflectionInfo"),
+            ("grpc.reflection.v1.ServerReflection", "ServerReflectionInfo")
+        );
+        assert_eq!(
+            get_grpc_call_labels("/invehicle_digital_twin.InvehicleDigitalTwin/Unknown"),
+            ("invehicle_digital_twin.InvehicleDigitalTwin", UNKNOWN_LABEL_VALUE)
+        );
+        assert_eq!(
+            get_grpc_call_labels("/test.TestService/Test"),
+            (UNKNOWN_LABEL_VALUE, UNKNOWN_LABEL_VALUE)
+        );
+        assert_eq!(get_grpc_call_labels("invalid"), (UNKNOWN_LABEL_VALUE, UNKNOWN_LABEL_VALUE));
+    }
+
+    #[test]
+    fn is_streaming_grpc_call_test() {
+        assert!(is_streaming_grpc_call(
+            "invehicle_digital_twin.InvehicleDigitalTwin",
+            "WatchEntities"
+        ));
+        assert!(is_streaming_grpc_call("grpc.health.v1.Health", "Watch"));
+        assert!(is_streaming_grpc_call(
+            "grpc.reflection.v1.ServerReflection",
+            "ServerReflectionInfo"
+        ));
+        assert!(!is_streaming_grpc_call("invehicle_digital_twin.InvehicleDigitalTwin", "FindById"));
+        assert!(!is_streaming_grpc_call("test.TestService", "Test"));
+    }
+
+    #[tokio::test]
+    async fn grpc_metrics_service_test() {
+        // A service that responds with the status that the method name asks for.
+        let service = tower::service_fn(
+            |request: http::request::Request<tonic::transport::Body>| async move {
+                let response = match request.uri().path() {
+                    "/grpc.health.v1.Health/Check" => tonic::Status::not_found("test").to_http(),
+                    _ => {
+                        let (mut sender, body) = hyper::Body::channel();
+                        let mut trailers = HeaderMap::new();
+                        trailers.insert("grpc-status", http::HeaderValue::from_static("0"));
+                        sender.send_trailers(trailers).await.unwrap();
+                        let body = body
+                            .map_err(|error| tonic::Status::from_error(Box::new(error)))
+                            .boxed_unsync();
+                        http::response::Response::new(body)
+                    }
+                };
+                Ok::<_, Infallible>(response)
+            },
+        );
+        // The calls are recorded in a registry of their own, so that the other tests' calls do not
+        // affect them.
+        let metrics = Arc::new(GrpcServerMetrics::new(&Registry::new()).unwrap());
+        let service = GrpcMetricsLayer { metrics: metrics.clone() }.layer(service);
+
+        let request = http::Request::builder()
+            .uri("/grpc.health.v1.Health/Check")
+            .body(tonic::transport::Body::empty())
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(metrics.in_flight_calls.get(), 1);
+        drop(response);
+        assert_eq!(
+            metrics
+                .handled_total
+                .with_label_values(&["grpc.health.v1.Health", "Check", "NotFound"])
+                .get(),
+            1
+        );
+        assert_eq!(metrics.in_flight_calls.get(), 0);
+
+        let request = http::Request::builder()
+            .uri("/test.TestService/Ok")
+            .body(tonic::transport::Body::empty())
+            .unwrap();
+        let mut body = service.oneshot(request).await.unwrap().into_body();
+        while body.data().await.is_some() {}
+        assert!(body.trailers().await.unwrap().is_some());
+        drop(body);
+        assert_eq!(
+            metrics
+                .handled_total
+                .with_label_values(&[UNKNOWN_LABEL_VALUE, UNKNOWN_LABEL_VALUE, "Ok"])
+                .get(),
+            1
+        );
+    }
+
+    #[test]
+    fn register_int_gauge_fn_test() {
+        let registry = Registry::new();
+        register_int_gauge_fn(&registry, "test_gauge", "A test gauge.", || 1).unwrap();
+        register_int_gauge_fn(&registry, "test_gauge", "A test gauge.", || 2).unwrap();
+
+        let metrics = encode_metrics(&registry).unwrap();
+        assert!(metrics.contains("test_gauge 2"));
+    }
+}
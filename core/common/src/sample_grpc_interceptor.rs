@@ -10,6 +10,9 @@ use std::error::Error;
 
 use crate::grpc_interceptor::GrpcInterceptor;
 
+/// The name that the sample gRPC interceptor is registered with.
+pub const INTERCEPTOR_NAME: &str = "sample_grpc_interceptor";
+
 /// Sample gRPC interceptor.
 #[derive(Clone)]
 pub struct SampleGrpcInterceptor {}
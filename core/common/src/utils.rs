@@ -16,6 +16,8 @@ use strum_macros::Display;
 use tokio::time::{sleep, Duration};
 use tonic::{Request, Status};
 
+use crate::grpc_channel;
+
 const IBEJI_HOME_VAR_NAME: &str = "IBEJI_HOME";
 
 /// An identifier used when discovering a service through Chariott.
@@ -116,8 +118,9 @@ pub async fn discover_service_using_chariott(
     expected_communication_kind: &str,
     expected_communication_reference: &str,
 ) -> Result<String, Status> {
-    let mut client = ServiceRegistryClient::connect(chariott_uri.to_owned())
+    let mut client = grpc_channel::connect(chariott_uri)
         .await
+        .map(ServiceRegistryClient::new)
         .map_err(|e| Status::internal(e.to_string()))?;
 
     let request = Request::new(DiscoverRequest {
@@ -185,6 +188,24 @@ pub async fn get_service_uri(
     Ok(result)
 }
 
+/// Get the URI that a module reaches the server that hosts it on. A full URI, e.g.
+/// "https://localhost:5010" or "unix:///run/ibeji/invehicle_digital_twin.sock", is used as it is,
+/// so that the server can use TLS or a Unix domain socket. An authority is reached over http.
+///
+/// # Arguments
+/// * `base_uri` - The server's full URI, if it was provided.
+/// * `base_authority` - The server's authority, if it was provided.
+pub fn get_base_uri(
+    base_uri: Option<&str>,
+    base_authority: Option<&str>,
+) -> Result<String, String> {
+    match (base_uri, base_authority) {
+        (Some(base_uri), _) => Ok(base_uri.to_string()),
+        (None, Some(base_authority)) => Ok(format!("http://{base_authority}")), // Devskim: ignore DS137138
+        (None, None) => Err(String::from("Either base_uri or base_authority must be provided")),
+    }
+}
+
 /// Is the provided subset a subset of the provided superset?
 ///
 /// # Arguments
@@ -240,6 +261,23 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn get_base_uri_test() {
+        assert_eq!(
+            get_base_uri(Some("https://localhost:5010"), Some("0.0.0.0:5010")).unwrap(),
+            "https://localhost:5010"
+        );
+        assert_eq!(
+            get_base_uri(Some("unix:///run/ibeji/invehicle_digital_twin.sock"), None).unwrap(),
+            "unix:///run/ibeji/invehicle_digital_twin.sock"
+        );
+        assert_eq!(
+            get_base_uri(None, Some("0.0.0.0:5010")).unwrap(),
+            "http://0.0.0.0:5010" // Devskim: ignore DS137138
+        );
+        assert!(get_base_uri(None, None).is_err());
+    }
+
     #[test]
     fn is_subset_test() {
         assert!(is_subset(&[], &[]));
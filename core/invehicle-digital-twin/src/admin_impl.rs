@@ -0,0 +1,311 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use crate::invehicle_digital_twin_impl::LeaseMap;
+use common::grpc_module_registry::LoadedGrpcModules;
+use common::logging;
+use core_protobuf_data_access::admin::v1::admin_server::Admin;
+use core_protobuf_data_access::admin::v1::{
+    DumpStateRequest, DumpStateResponse, GetStatusRequest, GetStatusResponse, ListModulesRequest,
+    ListModulesResponse, SetLogFilterRequest, SetLogFilterResponse,
+};
+use core_protobuf_data_access::invehicle_digital_twin::v1::EntityAccessInfo;
+use log::info;
+use parking_lot::RwLock;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::Instant;
+use tonic::{Request, Response, Status};
+
+/// The parts of the config keys whose values are secrets, which are redacted from the effective
+/// config, e.g. "token" or the "key" in "key_path", the path of a TLS private key. The parts of a key
+/// are separated by underscores.
+const SECRET_CONFIG_KEY_PARTS: &[&str] = &["token", "key", "password", "secret"];
+
+/// The value that replaces the secrets in the effective config.
+const REDACTED_CONFIG_VALUE: &str = "<redacted>";
+
+/// Is a config key's value a secret?
+///
+/// # Arguments
+/// * `key` - The config key.
+fn is_secret_config_key(key: &str) -> bool {
+    key.to_lowercase().split('_').any(|part| SECRET_CONFIG_KEY_PARTS.contains(&part))
+}
+
+/// Replace the secrets in a config with a placeholder, wherever they are nested.
+///
+/// # Arguments
+/// * `config` - The config.
+fn redact_secrets(config: &mut Value) {
+    match config {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if is_secret_config_key(key) {
+                    *value = Value::String(REDACTED_CONFIG_VALUE.to_string());
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        Value::Array(array) => array.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// The runtime administration of the In-Vehicle Digital Twin Service.
+#[derive(Clone)]
+pub struct AdminImpl {
+    /// When the service started.
+    pub start_time: Instant,
+    /// The service's effective config, with its secrets redacted.
+    pub effective_config: Value,
+    /// The names of the configured interceptors, from the outermost to the innermost.
+    pub interceptor_names: Vec<String>,
+    /// The core service's registered entities, keyed by entity id.
+    pub entity_access_info_map: Arc<RwLock<HashMap<String, EntityAccessInfo>>>,
+    /// The core service's leases, keyed by entity id and then by endpoint uri.
+    pub lease_map: Arc<RwLock<LeaseMap>>,
+    /// The modules that the app server hosts.
+    pub loaded_modules: LoadedGrpcModules,
+}
+
+impl AdminImpl {
+    /// Creates a new AdminImpl. It reports no modules until the app server's loaded modules are
+    /// provided.
+    ///
+    /// # Arguments
+    /// * `start_time` - When the service started.
+    /// * `effective_config` - The service's effective config. Its secrets are redacted.
+    /// * `interceptor_names` - The names of the configured interceptors, from the outermost to the
+    ///                         innermost.
+    /// * `entity_access_info_map` - The core service's registered entities.
+    /// * `lease_map` - The core service's leases.
+    pub fn new(
+        start_time: Instant,
+        effective_config: Value,
+        interceptor_names: Vec<String>,
+        entity_access_info_map: Arc<RwLock<HashMap<String, EntityAccessInfo>>>,
+        lease_map: Arc<RwLock<LeaseMap>>,
+    ) -> Self {
+        let mut effective_config = effective_config;
+        redact_secrets(&mut effective_config);
+
+        AdminImpl {
+            start_time,
+            effective_config,
+            interceptor_names,
+            entity_access_info_map,
+            lease_map,
+            loaded_modules: LoadedGrpcModules::default(),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminImpl {
+    /// Get status implementation.
+    ///
+    /// # Arguments
+    /// * `request` - Get status request.
+    async fn get_status(
+        &self,
+        _request: Request<GetStatusRequest>,
+    ) -> Result<Response<GetStatusResponse>, Status> {
+        let response = GetStatusResponse {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_in_seconds: self.start_time.elapsed().as_secs(),
+            log_filter: logging::log_filter().unwrap_or_default(),
+            effective_config_json: self.effective_config.to_string(),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    /// Set log filter implementation.
+    ///
+    /// # Arguments
+    /// * `request` - Set log filter request.
+    async fn set_log_filter(
+        &self,
+        request: Request<SetLogFilterRequest>,
+    ) -> Result<Response<SetLogFilterResponse>, Status> {
+        let log_filter = request.into_inner().log_filter;
+
+        logging::set_log_filter(&log_filter)
+            .map_err(|error| Status::invalid_argument(error.to_string()))?;
+
+        info!("The log filter was changed to '{log_filter}'.");
+
+        Ok(Response::new(SetLogFilterResponse {}))
+    }
+
+    /// List modules implementation.
+    ///
+    /// # Arguments
+    /// * `request` - List modules request.
+    async fn list_modules(
+        &self,
+        _request: Request<ListModulesRequest>,
+    ) -> Result<Response<ListModulesResponse>, Status> {
+        let response = ListModulesResponse {
+            module_names: self.loaded_modules.module_names(),
+            interceptor_names: self.interceptor_names.clone(),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    /// Dump state implementation. The state has the core service's registered entities and the
+    /// remaining time-to-live of their endpoints' leases, and the state of each module that reports it.
+    ///
+    /// # Arguments
+    /// * `request` - Dump state request.
+    async fn dump_state(
+        &self,
+        _request: Request<DumpStateRequest>,
+    ) -> Result<Response<DumpStateResponse>, Status> {
+        let now = Instant::now();
+        let leases: Map<String, Value> = self
+            .lease_map
+            .read()
+            .iter()
+            .map(|(entity_id, endpoint_leases)| {
+                let endpoint_leases: Map<String, Value> = endpoint_leases
+                    .iter()
+                    .map(|(uri, lease)| {
+                        (
+                            uri.clone(),
+                            json!({ "remaining_ttl_in_seconds": lease.remaining_ttl(now).as_secs() }),
+                        )
+                    })
+                    .collect();
+                (entity_id.clone(), Value::Object(endpoint_leases))
+            })
+            .collect();
+        let invehicle_digital_twin_state = json!({
+            "entities": *self.entity_access_info_map.read(),
+            "leases": leases,
+        });
+
+        let modules: Map<String, Value> = self.loaded_modules.state().await.into_iter().collect();
+
+        let state = json!({
+            "invehicle_digital_twin": invehicle_digital_twin_state,
+            "modules": modules,
+        });
+
+        Ok(Response::new(DumpStateResponse { state_json: state.to_string() }))
+    }
+}
+
+#[cfg(test)]
+mod admin_impl_tests {
+    use super::*;
+    use common::lease::Lease;
+    use tokio::time::Duration;
+
+    /// Create an AdminImpl with an entity that was registered with a lease.
+    fn create_admin_impl() -> AdminImpl {
+        let entity_access_info = EntityAccessInfo {
+            name: String::from("AmbientAirTemperature"),
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from(
+                "The immediate surroundings air temperature (in Fahrenheit).",
+            ),
+            ..Default::default()
+        };
+        let entity_id = entity_access_info.id.clone();
+
+        AdminImpl::new(
+            Instant::now(),
+            json!({
+                "invehicle_digital_twin_authority": "0.0.0.0:5010",
+                "auth": { "tokens": [{ "token": "secret", "identity": "provider" }] },
+            }),
+            vec![String::from("auth")],
+            Arc::new(RwLock::new(HashMap::from([(entity_id.clone(), entity_access_info)]))),
+            Arc::new(RwLock::new(HashMap::from([(
+                entity_id,
+                HashMap::from([(String::new(), Lease::new(Duration::from_secs(60)))]),
+            )]))),
+        )
+    }
+
+    #[test]
+    fn redact_secrets_test() {
+        let mut config = json!({
+            "auth": { "tokens": [{ "token": "secret", "identity": "provider" }] },
+            "tls": {
+                "cert_path": "/etc/ibeji/invehicle_digital_twin.crt",
+                "key_path": "/etc/ibeji/invehicle_digital_twin.key",
+            },
+            "modules": [{ "name": "test", "api_key": "secret", "password": "secret" }],
+            "client_secret": "secret",
+        });
+
+        redact_secrets(&mut config);
+
+        assert_eq!(config["auth"]["tokens"][0]["token"], REDACTED_CONFIG_VALUE);
+        assert_eq!(config["auth"]["tokens"][0]["identity"], "provider");
+        assert_eq!(config["tls"]["cert_path"], "/etc/ibeji/invehicle_digital_twin.crt");
+        assert_eq!(config["tls"]["key_path"], REDACTED_CONFIG_VALUE);
+        assert_eq!(config["modules"][0]["name"], "test");
+        assert_eq!(config["modules"][0]["api_key"], REDACTED_CONFIG_VALUE);
+        assert_eq!(config["modules"][0]["password"], REDACTED_CONFIG_VALUE);
+        assert_eq!(config["client_secret"], REDACTED_CONFIG_VALUE);
+    }
+
+    #[tokio::test]
+    async fn get_status_test() {
+        let admin_impl = create_admin_impl();
+
+        let response =
+            admin_impl.get_status(Request::new(GetStatusRequest {})).await.unwrap().into_inner();
+
+        assert_eq!(response.version, env!("CARGO_PKG_VERSION"));
+        let effective_config: Value =
+            serde_json::from_str(&response.effective_config_json).unwrap();
+        assert_eq!(effective_config["invehicle_digital_twin_authority"], "0.0.0.0:5010");
+        assert_eq!(effective_config["auth"]["tokens"][0]["token"], REDACTED_CONFIG_VALUE);
+    }
+
+    #[tokio::test]
+    async fn list_modules_test() {
+        let admin_impl = create_admin_impl();
+
+        let response = admin_impl
+            .list_modules(Request::new(ListModulesRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(response.module_names.is_empty());
+        assert_eq!(response.interceptor_names, ["auth"]);
+    }
+
+    #[tokio::test]
+    async fn dump_state_test() {
+        let admin_impl = create_admin_impl();
+        let entity_id = "dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1";
+
+        let response =
+            admin_impl.dump_state(Request::new(DumpStateRequest {})).await.unwrap().into_inner();
+
+        let state: Value = serde_json::from_str(&response.state_json).unwrap();
+        let invehicle_digital_twin_state = &state["invehicle_digital_twin"];
+        assert_eq!(
+            invehicle_digital_twin_state["entities"][entity_id]["name"],
+            "AmbientAirTemperature"
+        );
+        assert!(
+            invehicle_digital_twin_state["leases"][entity_id][""]["remaining_ttl_in_seconds"]
+                .as_u64()
+                .unwrap()
+                <= 60
+        );
+        assert_eq!(state["modules"], json!({}));
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use common::grpc_channel::get_unix_socket_path;
+use core_protobuf_data_access::invehicle_digital_twin::v1::{
+    EndpointHealthStatus, EndpointInfo, EntityAccessInfo,
+};
+use futures::future::join_all;
+use log::{debug, info};
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::net::{TcpStream, UnixStream};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
+use url::Url;
+
+/// The longest that a probe waits for an endpoint to accept a connection.
+const PROBE_TIMEOUT_IN_MILLISECONDS: u64 = 2000;
+/// The port that is probed for an MQTT broker whose uri does not include one.
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+const GRPC_PROTOCOL: &str = "grpc";
+const MQTT_PROTOCOL: &str = "mqtt";
+
+/// Periodically probes the registered endpoints and keeps track of their health.
+/// Both gRPC endpoints and MQTT brokers are probed by connecting to them over TCP, or over a Unix
+/// domain socket for a gRPC endpoint with a "unix://" uri.
+#[derive(Clone, Debug, Default)]
+pub struct EndpointHealthChecker {
+    /// The health of the endpoints that have been probed, keyed by uri.
+    endpoint_health_map: Arc<RwLock<HashMap<String, EndpointHealthStatus>>>,
+}
+
+impl EndpointHealthChecker {
+    /// Get the endpoint's health. An endpoint that has not been probed has an unknown health.
+    ///
+    /// # Arguments
+    /// * `uri` - The endpoint's uri.
+    pub fn get_endpoint_health(&self, uri: &str) -> EndpointHealthStatus {
+        let lock: RwLockReadGuard<HashMap<String, EndpointHealthStatus>> =
+            self.endpoint_health_map.read();
+        lock.get(uri).copied().unwrap_or(EndpointHealthStatus::Unknown)
+    }
+
+    /// Probe all of the registered entities' endpoints and record their health.
+    /// The health of endpoints that are no longer registered is forgotten.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_map` - The registered entities.
+    pub async fn check_endpoints(
+        &self,
+        entity_access_info_map: &Arc<RwLock<HashMap<String, EntityAccessInfo>>>,
+    ) {
+        let mut endpoint_info_list: Vec<EndpointInfo> = Vec::new();
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.read();
+            let mut uris = HashSet::new();
+            for entity_access_info in lock.values() {
+                for endpoint_info in &entity_access_info.endpoint_info_list {
+                    if uris.insert(endpoint_info.uri.clone()) {
+                        endpoint_info_list.push(endpoint_info.clone());
+                    }
+                }
+            }
+        }
+
+        // The endpoints are probed concurrently, so a check takes no longer than the slowest probe.
+        let endpoint_healths =
+            join_all(endpoint_info_list.into_iter().map(|endpoint_info| async {
+                let endpoint_health = Self::probe_endpoint(&endpoint_info).await;
+                debug!("The endpoint {} is {endpoint_health:?}", endpoint_info.uri);
+                (endpoint_info.uri, endpoint_health)
+            }))
+            .await;
+        let endpoint_health_map: HashMap<String, EndpointHealthStatus> =
+            endpoint_healths.into_iter().collect();
+
+        // This block controls the lifetime of the lock.
+        {
+            let mut lock: RwLockWriteGuard<HashMap<String, EndpointHealthStatus>> =
+                self.endpoint_health_map.write();
+            for (uri, endpoint_health) in &endpoint_health_map {
+                if lock.get(uri) != Some(endpoint_health) {
+                    info!("The endpoint {uri} is now {endpoint_health:?}");
+                }
+            }
+            *lock = endpoint_health_map;
+        }
+    }
+
+    /// Probe an endpoint by connecting to it over TCP, or over its Unix domain socket.
+    /// Endpoints with a protocol that cannot be probed have an unknown health.
+    ///
+    /// # Arguments
+    /// * `endpoint_info` - The endpoint.
+    pub async fn probe_endpoint(endpoint_info: &EndpointInfo) -> EndpointHealthStatus {
+        let default_port = match endpoint_info.protocol.as_str() {
+            GRPC_PROTOCOL => None,
+            MQTT_PROTOCOL => Some(DEFAULT_MQTT_PORT),
+            _ => return EndpointHealthStatus::Unknown,
+        };
+
+        let probe_timeout = Duration::from_millis(PROBE_TIMEOUT_IN_MILLISECONDS);
+
+        if let Some(unix_socket_path) = get_unix_socket_path(&endpoint_info.uri) {
+            return match timeout(probe_timeout, UnixStream::connect(unix_socket_path)).await {
+                Ok(Ok(_)) => EndpointHealthStatus::Healthy,
+                _ => EndpointHealthStatus::Unhealthy,
+            };
+        }
+
+        let Ok(url) = Url::parse(&endpoint_info.uri) else {
+            return EndpointHealthStatus::Unhealthy;
+        };
+        let (Some(host), Some(port)) =
+            (url.host_str(), url.port_or_known_default().or(default_port))
+        else {
+            return EndpointHealthStatus::Unhealthy;
+        };
+
+        match timeout(probe_timeout, TcpStream::connect(format!("{host}:{port}"))).await {
+            Ok(Ok(_)) => EndpointHealthStatus::Healthy,
+            _ => EndpointHealthStatus::Unhealthy,
+        }
+    }
+
+    /// Start the background task that periodically probes the registered entities' endpoints.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_map` - The registered entities.
+    /// * `check_interval` - The interval between health checks.
+    pub fn start(
+        &self,
+        entity_access_info_map: Arc<RwLock<HashMap<String, EntityAccessInfo>>>,
+        check_interval: Duration,
+    ) -> JoinHandle<()> {
+        let endpoint_health_checker = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(check_interval);
+            loop {
+                interval.tick().await;
+                endpoint_health_checker.check_endpoints(&entity_access_info_map).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod endpoint_health_checker_tests {
+    use super::*;
+    use tokio::net::{TcpListener, UnixListener};
+
+    #[tokio::test]
+    async fn check_endpoints_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap()); // Devskim: ignore DS137138
+
+        let entity_access_info = EntityAccessInfo {
+            name: String::from("AmbientAirTemperature"),
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from("Ambient air temperature"),
+            endpoint_info_list: vec![
+                EndpointInfo {
+                    protocol: String::from("grpc"),
+                    operations: vec![String::from("Subscribe")],
+                    uri: uri.clone(),
+                    context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                },
+                EndpointInfo {
+                    protocol: String::from("someip"),
+                    operations: vec![String::from("Subscribe")],
+                    uri: String::from("someip://vehicle"),
+                    context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                },
+            ],
+        };
+        let entity_access_info_map = Arc::new(RwLock::new(HashMap::from([(
+            entity_access_info.id.clone(),
+            entity_access_info,
+        )])));
+
+        let endpoint_health_checker = EndpointHealthChecker::default();
+        assert_eq!(
+            endpoint_health_checker.get_endpoint_health(&uri),
+            EndpointHealthStatus::Unknown
+        );
+
+        endpoint_health_checker.check_endpoints(&entity_access_info_map).await;
+        assert_eq!(
+            endpoint_health_checker.get_endpoint_health(&uri),
+            EndpointHealthStatus::Healthy
+        );
+        assert_eq!(
+            endpoint_health_checker.get_endpoint_health("someip://vehicle"),
+            EndpointHealthStatus::Unknown
+        );
+
+        drop(listener);
+
+        endpoint_health_checker.check_endpoints(&entity_access_info_map).await;
+        assert_eq!(
+            endpoint_health_checker.get_endpoint_health(&uri),
+            EndpointHealthStatus::Unhealthy
+        );
+    }
+
+    #[tokio::test]
+    async fn probe_unix_socket_endpoint_test() {
+        let path = std::env::temp_dir()
+            .join(format!("endpoint_health_checker_test_{}.sock", std::process::id()));
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let endpoint_info = EndpointInfo {
+            protocol: String::from("grpc"),
+            operations: vec![String::from("Subscribe")],
+            uri: format!("unix://{}", path.display()),
+            context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+        };
+        assert_eq!(
+            EndpointHealthChecker::probe_endpoint(&endpoint_info).await,
+            EndpointHealthStatus::Healthy
+        );
+
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            EndpointHealthChecker::probe_endpoint(&endpoint_info).await,
+            EndpointHealthStatus::Unhealthy
+        );
+    }
+}
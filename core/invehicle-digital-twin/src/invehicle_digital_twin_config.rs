@@ -2,18 +2,50 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+use common::auth_grpc_interceptor::AuthSettings;
+use common::grpc_channel::TlsSettings;
+use common::grpc_interceptor_registry::GrpcInterceptorSettings;
+use common::grpc_module_registry::GrpcModuleSettings;
+use common::grpc_tracing::TracingSettings;
+use common::logging::LoggingSettings;
 use common::utils;
+use core_protobuf_data_access::invehicle_digital_twin::v1::RegistrationPolicy;
 use serde_derive::Deserialize;
+use serde_json::Value;
 
 const CONFIG_FILENAME: &str = "invehicle_digital_twin_settings";
 
 #[derive(Debug, Deserialize)]
 pub struct Settings {
-    pub invehicle_digital_twin_authority: String,
+    pub invehicle_digital_twin_authority: Option<String>,
+    pub invehicle_digital_twin_unix_socket_uri: Option<String>,
+    pub unix_socket_mode: Option<String>,
     pub chariott_uri: Option<String>,
+    pub registration_policy: Option<RegistrationPolicy>,
+    pub lease_eviction_interval_in_seconds: Option<u64>,
+    pub persistence_directory: Option<String>,
+    pub persistence_snapshot_threshold: Option<usize>,
+    pub endpoint_health_check_interval_in_seconds: Option<u64>,
+    pub hide_unhealthy_endpoints: Option<bool>,
+    pub dtdl_model_directory: Option<String>,
+    pub dtdl_model_summary_interval_in_seconds: Option<u64>,
+    pub modules: Option<Vec<GrpcModuleSettings>>,
+    pub interceptors: Option<Vec<GrpcInterceptorSettings>>,
+    pub auth: Option<AuthSettings>,
+    pub tls: Option<TlsSettings>,
+    pub metrics_authority: Option<String>,
+    pub tracing: Option<TracingSettings>,
+    pub logging: Option<LoggingSettings>,
+    pub admin_address: Option<String>,
+    pub shutdown_drain_timeout_in_seconds: Option<u64>,
 }
 
 /// Load the settings.
 pub fn load_settings() -> Settings {
     utils::load_settings(CONFIG_FILENAME).unwrap()
 }
+
+/// Load the effective config, as it was read from the settings file, as JSON.
+pub fn load_effective_config() -> Result<Value, config::ConfigError> {
+    utils::load_settings(CONFIG_FILENAME)
+}
@@ -4,23 +4,112 @@
 
 extern crate iref;
 
+use crate::endpoint_health_checker::EndpointHealthChecker;
+use crate::invehicle_digital_twin_persistence::{EntityPersistence, PersistedEntity};
+use common::dtdl_model_catalog::DtdlModelCatalog;
+use common::dtmi::Dtmi;
+use common::entity_events::{create_entity_event_sender, EntityEvent, EntityEventKind};
+use common::grpc_status_details::status_with_typed_details;
+use common::lease::Lease;
+use common::logging;
+use common::metrics;
+use common::utils::is_subset;
 use core_protobuf_data_access::invehicle_digital_twin::v1::invehicle_digital_twin_server::InvehicleDigitalTwin;
 use core_protobuf_data_access::invehicle_digital_twin::v1::{
-    EntityAccessInfo, FindByIdRequest, FindByIdResponse, RegisterRequest, RegisterResponse,
+    EndpointHealthStatus, EntityAccessInfo, EntityRegistrationStatus, FindByIdRequest,
+    FindByIdResponse, ListEntitiesRequest, ListEntitiesResponse, RegisterRequest, RegisterResponse,
+    RegistrationPolicy, RenewLeaseRequest, RenewLeaseResponse, UnregisterEntityInfo,
+    UnregisterRequest, UnregisterResponse, WatchEntitiesRequest, WatchEntitiesResponse,
+    WatchEventKind,
 };
-use log::{debug, info};
-use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use log::{debug, info, warn};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use prometheus::Registry;
 use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
 use std::sync::Arc;
-use tonic::{Request, Response, Status};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Code, Request, Response, Status};
 
-#[derive(Debug, Default)]
+/// The capacity of the channel that buffers a watch's events until they are sent to the client.
+const WATCH_ENTITIES_CHANNEL_CAPACITY: usize = 100;
+/// The page size that is used when a list entities request does not provide one.
+const DEFAULT_LIST_ENTITIES_PAGE_SIZE: usize = 100;
+/// The largest page size that a list entities request can use.
+const MAX_LIST_ENTITIES_PAGE_SIZE: usize = 1000;
+/// The fully qualified protobuf name of the register response, which a failed register call
+/// carries in its status details.
+const REGISTER_RESPONSE_TYPE_NAME: &str = "invehicle_digital_twin.RegisterResponse";
+
+/// The leases of the registered endpoints, keyed by entity id and then by endpoint uri.
+/// An entity that was registered without endpoints has its lease under an empty uri.
+pub type LeaseMap = HashMap<String, HashMap<String, Lease>>;
+
+#[derive(Clone, Debug)]
 pub struct InvehicleDigitalTwinImpl {
     pub entity_access_info_map: Arc<RwLock<HashMap<String, EntityAccessInfo>>>,
+    /// The policy that is applied to a re-registration when the request does not specify one.
+    pub default_registration_policy: RegistrationPolicy,
+    /// The leases for the endpoints that were registered with one.
+    pub lease_map: Arc<RwLock<LeaseMap>>,
+    /// The sender for the events that are published when the registered entities change.
+    pub entity_event_sender: broadcast::Sender<EntityEvent>,
+    /// The durable persistence for the registered entities, when it is enabled.
+    pub persistence: Option<Arc<Mutex<EntityPersistence>>>,
+    /// The health checker for the registered endpoints, when endpoint health checks are enabled.
+    pub endpoint_health_checker: Option<EndpointHealthChecker>,
+    /// Should the endpoints that failed their last health check be hidden from lookups?
+    pub hide_unhealthy_endpoints: bool,
+    /// The DTDL models that registrations are validated against, when they have been loaded.
+    pub dtdl_model_catalog: Option<Arc<DtdlModelCatalog>>,
+    /// Serializes the changes to the registered entities and their leases, so that each change is
+    /// persisted before it is applied, in the order that the changes are applied.
+    pub change_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// A working copy of some of the registered entities and their leases. A change is prepared in a
+/// working copy and persisted before it is applied, so that a change that cannot be persisted
+/// leaves the registered entities unchanged.
+#[derive(Default)]
+struct WorkingCopy {
+    /// The ids of the copied entities, in the order that they were copied.
+    entity_ids: Vec<String>,
+    /// The copied entities that are registered, keyed by entity id.
+    entity_access_info_map: HashMap<String, EntityAccessInfo>,
+    /// The copied entities' leases.
+    lease_map: LeaseMap,
+    /// The events to publish once the working copy has been applied.
+    entity_events: Vec<EntityEvent>,
+}
+
+impl Default for InvehicleDigitalTwinImpl {
+    fn default() -> Self {
+        InvehicleDigitalTwinImpl {
+            entity_access_info_map: Arc::new(RwLock::new(HashMap::new())),
+            default_registration_policy: RegistrationPolicy::default(),
+            lease_map: Arc::new(RwLock::new(HashMap::new())),
+            entity_event_sender: create_entity_event_sender(),
+            persistence: None,
+            endpoint_health_checker: None,
+            hide_unhealthy_endpoints: false,
+            dtdl_model_catalog: None,
+            change_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
 }
 
 #[tonic::async_trait]
 impl InvehicleDigitalTwin for InvehicleDigitalTwinImpl {
+    type WatchEntitiesStream =
+        Pin<Box<dyn Stream<Item = Result<WatchEntitiesResponse, Status>> + Send>>;
+
     /// Find-by-id implementation.
     ///
     /// # Arguments
@@ -30,10 +119,11 @@ impl InvehicleDigitalTwin for InvehicleDigitalTwinImpl {
         request: Request<FindByIdRequest>,
     ) -> Result<Response<FindByIdResponse>, Status> {
         let entity_id = request.into_inner().id;
+        logging::set_entity_id(&entity_id);
 
         info!("Received a find_by_id request for entity id {entity_id}");
 
-        let entity_access_info;
+        let mut entity_access_info;
 
         // This block controls the lifetime of the lock.
         {
@@ -44,11 +134,14 @@ impl InvehicleDigitalTwin for InvehicleDigitalTwinImpl {
 
         info!("{entity_access_info:?}");
 
-        if entity_access_info.is_none() {
-            return Err(Status::not_found("Unable to find the entity with id {entity_id}"));
-        }
+        let endpoint_health = match entity_access_info.as_mut() {
+            Some(entity_access_info) => self.apply_endpoint_health(entity_access_info),
+            None => {
+                return Err(Status::not_found("Unable to find the entity with id {entity_id}"));
+            }
+        };
 
-        let response = FindByIdResponse { entity_access_info };
+        let response = FindByIdResponse { entity_access_info, endpoint_health };
 
         debug!("Responded to the find_by_id request.");
 
@@ -64,139 +157,2455 @@ impl InvehicleDigitalTwin for InvehicleDigitalTwinImpl {
         request: Request<RegisterRequest>,
     ) -> Result<Response<RegisterResponse>, Status> {
         let request_inner = request.into_inner();
+        let registration_policy =
+            self.resolve_registration_policy(request_inner.registration_policy());
+        let lease_ttl_in_seconds = request_inner.lease_ttl_in_seconds;
+        let entity_access_info_list = request_inner.entity_access_info_list;
 
-        for entity_access_info in &request_inner.entity_access_info_list {
-            info!("Received a register request for the the entity:\n{}", entity_access_info.id);
-
-            self.register_entity(entity_access_info.clone())?;
+        for entity_access_info in &entity_access_info_list {
+            info!("Received a register request for the entity:\n{}", entity_access_info.id);
         }
 
-        let response = RegisterResponse {};
+        // The changes are applied one at a time, each once it has been persisted.
+        let _change_guard = self.change_lock.lock().await;
+
+        let results = if request_inner.atomic {
+            self.register_entities_atomically(
+                &entity_access_info_list,
+                registration_policy,
+                lease_ttl_in_seconds,
+            )
+            .await
+        } else {
+            let mut results = Vec::new();
+            for entity_access_info in &entity_access_info_list {
+                results.push(
+                    self.register_entity(
+                        entity_access_info.clone(),
+                        registration_policy,
+                        lease_ttl_in_seconds,
+                    )
+                    .await,
+                );
+            }
+            results
+        };
+
+        self.take_snapshot_if_due().await;
 
         debug!("Completed the register request.");
 
+        Self::create_register_response(&entity_access_info_list, results)
+    }
+
+    /// Unregister implementation.
+    ///
+    /// # Arguments
+    /// * `request` - Unregister request.
+    async fn unregister(
+        &self,
+        request: Request<UnregisterRequest>,
+    ) -> Result<Response<UnregisterResponse>, Status> {
+        let request_inner = request.into_inner();
+        let unregister_entity_info_list = request_inner.unregister_entity_info_list;
+
+        for unregister_entity_info in &unregister_entity_info_list {
+            info!("Received an unregister request for the entity:\n{}", unregister_entity_info.id);
+        }
+
+        let _change_guard = self.change_lock.lock().await;
+
+        // The whole request is unregistered in one working copy, which is persisted as one change,
+        // so that either all or none of the entities and endpoints are unregistered.
+        let mut working_copy = self.create_working_copy(
+            unregister_entity_info_list
+                .iter()
+                .map(|unregister_entity_info| unregister_entity_info.id.as_str()),
+        );
+        for unregister_entity_info in &unregister_entity_info_list {
+            Self::unregister_entity_in_working_copy(&mut working_copy, unregister_entity_info)?;
+        }
+
+        if !working_copy.entity_ids.is_empty() {
+            // The change is persisted before it is applied.
+            self.persist_working_copy(&working_copy).await?;
+            for unregister_entity_info in &unregister_entity_info_list {
+                debug!("Unregistered entity {}", unregister_entity_info.id);
+            }
+            self.apply_working_copy(working_copy);
+        }
+
+        self.take_snapshot_if_due().await;
+
+        let response = UnregisterResponse {};
+
+        debug!("Completed the unregister request.");
+
         Ok(Response::new(response))
     }
-}
 
-impl InvehicleDigitalTwinImpl {
-    /// Register the entity.
+    /// Renew lease implementation.
     ///
     /// # Arguments
-    /// * `entity` - The entity.
-    fn register_entity(&self, entity_access_info: EntityAccessInfo) -> Result<(), Status> {
+    /// * `request` - Renew lease request.
+    async fn renew_lease(
+        &self,
+        request: Request<RenewLeaseRequest>,
+    ) -> Result<Response<RenewLeaseResponse>, Status> {
+        let request_inner = request.into_inner();
+        let ids = request_inner.ids;
+        let uri = request_inner.uri;
+
+        debug!("Received a renew_lease request for the entities {ids:?}");
+
+        // An empty uri renews all of an entity's leases.
+        let is_renewed = |lease_uri: &String| uri.is_empty() || *lease_uri == uri;
+
+        // A renewal waits for any change that is being persisted, so that applying the change
+        // does not undo the renewal.
+        let _change_guard = self.change_lock.lock().await;
+
         // This block controls the lifetime of the lock.
         {
-            let mut lock: RwLockWriteGuard<HashMap<String, EntityAccessInfo>> =
-                self.entity_access_info_map.write();
-            let get_result = lock.get(&entity_access_info.id);
-            match get_result {
-                Some(_) => {
-                    return Err(Status::unimplemented("The in-vehicle digital twin service does not yet support multiple registrations of the same entity."));
+            let mut lock: RwLockWriteGuard<LeaseMap> = self.lease_map.write();
+
+            // Check all of the leases first, so that either all or none of them are renewed.
+            if let Some(id) = ids.iter().find(|id| {
+                !lock.get(*id).is_some_and(|endpoint_leases| endpoint_leases.keys().any(is_renewed))
+            }) {
+                return Err(Status::not_found(format!(
+                    "Unable to find a lease for the entity with id {id}"
+                )));
+            }
+
+            for id in &ids {
+                if let Some(endpoint_leases) = lock.get_mut(id) {
+                    for (_, lease) in
+                        endpoint_leases.iter_mut().filter(|(lease_uri, _)| is_renewed(*lease_uri))
+                    {
+                        lease.renew();
+                    }
+                }
+            }
+        }
+
+        let response = RenewLeaseResponse {};
+
+        debug!("Completed the renew_lease request.");
+
+        Ok(Response::new(response))
+    }
+
+    /// Watch entities implementation.
+    ///
+    /// # Arguments
+    /// * `request` - Watch entities request.
+    async fn watch_entities(
+        &self,
+        request: Request<WatchEntitiesRequest>,
+    ) -> Result<Response<Self::WatchEntitiesStream>, Status> {
+        let watch_entities_request = request.into_inner();
+
+        info!("Received a watch_entities request: {watch_entities_request:?}");
+
+        // Subscribe before the initial snapshot is taken, so that no change is missed.
+        // A change that is made in between may be reported by both.
+        let mut entity_event_receiver = self.entity_event_sender.subscribe();
+
+        let mut initial_snapshot = Vec::new();
+        if watch_entities_request.include_initial_snapshot {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                self.entity_access_info_map.read();
+            initial_snapshot = lock
+                .values()
+                .filter(|entity_access_info| {
+                    Self::is_watched(&watch_entities_request, &entity_access_info.id)
+                })
+                .cloned()
+                .collect();
+        }
+
+        let (tx, rx) = mpsc::channel(WATCH_ENTITIES_CHANNEL_CAPACITY);
+
+        tokio::spawn(async move {
+            for entity_access_info in initial_snapshot {
+                let response = WatchEntitiesResponse {
+                    kind: WatchEventKind::Added.into(),
+                    entity_access_info: Some(entity_access_info),
+                };
+                if tx.send(Ok(response)).await.is_err() {
+                    debug!("The watch_entities client disconnected.");
+                    return;
                 }
-                None => {
-                    lock.insert(entity_access_info.id.clone(), entity_access_info.clone());
+            }
+
+            loop {
+                let entity_event = tokio::select! {
+                    _ = tx.closed() => break,
+                    result = entity_event_receiver.recv() => result,
+                };
+
+                match entity_event {
+                    Ok(entity_event) => {
+                        if !Self::is_watched(
+                            &watch_entities_request,
+                            &entity_event.entity_access_info.id,
+                        ) {
+                            continue;
+                        }
+
+                        let response = WatchEntitiesResponse {
+                            kind: Self::to_watch_event_kind(entity_event.kind).into(),
+                            entity_access_info: Some(entity_event.entity_access_info),
+                        };
+                        if tx.send(Ok(response)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped_count)) => {
+                        // The client can no longer rely on its view of the entities, so end the
+                        // watch and let it start a new one with an initial snapshot.
+                        warn!("A watch_entities client fell behind by {skipped_count} events.");
+                        let _ = tx
+                            .send(Err(Status::data_loss(format!(
+                                "The watch fell behind by {skipped_count} events"
+                            ))))
+                            .await;
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
                 }
-            };
+            }
+
+            debug!("The watch_entities client disconnected.");
+        });
+
+        let output_stream = ReceiverStream::new(rx);
+        Ok(Response::new(Box::pin(output_stream) as Self::WatchEntitiesStream))
+    }
+
+    /// List entities implementation.
+    ///
+    /// # Arguments
+    /// * `request` - List entities request.
+    async fn list_entities(
+        &self,
+        request: Request<ListEntitiesRequest>,
+    ) -> Result<Response<ListEntitiesResponse>, Status> {
+        let list_entities_request = request.into_inner();
+
+        debug!("Received a list_entities request: {list_entities_request:?}");
+
+        let page_size = match list_entities_request.page_size as usize {
+            0 => DEFAULT_LIST_ENTITIES_PAGE_SIZE,
+            page_size => page_size.min(MAX_LIST_ENTITIES_PAGE_SIZE),
+        };
+
+        let mut matching_entity_access_info_list: Vec<EntityAccessInfo>;
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                self.entity_access_info_map.read();
+            // The unhealthy endpoints are hidden before the filters are applied, so an entity is
+            // not listed for an endpoint that would be hidden from the response.
+            matching_entity_access_info_list = lock
+                .values()
+                .filter(|entity_access_info| {
+                    // The page token is the id of the last entity on the previous page.
+                    entity_access_info.id.as_str() > list_entities_request.page_token.as_str()
+                })
+                .cloned()
+                .map(|mut entity_access_info| {
+                    self.hide_unhealthy_endpoints(&mut entity_access_info);
+                    entity_access_info
+                })
+                .filter(|entity_access_info| {
+                    Self::is_listed(&list_entities_request, entity_access_info)
+                })
+                .collect();
         }
 
-        debug!("Registered entity {}", &entity_access_info.id);
+        matching_entity_access_info_list.sort_by(|a, b| a.id.cmp(&b.id));
 
-        Ok(())
+        let mut next_page_token = String::new();
+        if matching_entity_access_info_list.len() > page_size {
+            matching_entity_access_info_list.truncate(page_size);
+            next_page_token = matching_entity_access_info_list[page_size - 1].id.clone();
+        }
+
+        for entity_access_info in &mut matching_entity_access_info_list {
+            self.apply_endpoint_health(entity_access_info);
+        }
+
+        let response = ListEntitiesResponse {
+            entity_access_info_list: matching_entity_access_info_list,
+            next_page_token,
+        };
+
+        debug!("Completed the list_entities request.");
+
+        Ok(Response::new(response))
     }
 }
 
-#[cfg(test)]
-mod invehicle_digital_twin_impl_tests {
-    use super::*;
-    use core_protobuf_data_access::invehicle_digital_twin::v1::EndpointInfo;
-
-    #[tokio::test]
-    async fn find_by_id_test() {
-        let operations = vec![String::from("Subscribe"), String::from("Unsubscribe")];
+impl InvehicleDigitalTwinImpl {
+    /// Is the entity watched by the watch entities request?
+    ///
+    /// # Arguments
+    /// * `watch_entities_request` - The watch entities request.
+    /// * `entity_id` - The entity's id.
+    fn is_watched(watch_entities_request: &WatchEntitiesRequest, entity_id: &str) -> bool {
+        (watch_entities_request.ids.is_empty()
+            || watch_entities_request.ids.iter().any(|id| id == entity_id))
+            && entity_id.starts_with(&watch_entities_request.id_prefix)
+    }
 
-        let endpoint_info = EndpointInfo {
-            protocol: String::from("grpc"),
-            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
-            context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
-            operations,
+    /// Hide the entity's endpoints that failed their last health check, if the service is
+    /// configured to.
+    ///
+    /// # Arguments
+    /// * `entity_access_info` - The entity's access info.
+    fn hide_unhealthy_endpoints(&self, entity_access_info: &mut EntityAccessInfo) {
+        let Some(endpoint_health_checker) = &self.endpoint_health_checker else {
+            return;
         };
 
-        let entity_access_info = EntityAccessInfo {
-            name: String::from("AmbientAirTemperature"),
-            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
-            description: String::from("Ambient air temperature"),
-            endpoint_info_list: vec![endpoint_info],
+        if self.hide_unhealthy_endpoints {
+            entity_access_info.endpoint_info_list.retain(|endpoint_info| {
+                endpoint_health_checker.get_endpoint_health(&endpoint_info.uri)
+                    != EndpointHealthStatus::Unhealthy
+            });
+        }
+    }
+
+    /// Get the health of the entity's endpoints, keyed by uri, and hide its unhealthy endpoints
+    /// if the service is configured to. The health is empty when health checks are disabled.
+    ///
+    /// # Arguments
+    /// * `entity_access_info` - The entity's access info.
+    fn apply_endpoint_health(
+        &self,
+        entity_access_info: &mut EntityAccessInfo,
+    ) -> HashMap<String, i32> {
+        let Some(endpoint_health_checker) = &self.endpoint_health_checker else {
+            return HashMap::new();
         };
 
-        let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
+        self.hide_unhealthy_endpoints(entity_access_info);
 
-        let invehicle_digital_twin_impl =
-            InvehicleDigitalTwinImpl { entity_access_info_map: entity_access_info_map.clone() };
+        entity_access_info
+            .endpoint_info_list
+            .iter()
+            .map(|endpoint_info| {
+                let endpoint_health =
+                    endpoint_health_checker.get_endpoint_health(&endpoint_info.uri);
+                (endpoint_info.uri.clone(), endpoint_health.into())
+            })
+            .collect()
+    }
 
-        // This block controls the lifetime of the lock.
+    /// Does the entity match the list entities request's filters?
+    ///
+    /// # Arguments
+    /// * `list_entities_request` - The list entities request.
+    /// * `entity_access_info` - The entity's access info.
+    fn is_listed(
+        list_entities_request: &ListEntitiesRequest,
+        entity_access_info: &EntityAccessInfo,
+    ) -> bool {
+        if !entity_access_info.id.starts_with(&list_entities_request.id_prefix) {
+            return false;
+        }
+
+        if list_entities_request.protocol.is_empty() && list_entities_request.operations.is_empty()
         {
-            let mut lock: RwLockWriteGuard<HashMap<String, EntityAccessInfo>> =
-                entity_access_info_map.write();
-            lock.insert(entity_access_info.id.clone(), entity_access_info.clone());
+            return true;
         }
 
-        let request = tonic::Request::new(FindByIdRequest {
-            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
-        });
-        let result = invehicle_digital_twin_impl.find_by_id(request).await;
-        assert!(result.is_ok());
-        let response = result.unwrap();
-        let response_inner = response.into_inner();
+        entity_access_info.endpoint_info_list.iter().any(|endpoint_info| {
+            (list_entities_request.protocol.is_empty()
+                || endpoint_info.protocol == list_entities_request.protocol)
+                && is_subset(&list_entities_request.operations, &endpoint_info.operations)
+        })
+    }
 
-        assert!(response_inner.entity_access_info.is_some());
+    /// Convert an entity event kind to a watch event kind.
+    ///
+    /// # Arguments
+    /// * `entity_event_kind` - The entity event kind.
+    fn to_watch_event_kind(entity_event_kind: EntityEventKind) -> WatchEventKind {
+        match entity_event_kind {
+            EntityEventKind::Added => WatchEventKind::Added,
+            EntityEventKind::Updated => WatchEventKind::Updated,
+            EntityEventKind::Removed => WatchEventKind::Removed,
+        }
+    }
 
-        let response_entity_access_info = response_inner.entity_access_info.unwrap();
+    /// Resolve the registration policy that should be applied to a register request.
+    /// An unspecified policy falls back to the default policy, and an unspecified default policy
+    /// falls back to rejecting re-registrations.
+    ///
+    /// # Arguments
+    /// * `requested_policy` - The policy provided in the register request.
+    fn resolve_registration_policy(
+        &self,
+        requested_policy: RegistrationPolicy,
+    ) -> RegistrationPolicy {
+        match (requested_policy, self.default_registration_policy) {
+            (RegistrationPolicy::Unspecified, RegistrationPolicy::Unspecified) => {
+                RegistrationPolicy::Reject
+            }
+            (RegistrationPolicy::Unspecified, default_policy) => default_policy,
+            (policy, _) => policy,
+        }
+    }
 
-        assert_eq!(
-            response_entity_access_info.id,
-            "dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"
+    /// Validate an entity access info before it is registered.
+    ///
+    /// # Arguments
+    /// * `entity_access_info` - The entity access info.
+    fn validate_entity_access_info(
+        &self,
+        entity_access_info: &EntityAccessInfo,
+    ) -> Result<(), Status> {
+        if let Err(error) = Dtmi::parse(&entity_access_info.id) {
+            return Err(Status::invalid_argument(format!(
+                "Entity id '{}' is not a valid DTMI: {error}",
+                entity_access_info.id
+            )));
+        }
+
+        if let Some(dtdl_model_catalog) = &self.dtdl_model_catalog {
+            let operations: Vec<String> = entity_access_info
+                .endpoint_info_list
+                .iter()
+                .flat_map(|endpoint_info| endpoint_info.operations.clone())
+                .collect();
+            dtdl_model_catalog.validate(&entity_access_info.id, &operations)?;
+        }
+
+        Ok(())
+    }
+
+    /// Register the entity in the map of registered entities.
+    /// Returns the event that should be published once the registration is complete.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_map` - The map of registered entities.
+    /// * `entity_access_info` - The entity access info.
+    /// * `registration_policy` - The policy to apply when the entity is already registered.
+    fn register_entity_in_map(
+        entity_access_info_map: &mut HashMap<String, EntityAccessInfo>,
+        entity_access_info: EntityAccessInfo,
+        registration_policy: RegistrationPolicy,
+    ) -> Result<EntityEvent, Status> {
+        match entity_access_info_map.get_mut(&entity_access_info.id) {
+            Some(registered_entity_access_info) => {
+                match registration_policy {
+                    RegistrationPolicy::Replace => {
+                        *registered_entity_access_info = entity_access_info;
+                    }
+                    RegistrationPolicy::Merge => {
+                        Self::merge_entity_access_info(
+                            registered_entity_access_info,
+                            entity_access_info,
+                        );
+                    }
+                    RegistrationPolicy::Reject | RegistrationPolicy::Unspecified => {
+                        return Err(Status::already_exists(format!(
+                            "The entity with id {} is already registered.",
+                            entity_access_info.id
+                        )));
+                    }
+                };
+
+                Ok(EntityEvent {
+                    kind: EntityEventKind::Updated,
+                    entity_access_info: registered_entity_access_info.clone(),
+                })
+            }
+            None => {
+                entity_access_info_map
+                    .insert(entity_access_info.id.clone(), entity_access_info.clone());
+
+                Ok(EntityEvent { kind: EntityEventKind::Added, entity_access_info })
+            }
+        }
+    }
+
+    /// Register the entity. Its new state is persisted before it is applied.
+    ///
+    /// # Arguments
+    /// * `entity_access_info` - The entity.
+    /// * `registration_policy` - The policy to apply when the entity is already registered.
+    /// * `lease_ttl_in_seconds` - The time-to-live in seconds of the registered endpoints' leases.
+    async fn register_entity(
+        &self,
+        entity_access_info: EntityAccessInfo,
+        registration_policy: RegistrationPolicy,
+        lease_ttl_in_seconds: u32,
+    ) -> Result<(), Status> {
+        self.validate_entity_access_info(&entity_access_info)?;
+
+        let entity_id = entity_access_info.id.clone();
+
+        let mut working_copy = self.create_working_copy([entity_id.as_str()]);
+        Self::register_entity_in_working_copy(
+            &mut working_copy,
+            entity_access_info,
+            registration_policy,
+            lease_ttl_in_seconds,
+        )?;
+        self.persist_working_copy(&working_copy).await?;
+        self.apply_working_copy(working_copy);
+
+        debug!("Registered entity {entity_id}");
+
+        Ok(())
+    }
+
+    /// Register the entity in the working copy, and set the leases of the endpoints that were
+    /// registered.
+    ///
+    /// # Arguments
+    /// * `working_copy` - The working copy, which holds the entity if it is already registered.
+    /// * `entity_access_info` - The entity.
+    /// * `registration_policy` - The policy to apply when the entity is already registered.
+    /// * `lease_ttl_in_seconds` - The time-to-live in seconds of the registered endpoints' leases.
+    fn register_entity_in_working_copy(
+        working_copy: &mut WorkingCopy,
+        entity_access_info: EntityAccessInfo,
+        registration_policy: RegistrationPolicy,
+        lease_ttl_in_seconds: u32,
+    ) -> Result<(), Status> {
+        let uris: Vec<String> = entity_access_info
+            .endpoint_info_list
+            .iter()
+            .map(|endpoint_info| endpoint_info.uri.clone())
+            .collect();
+
+        let entity_event = Self::register_entity_in_map(
+            &mut working_copy.entity_access_info_map,
+            entity_access_info,
+            registration_policy,
+        )?;
+        Self::set_leases(
+            &mut working_copy.lease_map,
+            &entity_event.entity_access_info,
+            &uris,
+            lease_ttl_in_seconds,
         );
-        assert_eq!(response_entity_access_info.endpoint_info_list.len(), 1);
-        assert_eq!(
-            response_entity_access_info.endpoint_info_list[0].uri,
-            "http://[::1]:40010" // Devskim: ignore DS137138
+        working_copy.entity_events.push(entity_event);
+
+        Ok(())
+    }
+
+    /// Register all of the entities, or none of them if any of them cannot be registered.
+    /// Returns the outcome for each entity. When the batch fails, the entities that could have
+    /// been registered have an aborted status.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_list` - The entities.
+    /// * `registration_policy` - The policy to apply when an entity is already registered.
+    /// * `lease_ttl_in_seconds` - The time-to-live in seconds of the registered endpoints' leases.
+    async fn register_entities_atomically(
+        &self,
+        entity_access_info_list: &[EntityAccessInfo],
+        registration_policy: RegistrationPolicy,
+        lease_ttl_in_seconds: u32,
+    ) -> Vec<Result<(), Status>> {
+        let validation_results: Vec<Result<(), Status>> = entity_access_info_list
+            .iter()
+            .map(|entity_access_info| self.validate_entity_access_info(entity_access_info))
+            .collect();
+        if validation_results.iter().any(Result::is_err) {
+            return Self::abort_batch(validation_results);
+        }
+
+        // The whole batch is registered in the working copy, so an entity that appears more than
+        // once in the batch is registered on top of its earlier registration.
+        let mut working_copy = self.create_working_copy(
+            entity_access_info_list.iter().map(|entity_access_info| entity_access_info.id.as_str()),
         );
+        let results: Vec<Result<(), Status>> = entity_access_info_list
+            .iter()
+            .map(|entity_access_info| {
+                Self::register_entity_in_working_copy(
+                    &mut working_copy,
+                    entity_access_info.clone(),
+                    registration_policy,
+                    lease_ttl_in_seconds,
+                )
+            })
+            .collect();
+        if results.iter().any(Result::is_err) {
+            return Self::abort_batch(results);
+        }
+
+        if let Err(status) = self.persist_working_copy(&working_copy).await {
+            return results.into_iter().map(|_| Err(status.clone())).collect();
+        }
+
+        for entity_access_info in entity_access_info_list {
+            debug!("Registered entity {}", entity_access_info.id);
+        }
+        self.apply_working_copy(working_copy);
+
+        results
     }
 
-    #[tokio::test]
-    async fn register_test() {
-        let endpoint_info = EndpointInfo {
-            protocol: String::from("grpc"),
-            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
-            context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
-            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
-        };
+    /// Replace the successful outcomes in a failed batch with aborted statuses.
+    ///
+    /// # Arguments
+    /// * `results` - The outcome for each entity in the batch.
+    fn abort_batch(results: Vec<Result<(), Status>>) -> Vec<Result<(), Status>> {
+        results
+            .into_iter()
+            .map(|result| {
+                result.and(Err(Status::aborted(
+                    "The entity was not registered, because another entity in the batch could not be registered.",
+                )))
+            })
+            .collect()
+    }
 
-        let entity_access_info = EntityAccessInfo {
-            name: String::from("AmbientAirTemperature"),
-            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
-            description: String::from("Ambient air temperature"),
-            endpoint_info_list: vec![endpoint_info],
+    /// Create the register response from the outcome of each entity's registration.
+    /// When any entity was not registered, the first failure's status is returned instead. Its
+    /// details are a google.rpc.Status that carries the register response.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_list` - The entities in the register request.
+    /// * `results` - The outcome for each entity.
+    fn create_register_response(
+        entity_access_info_list: &[EntityAccessInfo],
+        results: Vec<Result<(), Status>>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let mut first_failure: Option<(Code, String)> = None;
+
+        let entity_status_list = entity_access_info_list
+            .iter()
+            .zip(results)
+            .map(|(entity_access_info, result)| {
+                let (code, message) = match result {
+                    Ok(()) => (Code::Ok, String::new()),
+                    Err(status) => (status.code(), status.message().to_string()),
+                };
+                if code != Code::Ok && first_failure.is_none() {
+                    first_failure = Some((code, message.clone()));
+                }
+                EntityRegistrationStatus {
+                    id: entity_access_info.id.clone(),
+                    code: code as i32,
+                    message,
+                }
+            })
+            .collect();
+
+        let response = RegisterResponse { entity_status_list };
+
+        match first_failure {
+            None => Ok(Response::new(response)),
+            Some((code, message)) => Err(status_with_typed_details(
+                code,
+                message,
+                REGISTER_RESPONSE_TYPE_NAME,
+                &response,
+            )),
+        }
+    }
+
+    /// Set the leases of the endpoints that were registered, or the entity's own lease when it was
+    /// registered without endpoints. A time-to-live of zero means that they do not have a lease.
+    /// Other providers' endpoints keep their leases, so that they are evicted independently.
+    ///
+    /// # Arguments
+    /// * `lease_map` - The leases.
+    /// * `entity_access_info` - The entity's registered access info, after the registration.
+    /// * `uris` - The uris of the endpoints that were registered.
+    /// * `lease_ttl_in_seconds` - The leases' time-to-live in seconds.
+    fn set_leases(
+        lease_map: &mut LeaseMap,
+        entity_access_info: &EntityAccessInfo,
+        uris: &[String],
+        lease_ttl_in_seconds: u32,
+    ) {
+        let entity_id = &entity_access_info.id;
+        let endpoint_leases = lease_map.entry(entity_id.clone()).or_default();
+
+        // An entity that was registered without endpoints has its lease under an empty uri.
+        let empty_uri = [String::new()];
+        let uris = if uris.is_empty() { &empty_uri[..] } else { uris };
+
+        for uri in uris {
+            if lease_ttl_in_seconds > 0 {
+                let lease = Lease::new(Duration::from_secs(u64::from(lease_ttl_in_seconds)));
+                endpoint_leases.insert(uri.clone(), lease);
+            } else {
+                endpoint_leases.remove(uri);
+            }
+        }
+
+        // A replaced entity may no longer have some of the endpoints that had a lease.
+        Self::prune_leases(lease_map, entity_id, Some(entity_access_info));
+    }
+
+    /// Drop the leases of the endpoints that the entity no longer has, or all of its leases when it
+    /// is no longer registered.
+    ///
+    /// # Arguments
+    /// * `lease_map` - The leases.
+    /// * `entity_id` - The entity's id.
+    /// * `entity_access_info` - The entity's registered access info, if it is still registered.
+    fn prune_leases(
+        lease_map: &mut LeaseMap,
+        entity_id: &str,
+        entity_access_info: Option<&EntityAccessInfo>,
+    ) {
+        let Some(endpoint_leases) = lease_map.get_mut(entity_id) else {
+            return;
         };
 
-        let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
+        match entity_access_info {
+            Some(entity_access_info) => endpoint_leases.retain(|uri, _| {
+                uri.is_empty()
+                    || entity_access_info
+                        .endpoint_info_list
+                        .iter()
+                        .any(|endpoint_info| endpoint_info.uri == *uri)
+            }),
+            None => endpoint_leases.clear(),
+        }
 
-        let invehicle_digital_twin_impl =
-            InvehicleDigitalTwinImpl { entity_access_info_map: entity_access_info_map.clone() };
+        if endpoint_leases.is_empty() {
+            lease_map.remove(entity_id);
+        }
+    }
 
-        let request = tonic::Request::new(RegisterRequest {
-            entity_access_info_list: vec![entity_access_info],
-        });
-        let result = invehicle_digital_twin_impl.register(request).await;
-        assert!(result.is_ok(), "register result is not okay: {result:?}");
+    /// Enable durable persistence in the directory, and restore the entities that were persisted
+    /// in it. This should be called before the service starts serving requests.
+    ///
+    /// # Arguments
+    /// * `directory` - The directory that holds the snapshot and the journal.
+    /// * `snapshot_threshold` - The number of journal records after which a snapshot should be taken.
+    pub fn enable_persistence(
+        &mut self,
+        directory: &Path,
+        snapshot_threshold: usize,
+    ) -> Result<(), io::Error> {
+        let (persistence, persisted_entity_map) =
+            EntityPersistence::open(directory, snapshot_threshold)?;
+        self.restore_entities(persisted_entity_map);
+        self.persistence = Some(Arc::new(Mutex::new(persistence)));
 
-        // This block controls the lifetime of the lock.
-        {
-            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
-                entity_access_info_map.read();
+        Ok(())
+    }
+
+    /// Restore the entities that were recovered from durable persistence.
+    ///
+    /// # Arguments
+    /// * `persisted_entity_map` - The recovered entities, keyed by id.
+    fn restore_entities(&self, persisted_entity_map: HashMap<String, PersistedEntity>) {
+        for (entity_id, persisted_entity) in persisted_entity_map {
+            if let Some(entity_access_info) = persisted_entity.entity_access_info {
+                self.entity_access_info_map.write().insert(entity_id.clone(), entity_access_info);
+
+                // Restored leases start over, so that providers have a chance to renew them.
+                let endpoint_leases: HashMap<String, Lease> = persisted_entity
+                    .endpoint_lease_ttl_in_seconds
+                    .into_iter()
+                    .filter(|(_, lease_ttl_in_seconds)| *lease_ttl_in_seconds > 0)
+                    .map(|(uri, lease_ttl_in_seconds)| {
+                        (uri, Lease::new(Duration::from_secs(u64::from(lease_ttl_in_seconds))))
+                    })
+                    .collect();
+                if !endpoint_leases.is_empty() {
+                    self.lease_map.write().insert(entity_id, endpoint_leases);
+                }
+            }
+        }
+    }
+
+    /// Copy the entities and their leases into a working copy.
+    ///
+    /// # Arguments
+    /// * `entity_ids` - The ids of the entities to copy. An id may appear more than once.
+    fn create_working_copy<'a>(
+        &self,
+        entity_ids: impl IntoIterator<Item = &'a str>,
+    ) -> WorkingCopy {
+        let mut working_copy = WorkingCopy::default();
+
+        let entity_lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+            self.entity_access_info_map.read();
+        let lease_lock: RwLockReadGuard<LeaseMap> = self.lease_map.read();
+
+        for entity_id in entity_ids {
+            if working_copy.entity_ids.iter().any(|id| id == entity_id) {
+                continue;
+            }
+
+            working_copy.entity_ids.push(entity_id.to_string());
+            if let Some(entity_access_info) = entity_lock.get(entity_id) {
+                working_copy
+                    .entity_access_info_map
+                    .insert(entity_id.to_string(), entity_access_info.clone());
+            }
+            if let Some(endpoint_leases) = lease_lock.get(entity_id) {
+                working_copy.lease_map.insert(entity_id.to_string(), endpoint_leases.clone());
+            }
+        }
+
+        working_copy
+    }
+
+    /// Persist the state of the entities in the working copy, when persistence is enabled.
+    /// The journal is written on a blocking thread, without holding the locks on the registered
+    /// entities. The change lock must be held, so that the records are appended in the same order
+    /// as the changes are applied.
+    ///
+    /// # Arguments
+    /// * `working_copy` - The working copy.
+    async fn persist_working_copy(&self, working_copy: &WorkingCopy) -> Result<(), Status> {
+        let Some(persistence) = self.persistence.clone() else {
+            return Ok(());
+        };
+
+        let persisted_entities: Vec<PersistedEntity> = working_copy
+            .entity_ids
+            .iter()
+            .map(|entity_id| {
+                Self::create_persisted_entity(
+                    entity_id,
+                    &working_copy.entity_access_info_map,
+                    &working_copy.lease_map,
+                )
+            })
+            .collect();
+
+        // The entities are appended as one record, so that they are persisted all together or not
+        // at all.
+        tokio::task::spawn_blocking(move || persistence.lock().append(&persisted_entities))
+            .await
+            .map_err(|error| Status::internal(format!("The persistence task failed: {error}")))?
+            .map_err(|error| {
+                Status::internal(format!(
+                    "Failed to persist the entities with ids {:?}: {error}",
+                    working_copy.entity_ids
+                ))
+            })
+    }
+
+    /// Apply the working copy to the registered entities and their leases, and publish the
+    /// working copy's events.
+    ///
+    /// # Arguments
+    /// * `working_copy` - The working copy.
+    fn apply_working_copy(&self, working_copy: WorkingCopy) {
+        let WorkingCopy { entity_ids, mut entity_access_info_map, mut lease_map, entity_events } =
+            working_copy;
+
+        // This block controls the lifetime of the locks.
+        {
+            let mut entity_lock: RwLockWriteGuard<HashMap<String, EntityAccessInfo>> =
+                self.entity_access_info_map.write();
+            let mut lease_lock: RwLockWriteGuard<LeaseMap> = self.lease_map.write();
+
+            for entity_id in entity_ids {
+                match entity_access_info_map.remove(&entity_id) {
+                    Some(entity_access_info) => {
+                        entity_lock.insert(entity_id.clone(), entity_access_info);
+                    }
+                    None => {
+                        entity_lock.remove(&entity_id);
+                    }
+                }
+                match lease_map.remove(&entity_id) {
+                    Some(endpoint_leases) => {
+                        lease_lock.insert(entity_id, endpoint_leases);
+                    }
+                    None => {
+                        lease_lock.remove(&entity_id);
+                    }
+                }
+            }
+        }
+
+        for entity_event in entity_events {
+            self.publish_entity_event(entity_event);
+        }
+    }
+
+    /// Take a snapshot of all of the registered entities, when persistence is enabled and a
+    /// snapshot is due. The snapshot is written on a blocking thread. The change lock must be held,
+    /// so that the snapshot includes every change in the journal.
+    async fn take_snapshot_if_due(&self) {
+        let Some(persistence) = self.persistence.clone() else {
+            return;
+        };
+
+        if !persistence.lock().is_snapshot_due() {
+            return;
+        }
+
+        let persisted_entities: Vec<PersistedEntity>;
+
+        // This block controls the lifetime of the locks.
+        {
+            let entity_lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                self.entity_access_info_map.read();
+            let lease_lock: RwLockReadGuard<LeaseMap> = self.lease_map.read();
+            persisted_entities = entity_lock
+                .keys()
+                .map(|entity_id| {
+                    Self::create_persisted_entity(entity_id, &entity_lock, &lease_lock)
+                })
+                .collect();
+        }
+
+        let result = tokio::task::spawn_blocking(move || {
+            persistence.lock().take_snapshot(persisted_entities.iter())
+        })
+        .await;
+
+        // The journal is still intact when a snapshot fails, so nothing is lost.
+        match result {
+            Ok(Ok(())) => (),
+            Ok(Err(error)) => {
+                warn!("Failed to take a snapshot of the registered entities: {error}")
+            }
+            Err(error) => warn!("The snapshot task failed: {error}"),
+        }
+    }
+
+    /// Create the persisted record of an entity's current state.
+    ///
+    /// # Arguments
+    /// * `entity_id` - The entity's id.
+    /// * `entity_access_info_map` - The registered entities.
+    /// * `lease_map` - The registered endpoints' leases.
+    fn create_persisted_entity(
+        entity_id: &str,
+        entity_access_info_map: &HashMap<String, EntityAccessInfo>,
+        lease_map: &LeaseMap,
+    ) -> PersistedEntity {
+        PersistedEntity {
+            id: entity_id.to_string(),
+            entity_access_info: entity_access_info_map.get(entity_id).cloned(),
+            endpoint_lease_ttl_in_seconds: lease_map
+                .get(entity_id)
+                .map(|endpoint_leases| {
+                    endpoint_leases
+                        .iter()
+                        .map(|(uri, lease)| {
+                            (uri.clone(), u32::try_from(lease.ttl.as_secs()).unwrap_or(u32::MAX))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Publish an entity event. It is fine for there to be no receivers.
+    ///
+    /// # Arguments
+    /// * `entity_event` - The entity event.
+    fn publish_entity_event(&self, entity_event: EntityEvent) {
+        // An error only means that there are currently no receivers.
+        let _ = self.entity_event_sender.send(entity_event);
+    }
+
+    /// Evict the endpoints whose leases have expired. An entity that is left without endpoints is
+    /// removed. The eviction is persisted before it is applied. Returns the ids of the entities
+    /// that were changed.
+    ///
+    /// # Arguments
+    /// * `now` - The instant to compare the leases' expiry with.
+    pub async fn evict_expired_entities(&self, now: Instant) -> Vec<String> {
+        let _change_guard = self.change_lock.lock().await;
+
+        let expired_leases: Vec<(String, Vec<String>)> = self
+            .lease_map
+            .read()
+            .iter()
+            .filter_map(|(entity_id, endpoint_leases)| {
+                let expired_uris: Vec<String> = endpoint_leases
+                    .iter()
+                    .filter(|(_, lease)| lease.is_expired(now))
+                    .map(|(uri, _)| uri.clone())
+                    .collect();
+                (!expired_uris.is_empty()).then(|| (entity_id.clone(), expired_uris))
+            })
+            .collect();
+
+        if expired_leases.is_empty() {
+            return Vec::new();
+        }
+
+        let mut working_copy = self
+            .create_working_copy(expired_leases.iter().map(|(entity_id, _)| entity_id.as_str()));
+
+        for (entity_id, expired_uris) in &expired_leases {
+            if let Some(endpoint_leases) = working_copy.lease_map.get_mut(entity_id) {
+                for uri in expired_uris {
+                    endpoint_leases.remove(uri);
+                }
+            }
+
+            if let Some(entity_access_info) = working_copy.entity_access_info_map.get_mut(entity_id)
+            {
+                let original_len = entity_access_info.endpoint_info_list.len();
+                entity_access_info
+                    .endpoint_info_list
+                    .retain(|endpoint_info| !expired_uris.contains(&endpoint_info.uri));
+
+                if entity_access_info.endpoint_info_list.is_empty() {
+                    working_copy.entity_events.push(EntityEvent {
+                        kind: EntityEventKind::Removed,
+                        entity_access_info: entity_access_info.clone(),
+                    });
+                    working_copy.entity_access_info_map.remove(entity_id);
+                } else if entity_access_info.endpoint_info_list.len() < original_len {
+                    working_copy.entity_events.push(EntityEvent {
+                        kind: EntityEventKind::Updated,
+                        entity_access_info: entity_access_info.clone(),
+                    });
+                }
+            }
+
+            Self::prune_leases(
+                &mut working_copy.lease_map,
+                entity_id,
+                working_copy.entity_access_info_map.get(entity_id),
+            );
+        }
+
+        // The leases stay expired, so the eviction is retried at the next check.
+        if let Err(error) = self.persist_working_copy(&working_copy).await {
+            warn!("Failed to persist the eviction of expired leases: {error}");
+            return Vec::new();
+        }
+
+        let mut changed_entity_ids = Vec::new();
+        for entity_event in &working_copy.entity_events {
+            let entity_id = &entity_event.entity_access_info.id;
+            if entity_event.kind == EntityEventKind::Removed {
+                info!("The lease for entity {entity_id} has expired, so it was removed.");
+            } else {
+                info!("The leases for some of the endpoints of entity {entity_id} have expired, so they were removed.");
+            }
+            changed_entity_ids.push(entity_id.clone());
+        }
+
+        self.apply_working_copy(working_copy);
+        self.take_snapshot_if_due().await;
+
+        changed_entity_ids
+    }
+
+    /// Report the number of registered entities and leases in the metrics.
+    ///
+    /// # Arguments
+    /// * `registry` - The registry to register the metrics in.
+    pub fn register_metrics(&self, registry: &Registry) -> Result<(), prometheus::Error> {
+        let entity_access_info_map = self.entity_access_info_map.clone();
+        metrics::register_int_gauge_fn(
+            registry,
+            "invehicle_digital_twin_entities",
+            "The number of entities that are registered with the In-Vehicle Digital Twin Service.",
+            move || entity_access_info_map.read().len() as i64,
+        )?;
+
+        let lease_map = self.lease_map.clone();
+        metrics::register_int_gauge_fn(
+            registry,
+            "invehicle_digital_twin_leases",
+            "The number of leases that the In-Vehicle Digital Twin Service holds for providers' endpoints.",
+            move || lease_map.read().values().map(HashMap::len).sum::<usize>() as i64,
+        )
+    }
+
+    /// Start the background task that periodically evicts the entities whose leases have expired.
+    ///
+    /// # Arguments
+    /// * `eviction_interval` - The interval between checks for expired leases.
+    pub fn start_lease_eviction(&self, eviction_interval: Duration) -> JoinHandle<()> {
+        let invehicle_digital_twin_impl = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(eviction_interval);
+            loop {
+                interval.tick().await;
+                invehicle_digital_twin_impl.evict_expired_entities(Instant::now()).await;
+            }
+        })
+    }
+
+    /// Start the background task that periodically logs which of the DTDL models' properties,
+    /// telemetry and commands have no registered provider. The summary is only logged when it
+    /// changes. Nothing is logged when no DTDL models have been loaded. Only the entities that are
+    /// registered with this service count as provided; the entries that are registered with the
+    /// digital twin registry module are left out.
+    ///
+    /// # Arguments
+    /// * `summary_interval` - The interval between checks for a change in the summary.
+    pub fn start_dtdl_model_summary(&self, summary_interval: Duration) -> Option<JoinHandle<()>> {
+        let dtdl_model_catalog = self.dtdl_model_catalog.clone()?;
+        let entity_access_info_map = self.entity_access_info_map.clone();
+
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(summary_interval);
+            let mut previous_unprovided_ids = None;
+            loop {
+                interval.tick().await;
+
+                let unprovided_ids = {
+                    let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                        entity_access_info_map.read();
+                    dtdl_model_catalog.get_unprovided_content_ids(|id| lock.contains_key(id))
+                };
+
+                if previous_unprovided_ids.as_ref() != Some(&unprovided_ids) {
+                    if unprovided_ids.is_empty() {
+                        info!("All of the DTDL models' properties, telemetry and commands have a provider.");
+                    } else {
+                        info!(
+                            "{} of the DTDL models' properties, telemetry and commands have no provider: {unprovided_ids:?}",
+                            unprovided_ids.len()
+                        );
+                    }
+                    previous_unprovided_ids = Some(unprovided_ids);
+                }
+            }
+        }))
+    }
+
+    /// Merge an entity access info into a registered entity access info.
+    /// The name and description are replaced. Endpoints with the same protocol and uri as a new
+    /// endpoint are replaced by it, all other new endpoints are added.
+    ///
+    /// # Arguments
+    /// * `registered_entity_access_info` - The registered entity access info.
+    /// * `entity_access_info` - The entity access info to merge into the registered one.
+    fn merge_entity_access_info(
+        registered_entity_access_info: &mut EntityAccessInfo,
+        entity_access_info: EntityAccessInfo,
+    ) {
+        registered_entity_access_info.name = entity_access_info.name;
+        registered_entity_access_info.description = entity_access_info.description;
+
+        for endpoint_info in entity_access_info.endpoint_info_list {
+            let registered_endpoint_info = registered_entity_access_info
+                .endpoint_info_list
+                .iter_mut()
+                .find(|registered_endpoint_info| {
+                    registered_endpoint_info.protocol == endpoint_info.protocol
+                        && registered_endpoint_info.uri == endpoint_info.uri
+                });
+
+            match registered_endpoint_info {
+                Some(registered_endpoint_info) => *registered_endpoint_info = endpoint_info,
+                None => registered_entity_access_info.endpoint_info_list.push(endpoint_info),
+            }
+        }
+    }
+
+    /// Unregister the entity, or one of its endpoints, in a working copy that holds it.
+    /// When the uri is empty, the entire entity is removed, and a protocol must not be provided.
+    /// Otherwise, only the endpoints that match the uri (and the protocol, if one is provided) are
+    /// removed. An entity that is left without any endpoints is removed.
+    ///
+    /// # Arguments
+    /// * `working_copy` - The working copy.
+    /// * `unregister_entity_info` - The entity (and optionally the endpoint) to unregister.
+    fn unregister_entity_in_working_copy(
+        working_copy: &mut WorkingCopy,
+        unregister_entity_info: &UnregisterEntityInfo,
+    ) -> Result<(), Status> {
+        let entity_id = &unregister_entity_info.id;
+
+        if entity_id.is_empty() {
+            return Err(Status::invalid_argument("Entity id is required"));
+        }
+
+        if unregister_entity_info.uri.is_empty() && !unregister_entity_info.protocol.is_empty() {
+            return Err(Status::invalid_argument(format!(
+                "A protocol was provided without a uri for the entity with id {entity_id}"
+            )));
+        }
+
+        let entity_event;
+
+        // This block controls the lifetime of the working copy's borrow.
+        {
+            let entity_access_info_map = &mut working_copy.entity_access_info_map;
+
+            let entity_access_info =
+                entity_access_info_map.get_mut(entity_id).ok_or_else(|| {
+                    Status::not_found(format!("Unable to find the entity with id {entity_id}"))
+                })?;
+
+            if !unregister_entity_info.uri.is_empty() {
+                let original_len = entity_access_info.endpoint_info_list.len();
+
+                entity_access_info.endpoint_info_list.retain(|endpoint_info| {
+                    endpoint_info.uri != unregister_entity_info.uri
+                        || (!unregister_entity_info.protocol.is_empty()
+                            && endpoint_info.protocol != unregister_entity_info.protocol)
+                });
+
+                if entity_access_info.endpoint_info_list.len() == original_len {
+                    return Err(Status::not_found(format!(
+                        "Unable to find the endpoint with uri {} for the entity with id {entity_id}",
+                        unregister_entity_info.uri
+                    )));
+                }
+            }
+
+            if unregister_entity_info.uri.is_empty()
+                || entity_access_info.endpoint_info_list.is_empty()
+            {
+                entity_event = EntityEvent {
+                    kind: EntityEventKind::Removed,
+                    entity_access_info: entity_access_info.clone(),
+                };
+                entity_access_info_map.remove(entity_id);
+            } else {
+                entity_event = EntityEvent {
+                    kind: EntityEventKind::Updated,
+                    entity_access_info: entity_access_info.clone(),
+                };
+            }
+
+            Self::prune_leases(
+                &mut working_copy.lease_map,
+                entity_id,
+                entity_access_info_map.get(entity_id),
+            );
+        }
+        working_copy.entity_events.push(entity_event);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod invehicle_digital_twin_impl_tests {
+    use super::*;
+    use common::grpc_status_details::get_typed_details;
+    use core_protobuf_data_access::invehicle_digital_twin::v1::EndpointInfo;
+    use tokio_stream::StreamExt;
+
+    /// Register the entity access info with the provided policy.
+    ///
+    /// # Arguments
+    /// * `invehicle_digital_twin_impl` - The in-vehicle digital twin service implementation.
+    /// * `entity_access_info` - The entity access info to register.
+    /// * `registration_policy` - The registration policy to include in the request.
+    async fn register_with_policy(
+        invehicle_digital_twin_impl: &InvehicleDigitalTwinImpl,
+        entity_access_info: EntityAccessInfo,
+        registration_policy: RegistrationPolicy,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info],
+            registration_policy: registration_policy.into(),
+            ..Default::default()
+        });
+        invehicle_digital_twin_impl.register(request).await
+    }
+
+    #[tokio::test]
+    async fn find_by_id_test() {
+        let operations = vec![String::from("Subscribe"), String::from("Unsubscribe")];
+
+        let endpoint_info = EndpointInfo {
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            operations,
+        };
+
+        let entity_access_info = EntityAccessInfo {
+            name: String::from("AmbientAirTemperature"),
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from("Ambient air temperature"),
+            endpoint_info_list: vec![endpoint_info],
+        };
+
+        let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
+
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
+
+        // This block controls the lifetime of the lock.
+        {
+            let mut lock: RwLockWriteGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.write();
+            lock.insert(entity_access_info.id.clone(), entity_access_info.clone());
+        }
+
+        let request = tonic::Request::new(FindByIdRequest {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+        });
+        let result = invehicle_digital_twin_impl.find_by_id(request).await;
+        assert!(result.is_ok());
+        let response = result.unwrap();
+        let response_inner = response.into_inner();
+
+        assert!(response_inner.entity_access_info.is_some());
+
+        let response_entity_access_info = response_inner.entity_access_info.unwrap();
+
+        assert_eq!(
+            response_entity_access_info.id,
+            "dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"
+        );
+        assert_eq!(response_entity_access_info.endpoint_info_list.len(), 1);
+        assert_eq!(
+            response_entity_access_info.endpoint_info_list[0].uri,
+            "http://[::1]:40010" // Devskim: ignore DS137138
+        );
+    }
+
+    #[test]
+    fn register_metrics_test() {
+        let registry = Registry::new();
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+        invehicle_digital_twin_impl.register_metrics(&registry).unwrap();
+        invehicle_digital_twin_impl.entity_access_info_map.write().insert(
+            String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            EntityAccessInfo::default(),
+        );
+
+        let encoded_metrics = metrics::encode_metrics(&registry).unwrap();
+        assert!(encoded_metrics.contains("invehicle_digital_twin_entities 1"));
+        assert!(encoded_metrics.contains("invehicle_digital_twin_leases 0"));
+    }
+
+    #[tokio::test]
+    async fn register_test() {
+        let endpoint_info = EndpointInfo {
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+        };
+
+        let entity_access_info = EntityAccessInfo {
+            name: String::from("AmbientAirTemperature"),
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from("Ambient air temperature"),
+            endpoint_info_list: vec![endpoint_info],
+        };
+
+        let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
+
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.read();
             // Make sure that we populated the entity map from the contents of the DTDL.
             assert_eq!(lock.len(), 1, "expected length was 1, actual length is {}", lock.len());
         }
     }
+
+    #[tokio::test]
+    async fn unregister_test() {
+        let endpoint_info = EndpointInfo {
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+        };
+
+        let entity_access_info = EntityAccessInfo {
+            name: String::from("AmbientAirTemperature"),
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from("Ambient air temperature"),
+            endpoint_info_list: vec![endpoint_info],
+        };
+
+        let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
+
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
+
+        // This block controls the lifetime of the lock.
+        {
+            let mut lock: RwLockWriteGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.write();
+            lock.insert(entity_access_info.id.clone(), entity_access_info.clone());
+        }
+
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![UnregisterEntityInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                protocol: String::new(),
+                uri: String::new(),
+            }],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert!(result.is_ok(), "unregister result is not okay: {result:?}");
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.read();
+            assert!(lock.is_empty(), "expected length was 0, actual length is {}", lock.len());
+        }
+
+        // Unregistering an entity that is not registered should fail.
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![UnregisterEntityInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                protocol: String::new(),
+                uri: String::new(),
+            }],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn unregister_all_or_none_test() {
+        let entity_access_info = EntityAccessInfo {
+            name: String::from("AmbientAirTemperature"),
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from("Ambient air temperature"),
+            endpoint_info_list: Vec::new(),
+        };
+
+        let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
+        entity_access_info_map.write().insert(entity_access_info.id.clone(), entity_access_info);
+
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
+        let mut entity_event_receiver = invehicle_digital_twin_impl.entity_event_sender.subscribe();
+
+        // The registered entity is not unregistered, because the other entity is not registered.
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![
+                UnregisterEntityInfo {
+                    id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                    ..Default::default()
+                },
+                UnregisterEntityInfo {
+                    id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:IsAirConditioningActive;1"),
+                    ..Default::default()
+                },
+            ],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::NotFound);
+        assert_eq!(entity_access_info_map.read().len(), 1);
+        assert!(entity_event_receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn unregister_endpoint_test() {
+        let grpc_endpoint_info = EndpointInfo {
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            context: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+        };
+
+        let mqtt_endpoint_info = EndpointInfo {
+            protocol: String::from("mqtt"),
+            uri: String::from("tcp://[::1]:1883"),
+            context: String::from("AmbientAirTemperature"),
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+        };
+
+        let entity_access_info = EntityAccessInfo {
+            name: String::from("AmbientAirTemperature"),
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from("Ambient air temperature"),
+            endpoint_info_list: vec![grpc_endpoint_info, mqtt_endpoint_info],
+        };
+
+        let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
+
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
+
+        // This block controls the lifetime of the lock.
+        {
+            let mut lock: RwLockWriteGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.write();
+            lock.insert(entity_access_info.id.clone(), entity_access_info.clone());
+        }
+
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![UnregisterEntityInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                protocol: String::from("grpc"),
+                uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            }],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert!(result.is_ok(), "unregister result is not okay: {result:?}");
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.read();
+            let remaining_entity_access_info =
+                lock.get("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1").unwrap();
+            assert_eq!(remaining_entity_access_info.endpoint_info_list.len(), 1);
+            assert_eq!(remaining_entity_access_info.endpoint_info_list[0].protocol, "mqtt");
+        }
+
+        // A protocol without a uri is rejected, rather than removing the entire entity.
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![UnregisterEntityInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                protocol: String::from("mqtt"),
+                uri: String::new(),
+            }],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::InvalidArgument);
+        assert_eq!(entity_access_info_map.read().len(), 1);
+
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![UnregisterEntityInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                protocol: String::new(),
+                uri: String::from("tcp://[::1]:1883"),
+            }],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert!(result.is_ok(), "unregister result is not okay: {result:?}");
+
+        // The entity no longer has any endpoints, so it should have been removed.
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                entity_access_info_map.read();
+            assert!(lock.is_empty(), "expected length was 0, actual length is {}", lock.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn register_with_reject_policy_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            ..Default::default()
+        };
+
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            entity_access_info.clone(),
+            RegistrationPolicy::Reject,
+        )
+        .await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            entity_access_info.clone(),
+            RegistrationPolicy::Reject,
+        )
+        .await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::AlreadyExists);
+
+        // Without a requested policy or a default policy, re-registrations are rejected.
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            entity_access_info,
+            RegistrationPolicy::Unspecified,
+        )
+        .await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::AlreadyExists);
+    }
+
+    #[tokio::test]
+    async fn register_with_replace_policy_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            description: String::from("Ambient air temperature"),
+            endpoint_info_list: vec![EndpointInfo {
+                protocol: String::from("grpc"),
+                uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            entity_access_info.clone(),
+            RegistrationPolicy::Replace,
+        )
+        .await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let replacement_entity_access_info = EntityAccessInfo {
+            description: String::from("Replaced ambient air temperature"),
+            endpoint_info_list: vec![EndpointInfo {
+                protocol: String::from("grpc"),
+                uri: String::from("http://[::1]:40020"), // Devskim: ignore DS137138
+                ..Default::default()
+            }],
+            ..entity_access_info
+        };
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            replacement_entity_access_info,
+            RegistrationPolicy::Replace,
+        )
+        .await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            let registered_entity_access_info =
+                lock.get("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1").unwrap();
+            assert_eq!(
+                registered_entity_access_info.description,
+                "Replaced ambient air temperature"
+            );
+            assert_eq!(registered_entity_access_info.endpoint_info_list.len(), 1);
+            assert_eq!(
+                registered_entity_access_info.endpoint_info_list[0].uri,
+                "http://[::1]:40020" // Devskim: ignore DS137138
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn register_with_merge_policy_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            endpoint_info_list: vec![EndpointInfo {
+                protocol: String::from("grpc"),
+                uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                operations: vec![String::from("Subscribe")],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            entity_access_info.clone(),
+            RegistrationPolicy::Merge,
+        )
+        .await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // The grpc endpoint has the same protocol and uri, so it is replaced.
+        // The mqtt endpoint is new, so it is added.
+        let merged_entity_access_info = EntityAccessInfo {
+            endpoint_info_list: vec![
+                EndpointInfo {
+                    protocol: String::from("grpc"),
+                    uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                    operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+                    ..Default::default()
+                },
+                EndpointInfo {
+                    protocol: String::from("mqtt"),
+                    uri: String::from("tcp://[::1]:1883"),
+                    ..Default::default()
+                },
+            ],
+            ..entity_access_info
+        };
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            merged_entity_access_info,
+            RegistrationPolicy::Merge,
+        )
+        .await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            let registered_entity_access_info =
+                lock.get("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1").unwrap();
+            assert_eq!(registered_entity_access_info.endpoint_info_list.len(), 2);
+            assert_eq!(registered_entity_access_info.endpoint_info_list[0].protocol, "grpc");
+            assert_eq!(registered_entity_access_info.endpoint_info_list[0].operations.len(), 2);
+            assert_eq!(registered_entity_access_info.endpoint_info_list[1].protocol, "mqtt");
+        }
+    }
+
+    #[tokio::test]
+    async fn register_with_default_registration_policy_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            default_registration_policy: RegistrationPolicy::Merge,
+            ..Default::default()
+        };
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            endpoint_info_list: vec![EndpointInfo {
+                protocol: String::from("grpc"),
+                uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            entity_access_info.clone(),
+            RegistrationPolicy::Unspecified,
+        )
+        .await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let mqtt_entity_access_info = EntityAccessInfo {
+            endpoint_info_list: vec![EndpointInfo {
+                protocol: String::from("mqtt"),
+                uri: String::from("tcp://[::1]:1883"),
+                ..Default::default()
+            }],
+            ..entity_access_info
+        };
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            mqtt_entity_access_info.clone(),
+            RegistrationPolicy::Unspecified,
+        )
+        .await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            let registered_entity_access_info =
+                lock.get("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1").unwrap();
+            assert_eq!(registered_entity_access_info.endpoint_info_list.len(), 2);
+        }
+
+        // A policy in the request takes precedence over the default policy.
+        let result = register_with_policy(
+            &invehicle_digital_twin_impl,
+            mqtt_entity_access_info,
+            RegistrationPolicy::Reject,
+        )
+        .await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::AlreadyExists);
+    }
+
+    #[tokio::test]
+    async fn lease_eviction_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+        let mut entity_event_receiver = invehicle_digital_twin_impl.entity_event_sender.subscribe();
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                ..Default::default()
+            }],
+            lease_ttl_in_seconds: 10,
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let entity_event = entity_event_receiver.try_recv().unwrap();
+        assert_eq!(entity_event.kind, EntityEventKind::Added);
+
+        // The lease has not expired yet.
+        let evicted_entity_ids =
+            invehicle_digital_twin_impl.evict_expired_entities(Instant::now()).await;
+        assert!(evicted_entity_ids.is_empty());
+
+        let evicted_entity_ids = invehicle_digital_twin_impl
+            .evict_expired_entities(Instant::now() + Duration::from_secs(11))
+            .await;
+        assert_eq!(evicted_entity_ids, vec!["dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"]);
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            assert!(lock.is_empty(), "expected length was 0, actual length is {}", lock.len());
+        }
+
+        let entity_event = entity_event_receiver.try_recv().unwrap();
+        assert_eq!(entity_event.kind, EntityEventKind::Removed);
+        assert_eq!(
+            entity_event.entity_access_info.id,
+            "dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"
+        );
+    }
+
+    #[tokio::test]
+    async fn lease_eviction_with_merge_policy_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        // One provider registers its endpoint with a lease, and another merges its endpoint in
+        // without one.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                endpoint_info_list: vec![EndpointInfo {
+                    protocol: String::from("grpc"),
+                    uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            lease_ttl_in_seconds: 10,
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                endpoint_info_list: vec![EndpointInfo {
+                    protocol: String::from("mqtt"),
+                    uri: String::from("tcp://[::1]:1883"),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            registration_policy: RegistrationPolicy::Merge.into(),
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // Only the endpoint whose lease expired is evicted.
+        let evicted_entity_ids = invehicle_digital_twin_impl
+            .evict_expired_entities(Instant::now() + Duration::from_secs(11))
+            .await;
+        assert_eq!(evicted_entity_ids, vec!["dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"]);
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            let registered_entity_access_info =
+                lock.get("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1").unwrap();
+            assert_eq!(registered_entity_access_info.endpoint_info_list.len(), 1);
+            assert_eq!(registered_entity_access_info.endpoint_info_list[0].protocol, "mqtt");
+        }
+        assert!(invehicle_digital_twin_impl.lease_map.read().is_empty());
+    }
+
+    #[tokio::test]
+    async fn renew_lease_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                ..Default::default()
+            }],
+            lease_ttl_in_seconds: 10,
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let original_expires_at = invehicle_digital_twin_impl.lease_map.read()
+            ["dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"][""]
+            .expires_at;
+
+        let request = tonic::Request::new(RenewLeaseRequest {
+            ids: vec![String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1")],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.renew_lease(request).await;
+        assert!(result.is_ok(), "renew_lease result is not okay: {result:?}");
+
+        let renewed_expires_at = invehicle_digital_twin_impl.lease_map.read()
+            ["dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"][""]
+            .expires_at;
+        assert!(renewed_expires_at >= original_expires_at);
+
+        // The entity has no endpoint with this uri, so it has no lease for it.
+        let request = tonic::Request::new(RenewLeaseRequest {
+            ids: vec![String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1")],
+            uri: String::from("tcp://[::1]:1883"),
+        });
+        let result = invehicle_digital_twin_impl.renew_lease(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::NotFound);
+
+        // An entity without a lease cannot have its lease renewed.
+        let request = tonic::Request::new(RenewLeaseRequest {
+            ids: vec![String::from("dtmi:sdv:Vehicle:Cabin:HVAC:Unknown;1")],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.renew_lease(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn persistence_restart_test() {
+        let directory = std::env::temp_dir().join(format!(
+            "invehicle_digital_twin_impl_persistence_restart_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        // A snapshot is taken after the first three changes, and the unregistration is only in
+        // the journal, so the restart loads the snapshot and replays the journal.
+        let mut invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+        invehicle_digital_twin_impl.enable_persistence(&directory, 3).unwrap();
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![
+                EntityAccessInfo {
+                    id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                    endpoint_info_list: vec![EndpointInfo {
+                        protocol: String::from("grpc"),
+                        uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                EntityAccessInfo {
+                    id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:IsAirConditioningActive;1"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                endpoint_info_list: vec![EndpointInfo {
+                    protocol: String::from("mqtt"),
+                    uri: String::from("tcp://[::1]:1883"),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            registration_policy: RegistrationPolicy::Merge.into(),
+            lease_ttl_in_seconds: 10,
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![UnregisterEntityInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:IsAirConditioningActive;1"),
+                ..Default::default()
+            }],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert!(result.is_ok(), "unregister result is not okay: {result:?}");
+
+        // Kill the service without shutting it down.
+        drop(invehicle_digital_twin_impl);
+
+        // Restart the service in a new instance, which only knows what it reloads from the files.
+        let mut invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+        invehicle_digital_twin_impl.enable_persistence(&directory, 3).unwrap();
+
+        let expected_entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            endpoint_info_list: vec![
+                EndpointInfo {
+                    protocol: String::from("grpc"),
+                    uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                    ..Default::default()
+                },
+                EndpointInfo {
+                    protocol: String::from("mqtt"),
+                    uri: String::from("tcp://[::1]:1883"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            *invehicle_digital_twin_impl.entity_access_info_map.read(),
+            HashMap::from([(expected_entity_access_info.id.clone(), expected_entity_access_info)])
+        );
+
+        // Only the mqtt endpoint was registered with a lease, and its lease was restored.
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<LeaseMap> = invehicle_digital_twin_impl.lease_map.read();
+            let endpoint_leases = &lock["dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"];
+            assert_eq!(endpoint_leases.len(), 1);
+            assert_eq!(endpoint_leases["tcp://[::1]:1883"].ttl, Duration::from_secs(10));
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn watch_entities_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            ..Default::default()
+        };
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info.clone()],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let request = tonic::Request::new(WatchEntitiesRequest {
+            id_prefix: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:"),
+            include_initial_snapshot: true,
+            ..Default::default()
+        });
+        let mut stream =
+            invehicle_digital_twin_impl.watch_entities(request).await.unwrap().into_inner();
+
+        // The initial snapshot.
+        let response = stream.next().await.unwrap().unwrap();
+        assert_eq!(response.kind(), WatchEventKind::Added);
+        assert_eq!(response.entity_access_info, Some(entity_access_info.clone()));
+
+        // An entity that does not match the prefix is not reported, and an entity that does is.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![
+                EntityAccessInfo {
+                    id: String::from("dtmi:sdv:Vehicle:Cabin:Seat:Massager;1"),
+                    ..Default::default()
+                },
+                EntityAccessInfo {
+                    id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                    endpoint_info_list: vec![EndpointInfo {
+                        protocol: String::from("mqtt"),
+                        uri: String::from("tcp://[::1]:1883"),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+            ],
+            registration_policy: RegistrationPolicy::Merge.into(),
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let response = stream.next().await.unwrap().unwrap();
+        assert_eq!(response.kind(), WatchEventKind::Updated);
+        assert_eq!(response.entity_access_info.unwrap().endpoint_info_list.len(), 1);
+
+        let request = tonic::Request::new(UnregisterRequest {
+            unregister_entity_info_list: vec![UnregisterEntityInfo {
+                id: entity_access_info.id.clone(),
+                ..Default::default()
+            }],
+        });
+        let result = invehicle_digital_twin_impl.unregister(request).await;
+        assert!(result.is_ok(), "unregister result is not okay: {result:?}");
+
+        let response = stream.next().await.unwrap().unwrap();
+        assert_eq!(response.kind(), WatchEventKind::Removed);
+        assert_eq!(response.entity_access_info.unwrap().id, entity_access_info.id);
+    }
+
+    #[tokio::test]
+    async fn list_entities_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let grpc_endpoint_info = EndpointInfo {
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+            ..Default::default()
+        };
+        let mqtt_endpoint_info = EndpointInfo {
+            protocol: String::from("mqtt"),
+            uri: String::from("tcp://[::1]:1883"),
+            operations: vec![String::from("Subscribe")],
+            ..Default::default()
+        };
+
+        let entity_ids = [
+            "dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1",
+            "dtmi:sdv:Vehicle:Cabin:HVAC:IsAirConditioningActive;1",
+            "dtmi:sdv:Vehicle:Cabin:HVAC:Fan;1",
+            "dtmi:sdv:Vehicle:OBD:HybridBatteryRemaining;1",
+        ];
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: entity_ids
+                .iter()
+                .map(|entity_id| EntityAccessInfo {
+                    id: entity_id.to_string(),
+                    endpoint_info_list: if *entity_id == entity_ids[2] {
+                        vec![grpc_endpoint_info.clone(), mqtt_endpoint_info.clone()]
+                    } else {
+                        vec![grpc_endpoint_info.clone()]
+                    },
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // Page through the entities with the HVAC prefix.
+        let request = tonic::Request::new(ListEntitiesRequest {
+            id_prefix: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:"),
+            page_size: 2,
+            ..Default::default()
+        });
+        let response =
+            invehicle_digital_twin_impl.list_entities(request).await.unwrap().into_inner();
+        let ids: Vec<String> =
+            response.entity_access_info_list.iter().map(|info| info.id.clone()).collect();
+        assert_eq!(ids, [entity_ids[0], entity_ids[2]]);
+        assert_eq!(response.next_page_token, entity_ids[2]);
+
+        let request = tonic::Request::new(ListEntitiesRequest {
+            id_prefix: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:"),
+            page_size: 2,
+            page_token: response.next_page_token,
+            ..Default::default()
+        });
+        let response =
+            invehicle_digital_twin_impl.list_entities(request).await.unwrap().into_inner();
+        let ids: Vec<String> =
+            response.entity_access_info_list.iter().map(|info| info.id.clone()).collect();
+        assert_eq!(ids, [entity_ids[1]]);
+        assert!(response.next_page_token.is_empty());
+
+        // Filter by protocol and operations.
+        let request = tonic::Request::new(ListEntitiesRequest {
+            protocol: String::from("mqtt"),
+            operations: vec![String::from("Subscribe")],
+            ..Default::default()
+        });
+        let response =
+            invehicle_digital_twin_impl.list_entities(request).await.unwrap().into_inner();
+        let ids: Vec<String> =
+            response.entity_access_info_list.iter().map(|info| info.id.clone()).collect();
+        assert_eq!(ids, [entity_ids[2]]);
+
+        // The mqtt endpoint does not support Unsubscribe, and the grpc endpoint is not mqtt.
+        let request = tonic::Request::new(ListEntitiesRequest {
+            protocol: String::from("mqtt"),
+            operations: vec![String::from("Unsubscribe")],
+            ..Default::default()
+        });
+        let response =
+            invehicle_digital_twin_impl.list_entities(request).await.unwrap().into_inner();
+        assert!(response.entity_access_info_list.is_empty());
+    }
+
+    #[tokio::test]
+    async fn find_by_id_with_endpoint_health_test() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let healthy_uri = format!("http://{}", listener.local_addr().unwrap()); // Devskim: ignore DS137138
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            endpoint_info_list: vec![
+                EndpointInfo {
+                    protocol: String::from("grpc"),
+                    uri: healthy_uri.clone(),
+                    ..Default::default()
+                },
+                // Nothing listens on port 1 of the loopback address.
+                EndpointInfo {
+                    protocol: String::from("mqtt"),
+                    uri: String::from("tcp://127.0.0.1:1"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let endpoint_health_checker = EndpointHealthChecker::default();
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            endpoint_health_checker: Some(endpoint_health_checker.clone()),
+            ..Default::default()
+        };
+        invehicle_digital_twin_impl
+            .entity_access_info_map
+            .write()
+            .insert(entity_access_info.id.clone(), entity_access_info.clone());
+
+        endpoint_health_checker
+            .check_endpoints(&invehicle_digital_twin_impl.entity_access_info_map)
+            .await;
+
+        let request = tonic::Request::new(FindByIdRequest { id: entity_access_info.id.clone() });
+        let response = invehicle_digital_twin_impl.find_by_id(request).await.unwrap().into_inner();
+        assert_eq!(response.entity_access_info.unwrap().endpoint_info_list.len(), 2);
+        assert_eq!(
+            response.endpoint_health,
+            HashMap::from([
+                (healthy_uri.clone(), EndpointHealthStatus::Healthy.into()),
+                (String::from("tcp://127.0.0.1:1"), EndpointHealthStatus::Unhealthy.into()),
+            ])
+        );
+
+        // Hide the unhealthy endpoints.
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            hide_unhealthy_endpoints: true,
+            ..invehicle_digital_twin_impl
+        };
+
+        let request = tonic::Request::new(FindByIdRequest { id: entity_access_info.id.clone() });
+        let response = invehicle_digital_twin_impl.find_by_id(request).await.unwrap().into_inner();
+        let response_entity_access_info = response.entity_access_info.unwrap();
+        assert_eq!(response_entity_access_info.endpoint_info_list.len(), 1);
+        assert_eq!(response_entity_access_info.endpoint_info_list[0].uri, healthy_uri);
+        assert_eq!(
+            response.endpoint_health,
+            HashMap::from([(healthy_uri.clone(), EndpointHealthStatus::Healthy.into())])
+        );
+
+        // The entity is not listed for the protocol of its hidden endpoint.
+        let request = tonic::Request::new(ListEntitiesRequest {
+            protocol: String::from("mqtt"),
+            ..Default::default()
+        });
+        let response =
+            invehicle_digital_twin_impl.list_entities(request).await.unwrap().into_inner();
+        assert!(response.entity_access_info_list.is_empty());
+    }
+
+    #[tokio::test]
+    async fn register_invalid_entity_id_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:HVAC:Ambient-Air-Temperature;1"),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        let status = invehicle_digital_twin_impl.register(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(
+            status.message().starts_with(
+                "Entity id 'dtmi:sdv:HVAC:Ambient-Air-Temperature;1' is not a valid DTMI: path segment 'Ambient-Air-Temperature'"
+            ),
+            "unexpected message: {}",
+            status.message()
+        );
+        assert!(invehicle_digital_twin_impl.entity_access_info_map.read().is_empty());
+    }
+
+    #[tokio::test]
+    async fn register_with_dtdl_model_catalog_test() {
+        let dtdl_directory = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../digital-twin-model/dtdl");
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl {
+            dtdl_model_catalog: Some(Arc::new(DtdlModelCatalog::load(&dtdl_directory).unwrap())),
+            ..Default::default()
+        };
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:hvac:ambient_air_temperature;1"),
+            endpoint_info_list: vec![EndpointInfo {
+                protocol: String::from("grpc"),
+                uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+                operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info.clone()],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        // A property cannot be invoked.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                endpoint_info_list: vec![EndpointInfo {
+                    protocol: String::from("grpc"),
+                    uri: String::from("http://[::1]:40020"), // Devskim: ignore DS137138
+                    operations: vec![String::from("Invoke")],
+                    ..Default::default()
+                }],
+                ..entity_access_info
+            }],
+            registration_policy: RegistrationPolicy::Merge.into(),
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        // The id is a valid DTMI, but the models do not define it.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            assert_eq!(lock.len(), 1);
+            assert_eq!(lock["dtmi:sdv:hvac:ambient_air_temperature;1"].endpoint_info_list.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn register_batch_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            ..Default::default()
+        };
+        let other_entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:IsAirConditioningActive;1"),
+            ..Default::default()
+        };
+
+        // The second entity is a duplicate of the first, so it is rejected.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![
+                entity_access_info.clone(),
+                entity_access_info,
+                other_entity_access_info.clone(),
+            ],
+            ..Default::default()
+        });
+        let status = invehicle_digital_twin_impl.register(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+        let response: RegisterResponse =
+            get_typed_details(&status, REGISTER_RESPONSE_TYPE_NAME).unwrap();
+        let codes: Vec<i32> =
+            response.entity_status_list.iter().map(|entity_status| entity_status.code).collect();
+        assert_eq!(
+            codes,
+            [tonic::Code::Ok as i32, tonic::Code::AlreadyExists as i32, tonic::Code::Ok as i32]
+        );
+        assert_eq!(response.entity_status_list[2].id, other_entity_access_info.id);
+        assert_eq!(invehicle_digital_twin_impl.entity_access_info_map.read().len(), 2);
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![EntityAccessInfo {
+                id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:Fan;1"),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        let response = invehicle_digital_twin_impl.register(request).await.unwrap().into_inner();
+        assert_eq!(response.entity_status_list.len(), 1);
+        assert_eq!(response.entity_status_list[0].code, tonic::Code::Ok as i32);
+    }
+
+    #[tokio::test]
+    async fn register_atomic_batch_test() {
+        let invehicle_digital_twin_impl = InvehicleDigitalTwinImpl::default();
+
+        let entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:AmbientAirTemperature;1"),
+            ..Default::default()
+        };
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info.clone()],
+            ..Default::default()
+        });
+        let result = invehicle_digital_twin_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let new_entity_access_info = EntityAccessInfo {
+            id: String::from("dtmi:sdv:Vehicle:Cabin:HVAC:IsAirConditioningActive;1"),
+            ..Default::default()
+        };
+        let replacement_entity_access_info = EntityAccessInfo {
+            description: String::from("Replacement"),
+            ..entity_access_info.clone()
+        };
+
+        // The already registered entity is rejected, so the new entity is not registered either.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![
+                new_entity_access_info.clone(),
+                replacement_entity_access_info.clone(),
+            ],
+            atomic: true,
+            ..Default::default()
+        });
+        let status = invehicle_digital_twin_impl.register(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+
+        let response: RegisterResponse =
+            get_typed_details(&status, REGISTER_RESPONSE_TYPE_NAME).unwrap();
+        let codes: Vec<i32> =
+            response.entity_status_list.iter().map(|entity_status| entity_status.code).collect();
+        assert_eq!(codes, [tonic::Code::Aborted as i32, tonic::Code::AlreadyExists as i32]);
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            assert_eq!(
+                *lock,
+                HashMap::from([(entity_access_info.id.clone(), entity_access_info.clone())])
+            );
+        }
+
+        // With the replace policy, the whole batch is registered.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![new_entity_access_info, replacement_entity_access_info],
+            registration_policy: RegistrationPolicy::Replace.into(),
+            atomic: true,
+            ..Default::default()
+        });
+        let response = invehicle_digital_twin_impl.register(request).await.unwrap().into_inner();
+        assert!(response
+            .entity_status_list
+            .iter()
+            .all(|entity_status| entity_status.code == tonic::Code::Ok as i32));
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, EntityAccessInfo>> =
+                invehicle_digital_twin_impl.entity_access_info_map.read();
+            assert_eq!(lock.len(), 2);
+            assert_eq!(lock[&entity_access_info.id].description, "Replacement");
+        }
+    }
 }
@@ -0,0 +1,433 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+//! Durable persistence for the registered entities.
+//!
+//! The registered entities are persisted in a directory that contains a snapshot and a journal.
+//! The snapshot holds the state of every registered entity at the time that it was taken. Every
+//! change since then is appended to the journal. At startup, the snapshot is loaded and the
+//! journal is replayed on top of it.
+//!
+//! Both files are sequences of length-delimited protobuf records. Each snapshot record holds the
+//! complete state of one entity. Each journal record holds the complete state of every entity that
+//! one change touched (or notes that they were removed), so replaying a record more than once is
+//! harmless, and a change that touches several entities, e.g. an atomic batch, is persisted as a
+//! whole or not at all. A change is appended to the journal and synced to disk before it is
+//! applied, so a change that cannot be persisted is not applied. A failed append is truncated away,
+//! and a crash during an append can only leave a partial record at the end of the journal, which is
+//! discarded when the journal is replayed. Snapshots are written to a temporary file that
+//! is then atomically renamed, so a crash while taking a snapshot leaves the previous snapshot and
+//! the journal intact. Any other damage, i.e. a record that cannot be decoded or a partial record
+//! in the snapshot, fails the recovery, rather than silently losing the records after it.
+
+use core_protobuf_data_access::invehicle_digital_twin::v1::EntityAccessInfo;
+use log::{info, warn};
+use prost::{decode_length_delimiter, Message};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const SNAPSHOT_FILENAME: &str = "snapshot";
+const SNAPSHOT_TEMP_FILENAME: &str = "snapshot.tmp";
+const JOURNAL_FILENAME: &str = "journal";
+
+/// The maximum length of a record's length delimiter, which is a varint.
+const MAX_LENGTH_DELIMITER_LEN: usize = 10;
+
+/// A persisted record of an entity's state.
+#[derive(Clone, PartialEq, Message)]
+pub struct PersistedEntity {
+    /// The entity's id.
+    #[prost(string, tag = "1")]
+    pub id: String,
+    /// The entity's access info. It is absent when the entity was removed.
+    #[prost(message, optional, tag = "2")]
+    pub entity_access_info: Option<EntityAccessInfo>,
+    // Tag 3 is reserved. It was the lease time-to-live of the entity as a whole, a uint32, so it
+    // must not be reused, in order that the records that were persisted with it can still be read.
+    /// The lease time-to-live in seconds of each of the entity's endpoints that has a lease, keyed
+    /// by uri. An entity without endpoints has its lease under an empty uri.
+    #[prost(map = "string, uint32", tag = "4")]
+    pub endpoint_lease_ttl_in_seconds: HashMap<String, u32>,
+}
+
+/// A journal record of one change, with the new state of each entity that it touched.
+#[derive(Clone, PartialEq, Message)]
+pub struct PersistedChange {
+    /// The new state of each entity that the change touched.
+    #[prost(message, repeated, tag = "1")]
+    pub persisted_entities: Vec<PersistedEntity>,
+}
+
+/// Persists the registered entities in a snapshot and a journal.
+#[derive(Debug)]
+pub struct EntityPersistence {
+    /// The directory that holds the snapshot and the journal.
+    directory: PathBuf,
+    /// The journal, opened for appending.
+    journal: File,
+    /// The number of changes that have been appended to the journal since the last snapshot.
+    journal_record_count: usize,
+    /// The number of journal records after which a snapshot should be taken.
+    snapshot_threshold: usize,
+}
+
+impl EntityPersistence {
+    /// Open the persistence directory, creating it if necessary, and recover the persisted
+    /// entities. Returns the persistence and the entities that were recovered, keyed by id.
+    ///
+    /// # Arguments
+    /// * `directory` - The directory that holds the snapshot and the journal.
+    /// * `snapshot_threshold` - The number of journal records after which a snapshot should be taken.
+    pub fn open(
+        directory: &Path,
+        snapshot_threshold: usize,
+    ) -> Result<(Self, HashMap<String, PersistedEntity>), io::Error> {
+        fs::create_dir_all(directory)?;
+
+        let mut persisted_entity_map = HashMap::new();
+
+        let snapshot_path = directory.join(SNAPSHOT_FILENAME);
+        if snapshot_path.exists() {
+            // A snapshot is renamed into place once it is complete, so it cannot have a partial
+            // record at its end.
+            let (snapshot_records, valid_len) =
+                Self::read_records::<PersistedEntity>(&snapshot_path)?;
+            if fs::metadata(&snapshot_path)?.len() > valid_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The snapshot {snapshot_path:?} ends with a partial record"),
+                ));
+            }
+            Self::apply_records(&mut persisted_entity_map, snapshot_records);
+        }
+
+        let journal_path = directory.join(JOURNAL_FILENAME);
+        let mut journal_record_count = 0;
+        if journal_path.exists() {
+            let (journal_records, valid_len) =
+                Self::read_records::<PersistedChange>(&journal_path)?;
+
+            // Discard any partial record that was left behind by a crash during an append.
+            let journal = OpenOptions::new().write(true).open(&journal_path)?;
+            if journal.metadata()?.len() > valid_len {
+                warn!("Discarding a partial record at the end of the journal {journal_path:?}");
+                journal.set_len(valid_len)?;
+                journal.sync_all()?;
+            }
+
+            journal_record_count = journal_records.len();
+            Self::apply_records(
+                &mut persisted_entity_map,
+                journal_records
+                    .into_iter()
+                    .flat_map(|persisted_change| persisted_change.persisted_entities),
+            );
+        }
+
+        let journal = OpenOptions::new().create(true).append(true).open(&journal_path)?;
+
+        info!("Recovered {} persisted entities from {directory:?}", persisted_entity_map.len());
+
+        let persistence = EntityPersistence {
+            directory: directory.to_path_buf(),
+            journal,
+            journal_record_count,
+            snapshot_threshold,
+        };
+
+        Ok((persistence, persisted_entity_map))
+    }
+
+    /// Append a change, with the new state of each entity that it touched, to the journal as one
+    /// record and sync it to disk. When the append fails, the journal is truncated back to its
+    /// previous length, so that a partial record cannot hide the records that are appended after it.
+    ///
+    /// # Arguments
+    /// * `persisted_entities` - The new state of each entity that the change touched.
+    pub fn append(&mut self, persisted_entities: &[PersistedEntity]) -> Result<(), io::Error> {
+        let journal_len = self.journal.metadata()?.len();
+        let persisted_change = PersistedChange { persisted_entities: persisted_entities.to_vec() };
+
+        if let Err(error) = self
+            .journal
+            .write_all(&persisted_change.encode_length_delimited_to_vec())
+            .and_then(|()| self.journal.sync_data())
+        {
+            if let Err(truncate_error) = self.journal.set_len(journal_len) {
+                warn!("Failed to truncate the journal after a failed append: {truncate_error}");
+            }
+            return Err(error);
+        }
+
+        self.journal_record_count += 1;
+
+        Ok(())
+    }
+
+    /// Is it time to take a snapshot?
+    pub fn is_snapshot_due(&self) -> bool {
+        self.journal_record_count >= self.snapshot_threshold
+    }
+
+    /// Take a snapshot of all of the entities and truncate the journal.
+    ///
+    /// # Arguments
+    /// * `persisted_entities` - The current state of all of the entities.
+    pub fn take_snapshot<'a>(
+        &mut self,
+        persisted_entities: impl Iterator<Item = &'a PersistedEntity>,
+    ) -> Result<(), io::Error> {
+        let snapshot_temp_path = self.directory.join(SNAPSHOT_TEMP_FILENAME);
+
+        // This block controls the lifetime of the temporary snapshot file.
+        {
+            let mut snapshot_temp = File::create(&snapshot_temp_path)?;
+            for persisted_entity in persisted_entities {
+                snapshot_temp.write_all(&persisted_entity.encode_length_delimited_to_vec())?;
+            }
+            snapshot_temp.sync_all()?;
+        }
+
+        fs::rename(&snapshot_temp_path, self.directory.join(SNAPSHOT_FILENAME))?;
+        // Make the rename durable before the journal is truncated.
+        File::open(&self.directory)?.sync_all()?;
+
+        self.journal.set_len(0)?;
+        self.journal.sync_all()?;
+        self.journal_record_count = 0;
+
+        Ok(())
+    }
+
+    /// Read the complete records from a file. Returns the records and the length of the file's
+    /// content that they occupy. Reading stops at a partial record at the end of the file, i.e. one
+    /// whose length delimiter or content is cut short. A record that cannot be decoded is an error.
+    ///
+    /// # Arguments
+    /// * `path` - The file's path.
+    fn read_records<M: Message + Default>(path: &Path) -> Result<(Vec<M>, u64), io::Error> {
+        let content = fs::read(path)?;
+        let mut remaining: &[u8] = &content;
+        let mut records = Vec::new();
+
+        while !remaining.is_empty() {
+            let offset = content.len() - remaining.len();
+            let corrupt_record_error = |error: prost::DecodeError| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("The record at offset {offset} of {path:?} is corrupt: {error}"),
+                )
+            };
+
+            let mut record_content = remaining;
+            let record_len = match decode_length_delimiter(&mut record_content) {
+                Ok(record_len) => record_len,
+                // Every byte of a length delimiter that is cut short has the continuation bit set.
+                Err(_)
+                    if remaining.len() < MAX_LENGTH_DELIMITER_LEN
+                        && remaining.iter().all(|byte| byte & 0x80 != 0) =>
+                {
+                    break;
+                }
+                Err(error) => return Err(corrupt_record_error(error)),
+            };
+            if record_content.len() < record_len {
+                break;
+            }
+
+            let record = M::decode(&record_content[..record_len]).map_err(corrupt_record_error)?;
+            records.push(record);
+            remaining = &record_content[record_len..];
+        }
+
+        Ok((records, (content.len() - remaining.len()) as u64))
+    }
+
+    /// Apply records to the map of persisted entities. Later records supersede earlier ones.
+    ///
+    /// # Arguments
+    /// * `persisted_entity_map` - The map of persisted entities, keyed by id.
+    /// * `records` - The records to apply.
+    fn apply_records(
+        persisted_entity_map: &mut HashMap<String, PersistedEntity>,
+        records: impl IntoIterator<Item = PersistedEntity>,
+    ) {
+        for record in records {
+            if record.entity_access_info.is_some() {
+                persisted_entity_map.insert(record.id.clone(), record);
+            } else {
+                persisted_entity_map.remove(&record.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod invehicle_digital_twin_persistence_tests {
+    use super::*;
+
+    /// Create an empty directory for a test's persistence files.
+    ///
+    /// # Arguments
+    /// * `test_name` - The test's name.
+    fn create_test_directory(test_name: &str) -> PathBuf {
+        let directory = std::env::temp_dir()
+            .join(format!("invehicle_digital_twin_persistence_{test_name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn create_persisted_entity(id: &str) -> PersistedEntity {
+        PersistedEntity {
+            id: id.to_string(),
+            entity_access_info: Some(EntityAccessInfo {
+                name: String::from("AmbientAirTemperature"),
+                id: id.to_string(),
+                description: String::from("Ambient air temperature"),
+                endpoint_info_list: Vec::new(),
+            }),
+            endpoint_lease_ttl_in_seconds: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn partial_journal_record_test() {
+        let directory = create_test_directory("partial_journal_record");
+
+        // This block controls the lifetime of the persistence.
+        {
+            let (mut persistence, persisted_entity_map) =
+                EntityPersistence::open(&directory, 100).unwrap();
+            assert!(persisted_entity_map.is_empty());
+
+            persistence.append(&[create_persisted_entity("entity_1")]).unwrap();
+            persistence.append(&[create_persisted_entity("entity_2")]).unwrap();
+            persistence
+                .append(&[PersistedEntity { id: String::from("entity_1"), ..Default::default() }])
+                .unwrap();
+        }
+
+        // Simulate a crash part way through the append of a change that touched two entities.
+        let partial_record = PersistedChange {
+            persisted_entities: vec![
+                create_persisted_entity("entity_3"),
+                create_persisted_entity("entity_5"),
+            ],
+        }
+        .encode_length_delimited_to_vec();
+        let mut journal =
+            OpenOptions::new().append(true).open(directory.join(JOURNAL_FILENAME)).unwrap();
+        // The partial record holds the whole state of entity_3, but none of the change is recovered.
+        journal.write_all(&partial_record[..partial_record.len() - 1]).unwrap();
+        drop(journal);
+
+        let (mut persistence, persisted_entity_map) =
+            EntityPersistence::open(&directory, 100).unwrap();
+        assert_eq!(persisted_entity_map.len(), 1);
+        assert!(persisted_entity_map.contains_key("entity_2"));
+
+        // Appends after recovery must not be corrupted by the discarded partial record.
+        persistence.append(&[create_persisted_entity("entity_4")]).unwrap();
+        drop(persistence);
+
+        let (_, persisted_entity_map) = EntityPersistence::open(&directory, 100).unwrap();
+        assert_eq!(persisted_entity_map.len(), 2);
+        assert!(persisted_entity_map.contains_key("entity_4"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn partial_length_delimiter_test() {
+        let directory = create_test_directory("partial_length_delimiter");
+
+        // This block controls the lifetime of the persistence.
+        {
+            let (mut persistence, _) = EntityPersistence::open(&directory, 100).unwrap();
+            persistence.append(&[create_persisted_entity("entity_1")]).unwrap();
+            persistence.append(&[create_persisted_entity("entity_2")]).unwrap();
+        }
+
+        // Simulate a crash part way through the append of a record's length delimiter, which is
+        // two bytes long for a record of 200 bytes.
+        let journal_path = directory.join(JOURNAL_FILENAME);
+        let journal_len = fs::metadata(&journal_path).unwrap().len();
+        let mut journal = OpenOptions::new().append(true).open(&journal_path).unwrap();
+        journal.write_all(&[0xc8]).unwrap();
+        drop(journal);
+
+        // The complete records are replayed and the partial record is truncated away.
+        let (mut persistence, persisted_entity_map) =
+            EntityPersistence::open(&directory, 100).unwrap();
+        assert_eq!(persisted_entity_map.len(), 2);
+        assert!(persisted_entity_map.contains_key("entity_1"));
+        assert!(persisted_entity_map.contains_key("entity_2"));
+        assert_eq!(fs::metadata(&journal_path).unwrap().len(), journal_len);
+
+        persistence.append(&[create_persisted_entity("entity_3")]).unwrap();
+        drop(persistence);
+
+        let (_, persisted_entity_map) = EntityPersistence::open(&directory, 100).unwrap();
+        assert_eq!(persisted_entity_map.len(), 3);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn corrupt_journal_record_test() {
+        let directory = create_test_directory("corrupt_journal_record");
+
+        // This block controls the lifetime of the persistence.
+        {
+            let (mut persistence, _) = EntityPersistence::open(&directory, 100).unwrap();
+            persistence.append(&[create_persisted_entity("entity_1")]).unwrap();
+            persistence.append(&[create_persisted_entity("entity_2")]).unwrap();
+        }
+
+        // Corrupt the first record, whose one byte length delimiter is followed by its content,
+        // with a field that has the invalid wire type 7.
+        let journal_path = directory.join(JOURNAL_FILENAME);
+        let mut journal_content = fs::read(&journal_path).unwrap();
+        journal_content[1] = 0x0f;
+        fs::write(&journal_path, &journal_content).unwrap();
+
+        // The records after the corrupt record are not silently discarded.
+        let error = EntityPersistence::open(&directory, 100).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&journal_path).unwrap(), journal_content);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn take_snapshot_test() {
+        let directory = create_test_directory("take_snapshot");
+
+        let (mut persistence, _) = EntityPersistence::open(&directory, 2).unwrap();
+
+        let persisted_entity_1 = create_persisted_entity("entity_1");
+        let persisted_entity_2 = create_persisted_entity("entity_2");
+
+        persistence.append(&[persisted_entity_1.clone()]).unwrap();
+        assert!(!persistence.is_snapshot_due());
+        persistence.append(&[persisted_entity_2.clone()]).unwrap();
+        assert!(persistence.is_snapshot_due());
+
+        persistence.take_snapshot([&persisted_entity_1, &persisted_entity_2].into_iter()).unwrap();
+        assert!(!persistence.is_snapshot_due());
+        assert_eq!(fs::metadata(directory.join(JOURNAL_FILENAME)).unwrap().len(), 0);
+
+        let persisted_entity_3 = create_persisted_entity("entity_3");
+        persistence.append(&[persisted_entity_3.clone()]).unwrap();
+        drop(persistence);
+
+        let (_, persisted_entity_map) = EntityPersistence::open(&directory, 2).unwrap();
+        assert_eq!(persisted_entity_map.len(), 3);
+        assert_eq!(persisted_entity_map.get("entity_3"), Some(&persisted_entity_3));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -6,46 +6,77 @@
 // Start: Module references.
 
 #[cfg(feature = "managed_subscribe")]
-use managed_subscribe::managed_subscribe_module::ManagedSubscribeModule;
+use managed_subscribe::managed_subscribe_module::{self, ManagedSubscribeModule};
 
 #[cfg(feature = "digital_twin_graph")]
-use digital_twin_graph::digital_twin_graph_module::DigitalTwinGraphModule;
+use digital_twin_graph::digital_twin_graph_module::{self, DigitalTwinGraphModule};
 
 #[cfg(feature = "digital_twin_registry")]
-use digital_twin_registry::digital_twin_registry_module::DigitalTwinRegistryModule;
+use digital_twin_registry::digital_twin_registry_module::{self, DigitalTwinRegistryModule};
 
 // End: Module references.
 
 #[allow(unused_imports)]
-use common::grpc_interceptor::GrpcInterceptorLayer;
-
-use common::grpc_server::GrpcServer;
+use common::grpc_module_registry::GrpcModuleResult;
+
+use admin_impl::AdminImpl;
+use common::auth_grpc_interceptor::{AuthGrpcInterceptor, AuthSettings};
+use common::dtdl_model_catalog::DtdlModelCatalog;
+use common::entity_events::{create_entity_event_sender, EntityEvent};
+use common::grpc_channel::{self, ClientCertificateVerification};
+use common::grpc_health::GrpcHealthReporter;
+use common::grpc_interceptor::{GrpcInterceptorLayer, SyncGrpcInterceptorAdapter};
+use common::grpc_interceptor_registry::{GrpcInterceptorRegistry, GrpcInterceptorSettings};
+use common::grpc_module_registry::{GrpcModuleRegistry, GrpcModuleSettings};
+use common::grpc_reflection::{self, ServerReflectionV1Service};
+use common::grpc_server::{bind_unix_socket, GrpcServerAddress};
+use common::grpc_tracing;
+use common::logging;
+use common::metrics;
+use common::sample_grpc_interceptor::{self, SampleGrpcInterceptor};
+use core_protobuf_data_access::admin::v1::admin_server::AdminServer;
 use core_protobuf_data_access::chariott::service_discovery::core::v1::service_registry_client::ServiceRegistryClient;
 use core_protobuf_data_access::chariott::service_discovery::core::v1::{
-    RegisterRequest, ServiceMetadata,
+    RegisterRequest, ServiceIdentifier, ServiceMetadata, UnregisterRequest,
 };
+use core_protobuf_data_access::get_file_descriptor_sets;
 use core_protobuf_data_access::invehicle_digital_twin::v1::invehicle_digital_twin_server::InvehicleDigitalTwinServer;
-use env_logger::{Builder, Target};
-use log::{debug, error, info, LevelFilter};
+use endpoint_health_checker::EndpointHealthChecker;
+use futures::future::try_join_all;
+use log::{debug, error, info, warn, LevelFilter};
 use parking_lot::RwLock;
 use std::boxed::Box;
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::env;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{broadcast, watch};
+use tokio::time::{Duration, Instant};
 use tonic::body::BoxBody;
 use tonic::server::NamedService;
-use tonic::transport::Body;
+use tonic::transport::{Body, Server, ServerTlsConfig};
 use tonic::{Request, Status};
-use tower::layer::util::Identity;
 use tower::Service;
 
+mod admin_impl;
+mod endpoint_health_checker;
 mod invehicle_digital_twin_config;
 mod invehicle_digital_twin_impl;
+mod invehicle_digital_twin_persistence;
 
 const DEFAULT_LOG_LEVEL: &str = "info";
+const DEFAULT_LEASE_EVICTION_INTERVAL_IN_SECONDS: u64 = 1;
+const DEFAULT_PERSISTENCE_SNAPSHOT_THRESHOLD: usize = 1000;
+const DEFAULT_DTDL_MODEL_SUMMARY_INTERVAL_IN_SECONDS: u64 = 60;
+const HEALTH_REPORT_INTERVAL_IN_SECONDS: u64 = 5;
+const DEFAULT_SHUTDOWN_DRAIN_TIMEOUT_IN_SECONDS: u64 = 5;
+const DRAIN_POLL_INTERVAL_IN_MILLISECONDS: u64 = 100;
 const INVEHICLE_DIGITAL_TWIN_SERVICE_NAMESPACE: &str = "sdv.ibeji";
 const INVEHICLE_DIGITAL_TWIN_SERVICE_NAME: &str = "invehicle_digital_twin";
 const INVEHICLE_DIGITAL_TWIN_SERVICE_VERSION: &str = "1.0";
@@ -61,8 +92,9 @@ async fn register_invehicle_digital_twin_service_with_chariott(
     chariott_uri: &str,
     invehicle_digital_twin_uri: &str,
 ) -> Result<(), Status> {
-    let mut client = ServiceRegistryClient::connect(chariott_uri.to_owned())
+    let mut client = grpc_channel::connect(chariott_uri)
         .await
+        .map(ServiceRegistryClient::new)
         .map_err(|e| Status::internal(e.to_string()))?;
 
     let service = Some(ServiceMetadata {
@@ -84,23 +116,332 @@ async fn register_invehicle_digital_twin_service_with_chariott(
     Ok(())
 }
 
+/// Unregister the invehicle digital twin service from Chariott.
+///
+/// # Arguments
+/// * `chariott_uri` - Chariott's URI.
+async fn unregister_invehicle_digital_twin_service_from_chariott(
+    chariott_uri: &str,
+) -> Result<(), Status> {
+    let mut client = grpc_channel::connect(chariott_uri)
+        .await
+        .map(ServiceRegistryClient::new)
+        .map_err(|e| Status::internal(e.to_string()))?;
+
+    let service_identifier = Some(ServiceIdentifier {
+        namespace: INVEHICLE_DIGITAL_TWIN_SERVICE_NAMESPACE.to_string(),
+        name: INVEHICLE_DIGITAL_TWIN_SERVICE_NAME.to_string(),
+        version: INVEHICLE_DIGITAL_TWIN_SERVICE_VERSION.to_string(),
+    });
+
+    let request = Request::new(UnregisterRequest { service_identifier });
+
+    client
+        .unregister(request)
+        .await
+        .map_err(|_| Status::internal("Chariott unregister request failed"))?;
+
+    Ok(())
+}
+
+/// Unregister the invehicle digital twin service from Chariott, if it is registered with it, so
+/// that it is no longer discovered. It is only unregistered once.
+///
+/// # Arguments
+/// * `registered_chariott_uri` - The URI of the Chariott that the service is registered with, if
+///                               it is registered. It is cleared.
+/// * `deadline` - When to stop waiting for Chariott.
+async fn unregister_from_chariott(registered_chariott_uri: &mut Option<String>, deadline: Instant) {
+    let Some(chariott_uri) = registered_chariott_uri.take() else {
+        return;
+    };
+
+    match tokio::time::timeout_at(
+        deadline,
+        unregister_invehicle_digital_twin_service_from_chariott(&chariott_uri),
+    )
+    .await
+    {
+        Ok(Ok(())) => info!("This service is no longer registered with Chariott."),
+        Ok(Err(error)) => warn!("Failed to unregister this service from Chariott: '{error}'"),
+        Err(_) => warn!("Timed out unregistering this service from Chariott."),
+    }
+}
+
+/// Wait for a signal to shut down the service: SIGINT (e.g. Ctrl+C) or SIGTERM (e.g. from systemd
+/// or the container runtime).
+async fn wait_for_shutdown_signal() {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(error) => {
+            warn!("Unable to listen for SIGTERM, so only SIGINT shuts down the service: {error}");
+            if let Err(error) = tokio::signal::ctrl_c().await {
+                error!("Unable to listen for SIGINT: {error}");
+                std::future::pending::<()>().await;
+            }
+            info!("Received SIGINT.");
+            return;
+        }
+    };
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => match result {
+            Ok(()) => info!("Received SIGINT."),
+            Err(error) => {
+                error!("Unable to listen for SIGINT: {error}");
+                sigterm.recv().await;
+                info!("Received SIGTERM.");
+            }
+        },
+        _ = sigterm.recv() => info!("Received SIGTERM."),
+    }
+}
+
+/// Wait until the servers are told to stop serving.
+///
+/// # Arguments
+/// * `shutdown_receiver` - The receiver that the servers are told to stop serving on.
+fn wait_for_shutdown(shutdown_receiver: &watch::Receiver<bool>) -> impl Future<Output = ()> {
+    let mut shutdown_receiver = shutdown_receiver.clone();
+
+    async move {
+        // The sender is only dropped once the servers have stopped serving.
+        let _ = shutdown_receiver.wait_for(|is_shutting_down| *is_shutting_down).await;
+    }
+}
+
+/// Wait until the app server has completed its in-flight unary calls, or until the deadline. The
+/// servers keep serving while they are drained, so that the answers to in-flight asks still
+/// arrive. The streaming calls, e.g. WatchEntities, are not waited for, as they can stay open for
+/// as long as their clients want. Returns whether all of the in-flight unary calls completed.
+///
+/// # Arguments
+/// * `deadline` - When to stop waiting.
+async fn drain_in_flight_calls(deadline: Instant) -> bool {
+    let mut interval =
+        tokio::time::interval(Duration::from_millis(DRAIN_POLL_INTERVAL_IN_MILLISECONDS));
+
+    loop {
+        interval.tick().await;
+
+        if metrics::grpc_server_in_flight_unary_calls() <= 0 {
+            return true;
+        }
+
+        if Instant::now() >= deadline {
+            return false;
+        }
+    }
+}
+
+/// Creates the Managed Subscribe module.
+///
+/// # Arguments
+/// * `module_settings` - The module's settings.
+/// * `entity_event_sender` - The sender for the core service's entity events.
+#[cfg(feature = "managed_subscribe")]
+async fn create_managed_subscribe_module(
+    module_settings: GrpcModuleSettings,
+    entity_event_sender: broadcast::Sender<EntityEvent>,
+) -> GrpcModuleResult {
+    // Initialize the Managed Subscribe module, which implements GrpcModule.
+    let mut managed_subscribe_module = match &module_settings.config_filename {
+        Some(config_filename) => {
+            ManagedSubscribeModule::new_with_config_filename(config_filename).await?
+        }
+        None => ManagedSubscribeModule::new().await?,
+    };
+
+    // Keep the module's store in sync with the entities that are removed from the core service.
+    managed_subscribe_module.entity_event_sender = Some(entity_event_sender);
+
+    Ok(Box::new(managed_subscribe_module))
+}
+
+/// Creates the Digital Twin Graph module.
+///
+/// # Arguments
+/// * `module_settings` - The module's settings.
+#[cfg(feature = "digital_twin_graph")]
+async fn create_digital_twin_graph_module(module_settings: GrpcModuleSettings) -> GrpcModuleResult {
+    // Initialize the Digital Twin Graph module, which implements GrpcModule.
+    let digital_twin_graph_module = match &module_settings.config_filename {
+        Some(config_filename) => {
+            DigitalTwinGraphModule::new_with_config_filename(config_filename).await?
+        }
+        None => DigitalTwinGraphModule::new().await?,
+    };
+
+    Ok(Box::new(digital_twin_graph_module))
+}
+
+/// Creates the Digital Twin Registry module.
+///
+/// # Arguments
+/// * `dtdl_model_catalog` - The DTDL models that registrations are validated against, if any.
+/// * `lease_eviction_interval` - The interval between checks for expired provider leases.
+/// * `is_provider_certificate_required` - Must the providers present a client certificate to
+///                                        register entities and renew leases?
+#[cfg(feature = "digital_twin_registry")]
+async fn create_digital_twin_registry_module(
+    dtdl_model_catalog: Option<Arc<DtdlModelCatalog>>,
+    lease_eviction_interval: Duration,
+    is_provider_certificate_required: bool,
+) -> GrpcModuleResult {
+    // Initialize the Digital Twin Registry module, which implements GrpcModule.
+    let digital_twin_registry_module = DigitalTwinRegistryModule::new(
+        dtdl_model_catalog,
+        lease_eviction_interval,
+        is_provider_certificate_required,
+    )
+    .await?;
+
+    Ok(Box::new(digital_twin_registry_module))
+}
+
+/// Creates the registry of the modules that are compiled into the service. Which of them are
+/// hosted is decided at runtime by the `modules` setting.
+///
+/// # Arguments
+/// * `entity_event_sender` - The sender for the core service's entity events, which modules
+///                           can subscribe to.
+/// * `dtdl_model_catalog` - The DTDL models that registrations are validated against, if any.
+/// * `lease_eviction_interval` - The interval between checks for expired leases.
+/// * `is_client_certificate_verified` - Does the app server verify client certificates? If it
+///                                      does, then the providers must present one to register
+///                                      with the Digital Twin Registry.
+///
+/// # How to add a Module to this function:
+/// 1. Add a function with the appropriate cfg feature flag that creates the boxed `GrpcModule`
+///    object. The module contributes its `GrpcInterceptor` object(s) - if applicable - through
+///    its `interceptors()` hook.
+/// 2. Add a block of code with the appropriate cfg feature flag.
+/// 3. Register a factory that calls the function with `.register_factory()`, using the module's
+///    name.
+#[allow(unused_mut, unused_variables)] // Necessary when no extra modules are built.
+fn create_module_registry(
+    entity_event_sender: broadcast::Sender<EntityEvent>,
+    dtdl_model_catalog: Option<Arc<DtdlModelCatalog>>,
+    lease_eviction_interval: Duration,
+    is_client_certificate_verified: bool,
+) -> GrpcModuleRegistry {
+    let mut module_registry = GrpcModuleRegistry::new();
+
+    #[cfg(feature = "managed_subscribe")]
+    // Registers the Managed Subscribe module.
+    module_registry.register_factory(
+        managed_subscribe_module::MODULE_NAME,
+        move |module_settings| {
+            create_managed_subscribe_module(module_settings, entity_event_sender.clone())
+        },
+    );
+
+    #[cfg(feature = "digital_twin_graph")]
+    // Registers the Digital Twin Graph module.
+    module_registry
+        .register_factory(digital_twin_graph_module::MODULE_NAME, create_digital_twin_graph_module);
+
+    #[cfg(feature = "digital_twin_registry")]
+    // Registers the Digital Twin Registry module.
+    module_registry.register_factory(digital_twin_registry_module::MODULE_NAME, move |_| {
+        create_digital_twin_registry_module(
+            dtdl_model_catalog.clone(),
+            lease_eviction_interval,
+            is_client_certificate_verified,
+        )
+    });
+
+    module_registry
+}
+
+/// Creates the registry of the interceptors that can be applied to the app server's calls from
+/// the `interceptors` setting. The modules' own interceptors are not included, because the
+/// modules apply them.
+///
+/// # How to add an Interceptor to this function:
+/// Register a factory that creates the boxed `AsyncGrpcInterceptor` object with
+/// `.register_factory()`, using the interceptor's name. A `GrpcInterceptor` object can be
+/// wrapped in a `SyncGrpcInterceptorAdapter`.
+fn create_interceptor_registry() -> GrpcInterceptorRegistry {
+    let mut interceptor_registry = GrpcInterceptorRegistry::new();
+
+    // Registers the Sample gRPC Interceptor, which logs the register requests and responses.
+    interceptor_registry.register_factory(sample_grpc_interceptor::INTERCEPTOR_NAME, || {
+        Box::new(SyncGrpcInterceptorAdapter::new(
+            SampleGrpcInterceptor::sample_grpc_interceptor_factory(),
+        ))
+    });
+
+    interceptor_registry
+}
+
+/// Creates the layers of the app server's configured interceptors. When auth is configured, its
+/// interceptor is the outermost, so that no other interceptor sees an unauthorized call.
+///
+/// # Arguments
+/// * `auth_settings` - The auth settings. If it is not provided, then calls are not authenticated.
+/// * `interceptor_registry` - The registry of the interceptors that can be configured.
+/// * `interceptor_settings_list` - The settings for the interceptors to apply. If it is not
+///                                 provided, then no other interceptors are applied.
+fn create_interceptor_layers(
+    auth_settings: Option<AuthSettings>,
+    interceptor_registry: GrpcInterceptorRegistry,
+    interceptor_settings_list: Option<Vec<GrpcInterceptorSettings>>,
+) -> Result<Vec<GrpcInterceptorLayer>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut interceptor_layers = Vec::new();
+
+    match auth_settings {
+        Some(auth_settings) => {
+            let auth_interceptor = AuthGrpcInterceptor::new(&auth_settings)
+                .map_err(|error| format!("Unable to create the auth interceptor: {error}"))?;
+            interceptor_layers.push(GrpcInterceptorLayer::new_async(Box::new(auth_interceptor)));
+            info!("Calls are authenticated with bearer tokens.");
+        }
+        None => info!("Calls are not authenticated."),
+    }
+
+    interceptor_layers.extend(
+        interceptor_registry.create_layers(&interceptor_settings_list.unwrap_or_default())?,
+    );
+
+    Ok(interceptor_layers)
+}
+
 /// Builds the enabled modules for the app server and starts the app server.
 ///
 /// # Arguments
-/// * `addr` - The address the server will be hosted on.
+/// * `addresses` - The addresses the server will be hosted on.
+/// * `unix_socket_mode` - The file mode of the Unix domain sockets that the servers are hosted on.
+///                        If it is not provided, then the umask's mode is kept.
 /// * `base_service` - The core service that will be hosted.
-///
-/// # How to add a Module to this method:
-/// 1. Add a block of code with the appropriate cfg feature flag.
-/// 2. Create the `GrpcModule` object within the block - if applicable.
-/// 3. Create the `GrpcInterceptorLayer` object(s) within the block - if applicable.
-/// 4. Add the grpc interceptors to the middleware stack with `.layer()`.
-/// 5. Call and return from the block `.add_module()` on the server with the updated middleware and
-/// module.
-#[allow(unused_assignments, unused_mut)] // Necessary when no extra modules are built.
+/// * `module_registry` - The registry of the modules that are compiled into the service.
+/// * `module_settings_list` - The settings for the modules to host. If it is not provided, then
+///                            all of the modules in the registry are hosted.
+/// * `interceptor_layers` - The layers of the configured interceptors, from the outermost to the
+///                          innermost.
+/// * `tls_config` - The app server's TLS config. If it is not provided, then TLS is not used.
+/// * `admin` - The address that the admin service is hosted on, and the admin service. If it is
+///             not provided, then the admin service is not hosted.
+/// * `chariott_registration` - Chariott's URI and the URI that the service is registered with it
+///                            by. The service is registered once everything that can fail to set
+///                            up has been set up, just before the servers start serving, and it is
+///                            unregistered as soon as a shutdown signal is received. If it is not
+///                            provided, then the service is not registered with Chariott.
+/// * `drain_timeout` - How long the service has to shut down once a shutdown signal is received:
+///                     to unregister from Chariott, complete the in-flight unary calls and shut
+///                     down the modules.
+#[allow(clippy::too_many_arguments)]
 async fn build_app_server_and_serve<S>(
-    addr: SocketAddr,
+    addresses: Vec<GrpcServerAddress>,
+    unix_socket_mode: Option<u32>,
     base_service: S,
+    module_registry: GrpcModuleRegistry,
+    module_settings_list: Option<Vec<GrpcModuleSettings>>,
+    interceptor_layers: Vec<GrpcInterceptorLayer>,
+    tls_config: Option<ServerTlsConfig>,
+    admin: Option<(GrpcServerAddress, AdminImpl)>,
+    chariott_registration: Option<(String, String)>,
+    drain_timeout: Duration,
 ) -> Result<(), Box<dyn std::error::Error>>
 where
     S: Service<http::Request<Body>, Response = http::Response<BoxBody>, Error = Infallible>
@@ -110,71 +451,199 @@ where
         + 'static,
     S::Future: Send + 'static,
 {
-    let mut server: GrpcServer<Identity> = GrpcServer::new(addr);
-
-    #[cfg(feature = "managed_subscribe")]
-    // Adds the Managed Subscribe module to the app server.
-    let mut server = {
-        // Initialize the Managed Subscribe module, which implements GrpcModule.
-        let managed_subscribe_module = ManagedSubscribeModule::new().await.map_err(|error| {
-            error!("Unable to create Managed Subscribe module.");
-            error
+    let module_settings_list =
+        module_settings_list.unwrap_or_else(|| module_registry.default_module_settings_list());
+
+    info!(
+        "The available modules are {:?}. The configured modules are {:?}.",
+        module_registry.module_names(),
+        module_settings_list
+            .iter()
+            .filter(|module_settings| module_settings.is_enabled())
+            .map(|module_settings| &module_settings.name)
+            .collect::<Vec<_>>()
+    );
+
+    let (mut server, loaded_modules) = module_registry
+        .build_server(addresses, &module_settings_list, interceptor_layers)
+        .await
+        .map_err(|error| {
+            error!("Unable to create the modules: {error}");
+            error as Box<dyn std::error::Error>
         })?;
 
-        // Create the interceptor layer to be added to the app server.
-        let managed_subscribe_layer =
-            GrpcInterceptorLayer::new(Box::new(managed_subscribe_module.create_interceptor()));
-
-        // Add the interceptor(s) to the middleware stack.
-        let current_middleware = server.middleware.clone();
-        let new_middleware = current_middleware.layer(managed_subscribe_layer);
+    // Start the modules' background tasks before the app server serves any requests.
+    loaded_modules.start().await.map_err(|error| {
+        error!("Unable to start the modules: {error}");
+        error as Box<dyn std::error::Error>
+    })?;
+
+    // Report the health of the services with the standard gRPC health service.
+    let (mut health_reporter, health_service) =
+        GrpcHealthReporter::new(loaded_modules.clone(), &[S::NAME]).await;
+    let health_report_task =
+        health_reporter.start(Duration::from_secs(HEALTH_REPORT_INTERVAL_IN_SECONDS));
+
+    // Describe the hosted services with the standard gRPC server reflection service.
+    let hosted_service_names: Vec<String> = std::iter::once(S::NAME.to_string())
+        .chain(
+            loaded_modules
+                .grpc_service_names()
+                .into_iter()
+                .flat_map(|(_module_name, service_names)| service_names),
+        )
+        .collect();
+    let reflection_service = grpc_reflection::create_reflection_service(&get_file_descriptor_sets(
+        &hosted_service_names,
+    ))
+    .map_err(|error| {
+        error!("Unable to create the server reflection service: {error}");
+        error
+    })?;
+    let reflection_v1_service = ServerReflectionV1Service::new(reflection_service.clone());
+
+    // The servers stop serving once they are told to on this channel.
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+
+    // Construct the app server, with a router for each of its addresses.
+    server.tls_config = tls_config;
+    let mut serve_futures: Vec<Pin<Box<dyn Future<Output = Result<(), tonic::transport::Error>>>>> =
+        Vec::new();
+    for address in server.addresses() {
+        let builder = server
+            .construct_server()
+            .map_err(|error| {
+                error!("Unable to construct the app server: {error}");
+                error
+            })?
+            .add_service(base_service.clone())
+            .add_service(health_service.clone())
+            .add_service(reflection_service.clone())
+            .add_service(reflection_v1_service.clone());
+
+        match address {
+            GrpcServerAddress::Tcp(socket_address) => {
+                serve_futures.push(Box::pin(
+                    builder.serve_with_shutdown(
+                        *socket_address,
+                        wait_for_shutdown(&shutdown_receiver),
+                    ),
+                ));
+            }
+            GrpcServerAddress::Unix(path) => {
+                let incoming = bind_unix_socket(path, unix_socket_mode).map_err(|error| {
+                    error!("Unable to bind the Unix domain socket '{}': {error}", path.display());
+                    error
+                })?;
+                serve_futures.push(Box::pin(builder.serve_with_incoming_shutdown(
+                    incoming,
+                    wait_for_shutdown(&shutdown_receiver),
+                )));
+            }
+        }
+    }
 
-        info!("Initialized Managed Subscribe module.");
+    // Host the admin service on its own address, without the app server's interceptors, so that
+    // access to it is controlled by where its address can be reached from.
+    if let Some((admin_address, mut admin_impl)) = admin {
+        admin_impl.loaded_modules = loaded_modules.clone();
+        let builder = Server::builder().add_service(AdminServer::new(admin_impl));
+
+        match &admin_address {
+            GrpcServerAddress::Tcp(socket_address) => {
+                serve_futures.push(Box::pin(
+                    builder.serve_with_shutdown(
+                        *socket_address,
+                        wait_for_shutdown(&shutdown_receiver),
+                    ),
+                ));
+            }
+            GrpcServerAddress::Unix(path) => {
+                let incoming = bind_unix_socket(path, unix_socket_mode).map_err(|error| {
+                    error!("Unable to bind the Unix domain socket '{}': {error}", path.display());
+                    error
+                })?;
+                serve_futures.push(Box::pin(builder.serve_with_incoming_shutdown(
+                    incoming,
+                    wait_for_shutdown(&shutdown_receiver),
+                )));
+            }
+        }
+        info!("The admin service is listening on address '{admin_address}'");
+    }
 
-        // Add the module with the updated middleware stack to the server.
-        server.add_module(new_middleware, Box::new(managed_subscribe_module))
-    };
+    // Register the invehicle digital twin service with Chariott only now, so that a service that
+    // fails to start does not leave its registration behind.
+    let mut registered_chariott_uri = None;
+    if let Some((chariott_uri, invehicle_digital_twin_uri)) = chariott_registration {
+        if let Err(error) = register_invehicle_digital_twin_service_with_chariott(
+            &chariott_uri,
+            &invehicle_digital_twin_uri,
+        )
+        .await
+        {
+            error!("Failed to register this service with Chariott: '{error}'");
+            health_report_task.abort();
+            loaded_modules.shutdown(Instant::now() + drain_timeout).await;
+            return Err(error.into());
+        }
+        info!("This service is now registered with Chariott.");
+        registered_chariott_uri = Some(chariott_uri);
+    }
 
-    #[cfg(feature = "digital_twin_graph")]
-    // Adds the Digital Twin Graph module to the app server.
-    let mut server = {
-        // Initialize the Digital Twin Graph module, which implements GrpcModule.
-        let digital_twin_graph_module = DigitalTwinGraphModule::new().await.map_err(|error| {
-            error!("Unable to create Digital Twin Graph module.");
-            error
-        })?;
+    // Start the app server. It stops serving all of its addresses when it fails on any of them.
+    let mut serve_future = try_join_all(serve_futures);
 
-        info!("Initialized Digital Twin Graph module.");
+    // On a shutdown signal, the service is unregistered from Chariott, so that new clients do not
+    // discover it, and the servers drain their in-flight unary calls, then they stop accepting
+    // calls and close their connections. Whatever is still in flight at the deadline is dropped.
+    let (serve_result, deadline) = tokio::select! {
+        serve_result = &mut serve_future => {
+            (serve_result.map(|_| ()), Instant::now() + drain_timeout)
+        }
+        _ = wait_for_shutdown_signal() => {
+            let deadline = Instant::now() + drain_timeout;
+            info!("Shutting down. The service has {drain_timeout:?} to shut down.");
+
+            health_report_task.abort();
+            unregister_from_chariott(&mut registered_chariott_uri, deadline).await;
+            health_reporter.report_not_serving().await;
+
+            if !drain_in_flight_calls(deadline).await {
+                warn!(
+                    "{} in-flight unary calls did not complete in time.",
+                    metrics::grpc_server_in_flight_unary_calls()
+                );
+            }
 
-        // Add the module with the updated middleware stack to the server.
-        server.add_module(server.middleware.clone(), Box::new(digital_twin_graph_module))
+            let _ = shutdown_sender.send(true);
+            let serve_result = match tokio::time::timeout_at(deadline, &mut serve_future).await {
+                Ok(serve_result) => serve_result.map(|_| ()),
+                Err(_) => {
+                    warn!("The servers did not close their connections in time.");
+                    Ok(())
+                }
+            };
+            (serve_result, deadline)
+        }
     };
 
-    #[cfg(feature = "digital_twin_registry")]
-    // Adds the Digital Twin Registry module to the app server.
-    let mut server = {
-        // Initialize the Digital Twin Registry module, which implements GrpcModule.
-        let digital_twin_registry_module =
-            DigitalTwinRegistryModule::new().await.map_err(|error| {
-                error!("Unable to create Digital Twin Registry module.");
-                error
-            })?;
-
-        info!("Initialized Digital Twin Registry module.");
-
-        // Add the module with the updated middleware stack to the server.
-        server.add_module(server.middleware.clone(), Box::new(digital_twin_registry_module))
-    };
+    // Unregister the invehicle digital twin service from Chariott if it was not unregistered on a
+    // shutdown signal, because the app server failed, so that it is no longer discovered.
+    unregister_from_chariott(&mut registered_chariott_uri, deadline).await;
 
-    // Construct the app server.
-    let builder = server.construct_server().add_service(base_service);
+    // Stop the modules' background tasks and release their resources once the app server has
+    // stopped, until the deadline.
+    health_report_task.abort();
+    loaded_modules.shutdown(deadline).await;
 
-    // Start the app server.
-    builder.serve(addr).await.map_err(|error| error.into())
+    serve_result.map_err(|error| error.into())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+
     let args: HashMap<String, Option<String>> = env::args()
         .skip(1)
         .map(|arg| {
@@ -200,7 +669,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .expect("No log-level value provided");
     let log_level =
         LevelFilter::from_str(log_level_arg.as_str()).expect("Could not parse log level");
-    Builder::new().filter(None, log_level).target(Target::Stdout).init();
+
+    // Load the config. It is loaded before the logging is set up, because it has the logging settings.
+    let settings = invehicle_digital_twin_config::load_settings();
+    logging::init_logging(log_level, settings.logging.as_ref())
+        .expect("Could not set up the logging");
 
     #[cfg(feature = "tokio_console")]
     {
@@ -211,40 +684,315 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("The In-Vehicle Digital Twin Service has started.");
 
-    // Load the config.
-    let settings = invehicle_digital_twin_config::load_settings();
-    let invehicle_digital_twin_authority = settings.invehicle_digital_twin_authority;
     let chariott_uri_option = settings.chariott_uri;
+    let shutdown_drain_timeout = Duration::from_secs(
+        settings
+            .shutdown_drain_timeout_in_seconds
+            .unwrap_or(DEFAULT_SHUTDOWN_DRAIN_TIMEOUT_IN_SECONDS),
+    );
+    let lease_eviction_interval = Duration::from_secs(
+        settings
+            .lease_eviction_interval_in_seconds
+            .unwrap_or(DEFAULT_LEASE_EVICTION_INTERVAL_IN_SECONDS),
+    );
+    if lease_eviction_interval.is_zero() {
+        error!("The lease_eviction_interval_in_seconds setting must be greater than zero.");
+        return Err("The lease eviction interval is zero".into());
+    }
+    if settings.persistence_snapshot_threshold == Some(0) {
+        error!("The persistence_snapshot_threshold setting must be greater than zero.");
+        return Err("The persistence snapshot threshold is zero".into());
+    }
+    if settings.endpoint_health_check_interval_in_seconds == Some(0) {
+        error!("The endpoint_health_check_interval_in_seconds setting must be greater than zero.");
+        return Err("The endpoint health check interval is zero".into());
+    }
+    let dtdl_model_summary_interval = Duration::from_secs(
+        settings
+            .dtdl_model_summary_interval_in_seconds
+            .unwrap_or(DEFAULT_DTDL_MODEL_SUMMARY_INTERVAL_IN_SECONDS),
+    );
+    if dtdl_model_summary_interval.is_zero() {
+        error!("The dtdl_model_summary_interval_in_seconds setting must be greater than zero.");
+        return Err("The DTDL model summary interval is zero".into());
+    }
+
+    // The app server is hosted on the TCP address and/or the Unix domain socket that were provided in the config.
+    let mut addresses = Vec::new();
+    for address in [
+        &settings.invehicle_digital_twin_authority,
+        &settings.invehicle_digital_twin_unix_socket_uri,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        addresses.push(GrpcServerAddress::parse(address).map_err(|error| {
+            error!("Invalid address for the app server: {error}");
+            error as Box<dyn std::error::Error>
+        })?);
+    }
+    if addresses.is_empty() {
+        error!("Either the invehicle_digital_twin_authority or the invehicle_digital_twin_unix_socket_uri setting must be provided.");
+        return Err("The app server has no address".into());
+    }
 
-    let addr: SocketAddr = invehicle_digital_twin_authority.parse()?;
+    // The file mode of the Unix domain sockets is given as an octal number, like chmod's.
+    let unix_socket_mode = match &settings.unix_socket_mode {
+        Some(unix_socket_mode) => match u32::from_str_radix(unix_socket_mode, 8) {
+            Ok(mode) if mode <= 0o777 => Some(mode),
+            _ => {
+                error!("The unix_socket_mode setting '{unix_socket_mode}' is not an octal file mode, e.g. \"660\".");
+                return Err("The Unix domain socket mode is not valid".into());
+            }
+        },
+        None => None,
+    };
 
-    let invehicle_digital_twin_address = format!("http://{invehicle_digital_twin_authority}"); // Devskim: ignore DS137138
-    info!("The HTTP server is listening on address '{invehicle_digital_twin_address}'");
+    // The admin service is hosted on its own address, if an admin address was provided in the config.
+    // It is not behind the interceptors, so its address must only be reachable from the local machine.
+    let admin_address = match &settings.admin_address {
+        Some(admin_address) => {
+            let admin_address = GrpcServerAddress::parse(admin_address).map_err(|error| {
+                error!("Invalid address for the admin service: {error}");
+                error as Box<dyn std::error::Error>
+            })?;
+            if addresses.contains(&admin_address) {
+                error!("The admin service's address '{admin_address}' must differ from the app server's addresses.");
+                return Err(
+                    "The admin service's address is one of the app server's addresses".into()
+                );
+            }
+            if !admin_address.is_local() {
+                error!("The admin service's address '{admin_address}' must be a loopback address or a Unix domain socket URI.");
+                return Err("The admin service's address is not a local address".into());
+            }
+            Some(admin_address)
+        }
+        None => {
+            info!("The admin service is not hosted.");
+            None
+        }
+    };
 
-    // Register the invehicle digital twin service with Chariott if Chariott's URI was provided in the config.
-    if chariott_uri_option.is_some() {
-        let response = register_invehicle_digital_twin_service_with_chariott(
-            &chariott_uri_option.unwrap(),
-            &invehicle_digital_twin_address,
-        )
-        .await;
-        if let Err(error) = response {
-            error!("Failed to register this service with Chariott: '{error}'");
-            return Err(error)?;
+    // Trace the gRPC calls if tracing settings were provided in the config.
+    match &settings.tracing {
+        Some(tracing_settings) => {
+            grpc_tracing::init_tracing(INVEHICLE_DIGITAL_TWIN_SERVICE_NAME, tracing_settings)
+                .map_err(|error| {
+                    error!("Failed to set up the tracing: {error}");
+                    error as Box<dyn std::error::Error>
+                })?;
         }
-        info!("This service is now registered with Chariott.");
-    } else {
-        info!("This service is not using Chariott.");
+        None => info!("The gRPC calls are not traced."),
+    }
+
+    // Use TLS for the app server and the outbound clients if TLS settings were provided in the config.
+    let tls_config = match &settings.tls {
+        Some(tls_settings) => {
+            grpc_channel::init_client_tls_config(tls_settings).map_err(|error| {
+                error!("Failed to load the client TLS settings: {error}");
+                error as Box<dyn std::error::Error>
+            })?;
+            let tls_config =
+                grpc_channel::create_server_tls_config(tls_settings).map_err(|error| {
+                    error!("Failed to load the server TLS settings: {error}");
+                    error as Box<dyn std::error::Error>
+                })?;
+            Some(tls_config)
+        }
+        None => None,
+    };
+    let is_client_certificate_verified = settings
+        .tls
+        .as_ref()
+        .and_then(|tls_settings| tls_settings.client_certificate_verification)
+        .is_some_and(|verification| verification != ClientCertificateVerification::None);
+
+    // The service is registered with Chariott by its first address, so the TCP address is preferred.
+    let invehicle_digital_twin_address = match &addresses[0] {
+        GrpcServerAddress::Tcp(socket_address) if tls_config.is_some() => {
+            format!("https://{socket_address}")
+        }
+        GrpcServerAddress::Tcp(socket_address) => format!("http://{socket_address}"), // Devskim: ignore DS137138
+        unix_socket_address @ GrpcServerAddress::Unix(_) => unix_socket_address.to_string(),
+    };
+    for address in &addresses {
+        info!("The HTTP server is listening on address '{address}'");
     }
 
-    let invehicle_digital_twin_impl = invehicle_digital_twin_impl::InvehicleDigitalTwinImpl {
+    // The service is registered with Chariott if Chariott's URI was provided in the config, once
+    // the app server is about to serve.
+    let chariott_registration = match chariott_uri_option {
+        Some(chariott_uri) => Some((chariott_uri, invehicle_digital_twin_address)),
+        None => {
+            info!("This service is not using Chariott.");
+            None
+        }
+    };
+
+    // Load the DTDL models if a DTDL model directory was provided in the config.
+    let dtdl_model_catalog = match &settings.dtdl_model_directory {
+        Some(dtdl_model_directory) => {
+            let dtdl_model_catalog = DtdlModelCatalog::load(Path::new(dtdl_model_directory))
+                .map_err(|error| {
+                    error!("Failed to load the DTDL models from '{dtdl_model_directory}': {error}");
+                    error
+                })?;
+            info!(
+                "Registrations are validated against the DTDL models in '{dtdl_model_directory}'."
+            );
+            Some(Arc::new(dtdl_model_catalog))
+        }
+        None => {
+            info!("Registrations are not validated against DTDL models.");
+            None
+        }
+    };
+
+    let entity_event_sender = create_entity_event_sender();
+
+    let mut invehicle_digital_twin_impl = invehicle_digital_twin_impl::InvehicleDigitalTwinImpl {
         entity_access_info_map: Arc::new(RwLock::new(HashMap::new())),
+        default_registration_policy: settings.registration_policy.unwrap_or_default(),
+        lease_map: Arc::new(RwLock::new(HashMap::new())),
+        entity_event_sender: entity_event_sender.clone(),
+        persistence: None,
+        endpoint_health_checker: None,
+        hide_unhealthy_endpoints: settings.hide_unhealthy_endpoints.unwrap_or_default(),
+        dtdl_model_catalog: dtdl_model_catalog.clone(),
+        change_lock: Arc::new(tokio::sync::Mutex::new(())),
+    };
+
+    // Recover the persisted entities before the service starts serving requests.
+    if let Some(persistence_directory) = settings.persistence_directory {
+        let snapshot_threshold = settings
+            .persistence_snapshot_threshold
+            .unwrap_or(DEFAULT_PERSISTENCE_SNAPSHOT_THRESHOLD);
+        invehicle_digital_twin_impl
+            .enable_persistence(Path::new(&persistence_directory), snapshot_threshold)
+            .map_err(|error| {
+                error!(
+                    "Failed to open the persistence directory '{persistence_directory}': {error}"
+                );
+                error
+            })?;
+        info!("The registered entities are persisted in '{persistence_directory}'.");
+    } else {
+        info!("The registered entities are not persisted.");
+    }
+
+    // Check the health of the registered endpoints if a health check interval was provided in the config.
+    if let Some(endpoint_health_check_interval_in_seconds) =
+        settings.endpoint_health_check_interval_in_seconds
+    {
+        let endpoint_health_checker = EndpointHealthChecker::default();
+        endpoint_health_checker.start(
+            invehicle_digital_twin_impl.entity_access_info_map.clone(),
+            Duration::from_secs(endpoint_health_check_interval_in_seconds),
+        );
+        invehicle_digital_twin_impl.endpoint_health_checker = Some(endpoint_health_checker);
+        info!("The registered endpoints' health is checked every {endpoint_health_check_interval_in_seconds} seconds.");
+    }
+
+    // Serve the metrics for Prometheus to scrape if a metrics authority was provided in the config.
+    if let Some(metrics_authority) = &settings.metrics_authority {
+        let metrics_address: SocketAddr = metrics_authority.parse().map_err(|error| {
+            error!("Invalid metrics authority '{metrics_authority}': {error}");
+            error
+        })?;
+        if let Err(error) =
+            invehicle_digital_twin_impl.register_metrics(prometheus::default_registry())
+        {
+            warn!("Unable to register the registered entity metrics: {error}");
+        }
+        tokio::spawn(async move {
+            if let Err(error) = metrics::serve_metrics(metrics_address).await {
+                error!("The metrics server failed: {error}");
+            }
+        });
+    } else {
+        info!("The metrics are not served.");
+    }
+
+    // Evict the registrations whose leases have expired.
+    invehicle_digital_twin_impl.start_lease_eviction(lease_eviction_interval);
+
+    // Log which of the DTDL models have no provider, if DTDL models were loaded.
+    invehicle_digital_twin_impl.start_dtdl_model_summary(dtdl_model_summary_interval);
+
+    // The admin service reports the core service's registered entities and the effective config.
+    let admin = match admin_address {
+        Some(admin_address) => {
+            let effective_config =
+                invehicle_digital_twin_config::load_effective_config().map_err(|error| {
+                    error!("Unable to load the effective config: {error}");
+                    error
+                })?;
+            // The auth interceptor is listed by the name of its setting.
+            let interceptor_names = settings
+                .auth
+                .as_ref()
+                .map(|_| String::from("auth"))
+                .into_iter()
+                .chain(
+                    settings
+                        .interceptors
+                        .iter()
+                        .flatten()
+                        .filter(|interceptor_settings| interceptor_settings.is_enabled())
+                        .map(|interceptor_settings| interceptor_settings.name.clone()),
+                )
+                .collect();
+            let admin_impl = AdminImpl::new(
+                start_time,
+                effective_config,
+                interceptor_names,
+                invehicle_digital_twin_impl.entity_access_info_map.clone(),
+                invehicle_digital_twin_impl.lease_map.clone(),
+            );
+            Some((admin_address, admin_impl))
+        }
+        None => None,
     };
 
     let base_service = InvehicleDigitalTwinServer::new(invehicle_digital_twin_impl);
 
+    let module_registry = create_module_registry(
+        entity_event_sender,
+        dtdl_model_catalog,
+        lease_eviction_interval,
+        is_client_certificate_verified,
+    );
+
+    let interceptor_layers = create_interceptor_layers(
+        settings.auth,
+        create_interceptor_registry(),
+        settings.interceptors,
+    )
+    .map_err(|error| {
+        error!("Unable to create the interceptors: {error}");
+        error as Box<dyn std::error::Error>
+    })?;
+
     // Build and start the app server.
-    build_app_server_and_serve(addr, base_service).await?;
+    let serve_result = build_app_server_and_serve(
+        addresses,
+        unix_socket_mode,
+        base_service,
+        module_registry,
+        settings.modules,
+        interceptor_layers,
+        tls_config,
+        admin,
+        chariott_registration,
+        shutdown_drain_timeout,
+    )
+    .await;
+
+    // Export the spans that have not been exported yet.
+    grpc_tracing::shutdown_tracing().await;
+
+    serve_result?;
 
     debug!("The Digital Twin Service has completed.");
 
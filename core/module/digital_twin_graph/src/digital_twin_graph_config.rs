@@ -5,13 +5,17 @@
 use common::utils;
 use serde_derive::Deserialize;
 
-const DEFAULT_CONFIG_FILENAME: &str = "digital_twin_graph_settings";
+pub const DEFAULT_CONFIG_FILENAME: &str = "digital_twin_graph_settings";
 
 /// The settings for the digital twin graph service.
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct Settings {
     /// The authority (address + optional port in the format "<address>[:<port>]") for the Ibeji application server.
-    pub base_authority: String,
+    /// It is reached over http. It is only used when the base_uri is not provided.
+    pub base_authority: Option<String>,
+    /// The full URI of the Ibeji application server, e.g. "https://localhost:5010" or
+    /// "unix:///run/ibeji/invehicle_digital_twin.sock".
+    pub base_uri: Option<String>,
 }
 
 /// Load the settings.
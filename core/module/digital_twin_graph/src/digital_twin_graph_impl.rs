@@ -2,6 +2,9 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+use common::grpc_channel;
+use common::grpc_tracing::{TracedChannel, TRACER_NAME};
+use common::logging;
 use common::utils::is_subset;
 use core_protobuf_data_access::async_rpc::v1::request::{
     request_client::RequestClient, AskRequest,
@@ -16,24 +19,42 @@ use core_protobuf_data_access::module::digital_twin_registry::v1::{
     EntityAccessInfo, FindByInstanceIdRequest, FindByInstanceIdResponse, FindByModelIdRequest,
     FindByModelIdResponse,
 };
+use lazy_static::lazy_static;
 use log::{debug, warn};
+use opentelemetry::trace::{Link, Span, Status, TraceContextExt, Tracer};
+use opentelemetry::{global, KeyValue};
+use prometheus::{register_histogram, register_int_counter, Histogram, IntCounter};
 use std::sync::Arc;
 use tokio::sync::broadcast;
-use tokio::time::{sleep, timeout, Duration};
+use tokio::time::{sleep, timeout, Duration, Instant};
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
 use tokio_retry::Retry;
 use uuid::Uuid;
 
+use crate::respond_impl::ReceivedAnswer;
 use crate::{digital_twin_operation, digital_twin_protocol, TargetedPayload};
 
+lazy_static! {
+    static ref ANSWER_ROUND_TRIP_SECONDS: Histogram = register_histogram!(
+        "digital_twin_graph_answer_round_trip_seconds",
+        "How long the providers took to answer the asks, in seconds."
+    )
+    .expect("Unable to register the digital_twin_graph_answer_round_trip_seconds metric");
+    static ref ANSWER_TIMEOUTS_TOTAL: IntCounter = register_int_counter!(
+        "digital_twin_graph_answer_timeouts_total",
+        "The number of asks that no answer was received for before the retries ran out."
+    )
+    .expect("Unable to register the digital_twin_graph_answer_timeouts_total metric");
+}
+
 #[derive(Debug)]
 pub struct DigitalTwinGraphImpl {
     /// Digital Twin Registry URI.
     digital_twin_registry_uri: String,
     /// Respond URI.
     respond_uri: String,
-    /// The sender for the asynchronous channel for AnswerRequests.
-    tx: Arc<broadcast::Sender<AnswerRequest>>,
+    /// The sender for the asynchronous channel for ReceivedAnswers.
+    tx: Arc<broadcast::Sender<ReceivedAnswer>>,
 }
 
 impl DigitalTwinGraphImpl {
@@ -51,11 +72,11 @@ impl DigitalTwinGraphImpl {
     /// # Arguments
     /// * `digital_twin_registry_uri` - The uri for the digital twin registry service.
     /// * `respond_uri` - The uri for the respond service.
-    /// * `tx` - The sender for the asynchronous channel for AnswerRequest's.
+    /// * `tx` - The sender for the asynchronous channel for ReceivedAnswer's.
     pub fn new(
         digital_twin_registry_uri: &str,
         respond_uri: &str,
-        tx: Arc<broadcast::Sender<AnswerRequest>>,
+        tx: Arc<broadcast::Sender<ReceivedAnswer>>,
     ) -> DigitalTwinGraphImpl {
         DigitalTwinGraphImpl {
             digital_twin_registry_uri: digital_twin_registry_uri.to_string(),
@@ -83,10 +104,10 @@ impl DigitalTwinGraphImpl {
             .take(Self::MAX_RETRIES);
 
         let response: FindByModelIdResponse = Retry::spawn(retry_strategy.clone(), || async {
-            let mut client =
-                DigitalTwinRegistryClient::connect(self.digital_twin_registry_uri.to_string())
-                    .await
-                    .map_err(|error| tonic::Status::internal(format!("{error}")))?;
+            let mut client = grpc_channel::connect(&self.digital_twin_registry_uri)
+                .await
+                .map(DigitalTwinRegistryClient::new)
+                .map_err(|error| tonic::Status::internal(format!("{error}")))?;
 
             let request =
                 tonic::Request::new(FindByModelIdRequest { model_id: model_id.to_string() });
@@ -125,10 +146,10 @@ impl DigitalTwinGraphImpl {
             .take(Self::MAX_RETRIES);
 
         let response: FindByInstanceIdResponse = Retry::spawn(retry_strategy.clone(), || async {
-            let mut client =
-                DigitalTwinRegistryClient::connect(self.digital_twin_registry_uri.to_string())
-                    .await
-                    .map_err(|error| tonic::Status::internal(format!("{error}")))?;
+            let mut client = grpc_channel::connect(&self.digital_twin_registry_uri)
+                .await
+                .map(DigitalTwinRegistryClient::new)
+                .map_err(|error| tonic::Status::internal(format!("{error}")))?;
 
             let request = tonic::Request::new(FindByInstanceIdRequest {
                 instance_id: instance_id.to_string(),
@@ -159,7 +180,7 @@ impl DigitalTwinGraphImpl {
     /// * `targeted_payload` - The targeted payload.
     pub async fn send_ask(
         &self,
-        mut client: RequestClient<tonic::transport::Channel>,
+        mut client: RequestClient<TracedChannel>,
         respond_uri: &str,
         ask_id: &str,
         targeted_payload: &TargetedPayload,
@@ -181,25 +202,44 @@ impl DigitalTwinGraphImpl {
         Ok(())
     }
 
-    /// Wait for the answer.
+    /// Wait for the answer. The time from when the ask was sent until the answer is received is
+    /// recorded in the metrics. The wait is traced, and the receipt of the answer is traced with a
+    /// span that is linked to the answer call's span. The answer is a separate call from the
+    /// provider, so its span is only in the ask's trace when the provider passes the ask's trace
+    /// context on to it.
     ///
     /// # Arguments
     /// * `ask_id` - The ask id.
-    /// * `rx` - The receiver for the asynchronous channel for AnswerRequest's.
+    /// * `rx` - The receiver for the asynchronous channel for ReceivedAnswer's.
     pub async fn wait_for_answer(
         &self,
         ask_id: String,
-        rx: &mut broadcast::Receiver<AnswerRequest>,
+        rx: &mut broadcast::Receiver<ReceivedAnswer>,
     ) -> Result<AnswerRequest, tonic::Status> {
+        let start = Instant::now();
+        let tracer = global::tracer(TRACER_NAME);
+        let context = opentelemetry::Context::current_with_span(tracer.start("wait_for_answer"));
+        let span = context.span();
+        span.set_attribute(KeyValue::new("async_rpc.ask_id", ask_id.clone()));
+
         let mut answer_request: AnswerRequest = Default::default();
         let mut attempts_after_failure = 0;
         const MAX_ATTEMPTS_AFTER_FAILURE: u8 = 10;
         while attempts_after_failure < MAX_ATTEMPTS_AFTER_FAILURE {
             match timeout(Duration::from_millis(Self::TIMEOUT_PERIOD_IN_MILLIS), rx.recv()).await {
-                Ok(Ok(request)) => {
-                    if ask_id == request.ask_id {
+                Ok(Ok(received_answer)) => {
+                    if ask_id == received_answer.answer_request.ask_id {
                         // We have received the answer request that we are expecting.
-                        answer_request = request;
+                        ANSWER_ROUND_TRIP_SECONDS.observe(start.elapsed().as_secs_f64());
+                        tracer
+                            .span_builder("answer_received")
+                            .with_links(vec![Link::new(
+                                received_answer.span_context.clone(),
+                                vec![KeyValue::new("async_rpc.ask_id", ask_id.clone())],
+                            )])
+                            .start_with_context(&tracer, &context)
+                            .end();
+                        answer_request = received_answer.answer_request;
                         break;
                     } else {
                         // Ignore this answer request, as it is not the one that we are expecting.
@@ -215,6 +255,7 @@ impl DigitalTwinGraphImpl {
                 }
                 Err(error_message) => {
                     warn!("Failed to receive the answer request.  The error message is '{}'.  We may retry in a moment.", error_message);
+                    span.add_event("timed out", vec![]);
                     sleep(Duration::from_secs(1)).await;
                     attempts_after_failure += 1;
                     continue;
@@ -222,6 +263,12 @@ impl DigitalTwinGraphImpl {
             }
         }
 
+        if attempts_after_failure == MAX_ATTEMPTS_AFTER_FAILURE {
+            ANSWER_TIMEOUTS_TOTAL.inc();
+            span.set_status(Status::error("No answer was received"));
+        }
+        span.end();
+
         Ok(answer_request)
     }
 }
@@ -288,7 +335,7 @@ impl DigitalTwinGraph for DigitalTwinGraphImpl {
             let tx = self.tx.clone();
             let mut rx = tx.subscribe();
 
-            let client_result = RequestClient::connect(provider_uri.clone()).await;
+            let client_result = grpc_channel::connect(&provider_uri).await.map(RequestClient::new);
             if client_result.is_err() {
                 warn!("Unable to connect. We will skip this one.");
                 continue;
@@ -335,6 +382,7 @@ impl DigitalTwinGraph for DigitalTwinGraphImpl {
     ) -> Result<tonic::Response<GetResponse>, tonic::Status> {
         let get_request = request.into_inner();
         let instance_id = get_request.instance_id;
+        logging::set_instance_id(&instance_id);
         let member_path = get_request.member_path;
 
         if instance_id.is_empty() {
@@ -368,7 +416,7 @@ impl DigitalTwinGraph for DigitalTwinGraphImpl {
         let mut rx = tx.subscribe();
 
         // Connect to the provider where we will send the ask to get the instance's value.
-        let client_result = RequestClient::connect(provider_uri.clone()).await;
+        let client_result = grpc_channel::connect(&provider_uri).await.map(RequestClient::new);
         if client_result.is_err() {
             return Err(tonic::Status::internal("Unable to connect to the provider."));
         }
@@ -422,6 +470,7 @@ impl DigitalTwinGraph for DigitalTwinGraphImpl {
     ) -> Result<tonic::Response<InvokeResponse>, tonic::Status> {
         let invoke_request = request.into_inner();
         let instance_id = invoke_request.instance_id;
+        logging::set_instance_id(&instance_id);
         let member_path = invoke_request.member_path;
         let request_payload = invoke_request.request_payload;
 
@@ -459,7 +508,7 @@ impl DigitalTwinGraph for DigitalTwinGraphImpl {
         let tx = self.tx.clone();
         let mut rx = tx.subscribe();
 
-        let client_result = RequestClient::connect(provider_uri.clone()).await;
+        let client_result = grpc_channel::connect(&provider_uri).await.map(RequestClient::new);
         if client_result.is_err() {
             return Err(tonic::Status::internal("Unable to connect to the provider."));
         }
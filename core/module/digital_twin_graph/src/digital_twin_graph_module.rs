@@ -3,42 +3,87 @@
 // SPDX-License-Identifier: MIT
 
 use common::grpc_module::GrpcModule;
+use common::utils;
 use core_protobuf_data_access::async_rpc::v1::respond::respond_server::RespondServer;
 use core_protobuf_data_access::module::digital_twin_graph::v1::digital_twin_graph_server::DigitalTwinGraphServer;
+use std::error::Error;
 use std::sync::Arc;
 use tokio::sync::broadcast;
+use tonic::server::NamedService;
 use tonic::transport::server::RoutesBuilder;
 
 use crate::digital_twin_graph_config;
 use crate::digital_twin_graph_impl::DigitalTwinGraphImpl;
 use crate::respond_impl::RespondImpl;
 
+/// The name that the module is referred to by in the In-Vehicle Digital Twin Service's settings.
+pub const MODULE_NAME: &str = "digital_twin_graph";
+
 /// The capacity of the broadcast channel.
 const BROADCAST_CHANNEL_CAPACITY: usize = 100;
 
 /// Digital Twin Graph Module.
 #[derive(Clone, Debug)]
-pub struct DigitalTwinGraphModule {}
+pub struct DigitalTwinGraphModule {
+    /// The name of the module's config file.
+    config_filename: String,
+    /// The URI of the server that hosts the module. It is loaded from the module's settings when
+    /// the module is initialized.
+    base_uri: Option<String>,
+}
 
 impl DigitalTwinGraphModule {
     /// Creates a new instance of the DigitalTwinGraphModule.
     pub async fn new() -> Result<Self, tonic::Status> {
-        Ok(Self {})
+        Self::new_with_config_filename(digital_twin_graph_config::DEFAULT_CONFIG_FILENAME).await
+    }
+
+    /// Creates a new instance of the DigitalTwinGraphModule that loads its settings from the
+    /// specified config file.
+    ///
+    /// # Arguments
+    /// * `config_filename` - The name of the config file.
+    pub async fn new_with_config_filename(config_filename: &str) -> Result<Self, tonic::Status> {
+        Ok(Self { config_filename: config_filename.to_string(), base_uri: None })
     }
 }
 
+#[tonic::async_trait]
 impl GrpcModule for DigitalTwinGraphModule {
+    /// Initializes the module by loading its settings.
+    async fn init(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Load the config.
+        let settings =
+            utils::load_settings::<digital_twin_graph_config::Settings>(&self.config_filename)
+                .map_err(|error| {
+                    format!("Unable to load the config file '{}': {error}", self.config_filename)
+                })?;
+        self.base_uri =
+            Some(
+                utils::get_base_uri(
+                    settings.base_uri.as_deref(),
+                    settings.base_authority.as_deref(),
+                )
+                .map_err(|error| {
+                    format!("Invalid config file '{}': {error}", self.config_filename)
+                })?,
+            );
+
+        Ok(())
+    }
+
     /// Adds the gRPC services for this module to the server builder.
     ///
     /// # Arguments
     /// * `builder` - A tonic::RoutesBuilder that contains the grpc services to build.
     fn add_grpc_services(&self, builder: &mut RoutesBuilder) {
-        // Load the config.
-        let settings = digital_twin_graph_config::load_settings();
-        let base_authority = settings.base_authority;
+        let base_uri = self.base_uri.as_ref().expect(
+            "The Digital Twin Graph module must be initialized before its services are added",
+        );
 
-        let invehicle_digital_twin_uri = format!("http://{base_authority}"); // Devskim: ignore DS137138
-        let respond_uri = format!("http://{base_authority}"); // Devskim: ignore DS137138
+        // Both services are hosted by the same server as the module.
+        let invehicle_digital_twin_uri = base_uri;
+        let respond_uri = base_uri;
 
         let (tx, _rx) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
         let tx = Arc::new(tx);
@@ -49,10 +94,18 @@ impl GrpcModule for DigitalTwinGraphModule {
 
         // Setup the digital twin graph service.
         let digital_twin_graph_impl =
-            DigitalTwinGraphImpl::new(&invehicle_digital_twin_uri, &respond_uri, tx);
+            DigitalTwinGraphImpl::new(invehicle_digital_twin_uri, respond_uri, tx);
         let digital_twin_graph_service = DigitalTwinGraphServer::new(digital_twin_graph_impl);
 
         builder.add_service(digital_twin_graph_service);
         builder.add_service(respond_service);
     }
+
+    /// The full names of the gRPC services for this module.
+    fn grpc_service_names(&self) -> Vec<String> {
+        vec![
+            <DigitalTwinGraphServer<DigitalTwinGraphImpl> as NamedService>::NAME.to_string(),
+            <RespondServer<RespondImpl> as NamedService>::NAME.to_string(),
+        ]
+    }
 }
@@ -5,20 +5,32 @@
 use core_protobuf_data_access::async_rpc::v1::respond::respond_server::Respond;
 use core_protobuf_data_access::async_rpc::v1::respond::{AnswerRequest, AnswerResponse};
 use log::debug;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use opentelemetry::KeyValue;
 use std::sync::Arc;
 use tokio::sync::broadcast;
 
+/// An answer request, with the span context of the answer call that delivered it, so that the ask
+/// that it answers can refer to the call's trace.
+#[derive(Clone, Debug)]
+pub struct ReceivedAnswer {
+    /// The answer request.
+    pub answer_request: AnswerRequest,
+    /// The span context of the answer call.
+    pub span_context: SpanContext,
+}
+
 #[derive(Debug)]
 pub struct RespondImpl {
-    pub tx: Arc<broadcast::Sender<AnswerRequest>>,
+    pub tx: Arc<broadcast::Sender<ReceivedAnswer>>,
 }
 
 impl RespondImpl {
     /// Create a new instance of a RespondImpl.
     ///
     /// # Arguments
-    /// * `tx` - The sender for the asynchronous channel for AnswerRequests.
-    pub fn new(tx: Arc<broadcast::Sender<AnswerRequest>>) -> RespondImpl {
+    /// * `tx` - The sender for the asynchronous channel for ReceivedAnswers.
+    pub fn new(tx: Arc<broadcast::Sender<ReceivedAnswer>>) -> RespondImpl {
         RespondImpl { tx }
     }
 }
@@ -37,8 +49,17 @@ impl Respond for RespondImpl {
 
         let tx = Arc::clone(&self.tx);
 
+        // The answer call's span is the current span. It is tagged with the ask id, so that it can
+        // be found from the ask's trace.
+        let answer_request = request.into_inner();
+        let context = opentelemetry::Context::current();
+        let span = context.span();
+        span.set_attribute(KeyValue::new("async_rpc.ask_id", answer_request.ask_id.clone()));
+        let received_answer =
+            ReceivedAnswer { answer_request, span_context: span.span_context().clone() };
+
         // Send the request to the channel.
-        if let Err(err_msg) = tx.send(request.into_inner()) {
+        if let Err(err_msg) = tx.send(received_answer) {
             return Err(tonic::Status::internal(format!(
                 "Failed to send the answer request due to: {err_msg}"
             )));
@@ -4,22 +4,39 @@
 
 extern crate iref;
 
+use common::dtdl_model_catalog::DtdlModelCatalog;
+use common::dtmi::Dtmi;
+use common::grpc_status_details::status_with_typed_details;
+use common::lease::Lease;
+use common::logging;
 use core_protobuf_data_access::module::digital_twin_registry::v1::digital_twin_registry_server::DigitalTwinRegistry;
 use core_protobuf_data_access::module::digital_twin_registry::v1::{
-    EntityAccessInfo, FindByInstanceIdRequest, FindByInstanceIdResponse, FindByModelIdRequest,
-    FindByModelIdResponse, RegisterRequest, RegisterResponse,
+    EntityAccessInfo, EntityRegistrationStatus, FindByInstanceIdRequest, FindByInstanceIdResponse,
+    FindByModelIdRequest, FindByModelIdResponse, RegisterRequest, RegisterResponse,
+    RenewLeaseRequest, RenewLeaseResponse,
 };
 use log::{debug, info};
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::vec::Vec;
-use tonic::{Request, Response, Status};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tonic::{Code, Request, Response, Status};
 
-#[derive(Debug, Default)]
+/// The fully qualified protobuf name of the register response, which a failed register call
+/// carries in its status details.
+const REGISTER_RESPONSE_TYPE_NAME: &str =
+    "digital_twin_registry.v1.digital_twin_registry.RegisterResponse";
+
+#[derive(Clone, Debug, Default)]
 pub struct DigitalTwinRegistryImpl {
     /// Entity access info map.
     pub entity_access_info_map: Arc<RwLock<HashMap<String, Vec<EntityAccessInfo>>>>,
+    /// The leases for the providers that registered with one, keyed by provider id.
+    pub lease_map: Arc<RwLock<HashMap<String, Lease>>>,
+    /// The DTDL models that registrations are validated against, when they have been loaded.
+    pub dtdl_model_catalog: Option<Arc<DtdlModelCatalog>>,
 }
 
 #[tonic::async_trait]
@@ -66,6 +83,7 @@ impl DigitalTwinRegistry for DigitalTwinRegistryImpl {
         request: Request<FindByInstanceIdRequest>,
     ) -> Result<Response<FindByInstanceIdResponse>, Status> {
         let instance_id = request.into_inner().instance_id;
+        logging::set_instance_id(&instance_id);
 
         debug!("Received a find_by_instance_id request for instance id {instance_id}");
 
@@ -107,9 +125,21 @@ impl DigitalTwinRegistry for DigitalTwinRegistryImpl {
         request: Request<RegisterRequest>,
     ) -> Result<Response<RegisterResponse>, Status> {
         let request_inner = request.into_inner();
+        let entity_access_info_list = request_inner.entity_access_info_list;
+
+        let results: Vec<Result<(), Status>> = if request_inner.atomic {
+            self.register_entities_atomically(&entity_access_info_list)
+        } else {
+            entity_access_info_list
+                .iter()
+                .map(|entity_access_info| self.register_entity(entity_access_info))
+                .collect()
+        };
 
-        for entity_access_info in &request_inner.entity_access_info_list {
-            self.register_entity(entity_access_info)?;
+        for (entity_access_info, result) in entity_access_info_list.iter().zip(&results) {
+            if result.is_err() {
+                continue;
+            }
 
             info!(
                 "Registered the entity with provider id: {} instance id: {} model id: {}",
@@ -117,12 +147,42 @@ impl DigitalTwinRegistry for DigitalTwinRegistryImpl {
                 entity_access_info.instance_id,
                 entity_access_info.model_id
             );
-        }
 
-        let response = RegisterResponse {};
+            if request_inner.lease_ttl_in_seconds > 0 {
+                self.set_lease(&entity_access_info.provider_id, request_inner.lease_ttl_in_seconds);
+            }
+        }
 
         debug!("Completed the register request.");
 
+        Self::create_register_response(&entity_access_info_list, results)
+    }
+
+    /// Renew lease implementation.
+    ///
+    /// # Arguments
+    /// * `request` - Renew lease request.
+    async fn renew_lease(
+        &self,
+        request: Request<RenewLeaseRequest>,
+    ) -> Result<Response<RenewLeaseResponse>, Status> {
+        let provider_id = request.into_inner().provider_id;
+
+        debug!("Received a renew_lease request for provider id {provider_id}");
+
+        // This block controls the lifetime of the lock.
+        {
+            let mut lock: RwLockWriteGuard<HashMap<String, Lease>> = self.lease_map.write();
+            let lease = lock.get_mut(&provider_id).ok_or_else(|| {
+                Status::not_found(format!("Unable to find a lease for provider id {provider_id}"))
+            })?;
+            lease.renew();
+        }
+
+        let response = RenewLeaseResponse {};
+
+        debug!("Completed the renew_lease request.");
+
         Ok(Response::new(response))
     }
 }
@@ -133,6 +193,53 @@ impl DigitalTwinRegistryImpl {
     /// # Arguments
     /// * `entity` - The entity.
     fn register_entity(&self, entity_access_info: &EntityAccessInfo) -> Result<(), Status> {
+        self.validate_entity_access_info(entity_access_info)?;
+        self.insert_entities([entity_access_info]);
+
+        Ok(())
+    }
+
+    /// Register all of the entities, or none of them if any of them is invalid.
+    /// Returns the outcome for each entity. When the batch fails, the valid entities have an
+    /// aborted status.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_list` - The entities.
+    fn register_entities_atomically(
+        &self,
+        entity_access_info_list: &[EntityAccessInfo],
+    ) -> Vec<Result<(), Status>> {
+        let results: Vec<Result<(), Status>> = entity_access_info_list
+            .iter()
+            .map(|entity_access_info| self.validate_entity_access_info(entity_access_info))
+            .collect();
+
+        if results.iter().any(Result::is_err) {
+            return results
+                .into_iter()
+                .map(|result| {
+                    result.and(Err(Status::aborted(
+                        "The entity was not registered, because another entity in the batch could not be registered.",
+                    )))
+                })
+                .collect();
+        }
+
+        // Entities are only ever added to a model id's list, so once every entity is valid the
+        // inserts cannot fail.
+        self.insert_entities(entity_access_info_list);
+
+        results
+    }
+
+    /// Validate an entity before it is registered.
+    ///
+    /// # Arguments
+    /// * `entity` - The entity.
+    fn validate_entity_access_info(
+        &self,
+        entity_access_info: &EntityAccessInfo,
+    ) -> Result<(), Status> {
         if entity_access_info.provider_id.is_empty() {
             return Err(Status::invalid_argument("Provider id is required"));
         }
@@ -141,6 +248,18 @@ impl DigitalTwinRegistryImpl {
             return Err(Status::invalid_argument("Model id is required"));
         }
 
+        if let Err(error) = Dtmi::parse(&entity_access_info.model_id) {
+            return Err(Status::invalid_argument(format!(
+                "Model id '{}' is not a valid DTMI: {error}",
+                entity_access_info.model_id
+            )));
+        }
+
+        if let Some(dtdl_model_catalog) = &self.dtdl_model_catalog {
+            dtdl_model_catalog
+                .validate(&entity_access_info.model_id, &entity_access_info.operations)?;
+        }
+
         if entity_access_info.instance_id.is_empty() {
             return Err(Status::invalid_argument("Instance id is required"));
         }
@@ -157,40 +276,165 @@ impl DigitalTwinRegistryImpl {
             return Err(Status::invalid_argument("Operations is required"));
         }
 
+        Ok(())
+    }
+
+    /// Insert validated entities into the map of registered entities. They are inserted under
+    /// one lock, so that no lookup sees some of them without the others.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_list` - The entities.
+    fn insert_entities<'a>(
+        &self,
+        entity_access_info_list: impl IntoIterator<Item = &'a EntityAccessInfo>,
+    ) {
         // This block controls the lifetime of the lock.
         {
             // Note: the context is optional.
 
             let mut lock: RwLockWriteGuard<HashMap<String, Vec<EntityAccessInfo>>> =
                 self.entity_access_info_map.write();
-            let get_result = lock.get(&entity_access_info.model_id);
-            match get_result {
-                Some(_) => {
-                    info!(
-                        "Registered another entity access info for entity {}",
-                        &entity_access_info.model_id
-                    );
-                    lock.get_mut(&entity_access_info.model_id)
-                        .unwrap()
-                        .push(entity_access_info.clone());
+            for entity_access_info in entity_access_info_list {
+                let get_result = lock.get(&entity_access_info.model_id);
+                match get_result {
+                    Some(_) => {
+                        info!(
+                            "Registered another entity access info for entity {}",
+                            &entity_access_info.model_id
+                        );
+                        lock.get_mut(&entity_access_info.model_id)
+                            .unwrap()
+                            .push(entity_access_info.clone());
+                    }
+                    None => {
+                        info!("Registered entity {}", &entity_access_info.model_id);
+                        lock.insert(
+                            entity_access_info.model_id.clone(),
+                            vec![entity_access_info.clone()],
+                        );
+                    }
+                };
+            }
+        }
+    }
+
+    /// Create the register response from the outcome of each entity's registration.
+    /// When any entity was not registered, the first failure's status is returned instead. Its
+    /// details are a google.rpc.Status that carries the register response.
+    ///
+    /// # Arguments
+    /// * `entity_access_info_list` - The entities in the register request.
+    /// * `results` - The outcome for each entity.
+    fn create_register_response(
+        entity_access_info_list: &[EntityAccessInfo],
+        results: Vec<Result<(), Status>>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let mut first_failure: Option<(Code, String)> = None;
+
+        let entity_status_list = entity_access_info_list
+            .iter()
+            .zip(results)
+            .map(|(entity_access_info, result)| {
+                let (code, message) = match result {
+                    Ok(()) => (Code::Ok, String::new()),
+                    Err(status) => (status.code(), status.message().to_string()),
+                };
+                if code != Code::Ok && first_failure.is_none() {
+                    first_failure = Some((code, message.clone()));
                 }
-                None => {
-                    info!("Registered entity {}", &entity_access_info.model_id);
-                    lock.insert(
-                        entity_access_info.model_id.clone(),
-                        vec![entity_access_info.clone()],
-                    );
+                EntityRegistrationStatus {
+                    model_id: entity_access_info.model_id.clone(),
+                    instance_id: entity_access_info.instance_id.clone(),
+                    code: code as i32,
+                    message,
                 }
-            };
+            })
+            .collect();
+
+        let response = RegisterResponse { entity_status_list };
+
+        match first_failure {
+            None => Ok(Response::new(response)),
+            Some((code, message)) => Err(status_with_typed_details(
+                code,
+                message,
+                REGISTER_RESPONSE_TYPE_NAME,
+                &response,
+            )),
         }
+    }
 
-        Ok(())
+    /// Set the provider's lease. The lease covers all of the provider's entries.
+    ///
+    /// # Arguments
+    /// * `provider_id` - The provider's id.
+    /// * `lease_ttl_in_seconds` - The lease's time-to-live in seconds.
+    fn set_lease(&self, provider_id: &str, lease_ttl_in_seconds: u32) {
+        let lease = Lease::new(Duration::from_secs(u64::from(lease_ttl_in_seconds)));
+        self.lease_map.write().insert(provider_id.to_string(), lease);
+    }
+
+    /// Evict the entries of the providers whose leases have expired.
+    /// Returns the ids of the providers whose entries were evicted.
+    ///
+    /// # Arguments
+    /// * `now` - The instant to compare the leases' expiry with.
+    pub fn evict_expired_providers(&self, now: Instant) -> Vec<String> {
+        // This block controls the lifetime of the locks.
+        let expired_provider_ids = {
+            let mut entity_lock: RwLockWriteGuard<HashMap<String, Vec<EntityAccessInfo>>> =
+                self.entity_access_info_map.write();
+            let mut lease_lock: RwLockWriteGuard<HashMap<String, Lease>> = self.lease_map.write();
+
+            let expired_provider_ids: Vec<String> = lease_lock
+                .iter()
+                .filter(|(_, lease)| lease.is_expired(now))
+                .map(|(provider_id, _)| provider_id.clone())
+                .collect();
+
+            for provider_id in &expired_provider_ids {
+                lease_lock.remove(provider_id);
+            }
+
+            for entity_access_info_list in entity_lock.values_mut() {
+                entity_access_info_list.retain(|entity_access_info| {
+                    !expired_provider_ids.contains(&entity_access_info.provider_id)
+                });
+            }
+            entity_lock.retain(|_, entity_access_info_list| !entity_access_info_list.is_empty());
+
+            expired_provider_ids
+        };
+
+        for provider_id in &expired_provider_ids {
+            info!("The lease for provider {provider_id} has expired, so its entries were removed.");
+        }
+
+        expired_provider_ids
+    }
+
+    /// Start the background task that periodically evicts the entries of the providers whose
+    /// leases have expired.
+    ///
+    /// # Arguments
+    /// * `eviction_interval` - The interval between checks for expired leases.
+    pub fn start_lease_eviction(&self, eviction_interval: Duration) -> JoinHandle<()> {
+        let digital_twin_registry_impl = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(eviction_interval);
+            loop {
+                interval.tick().await;
+                digital_twin_registry_impl.evict_expired_providers(Instant::now());
+            }
+        })
     }
 }
 
 #[cfg(test)]
 mod digital_twin_registry_impl_tests {
     use super::*;
+    use common::grpc_status_details::get_typed_details;
 
     #[tokio::test]
     async fn find_by_model_id_test() {
@@ -208,8 +452,10 @@ mod digital_twin_registry_impl_tests {
 
         let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
 
-        let digital_twin_registry_impl =
-            DigitalTwinRegistryImpl { entity_access_info_map: entity_access_info_map.clone() };
+        let digital_twin_registry_impl = DigitalTwinRegistryImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
 
         // This block controls the lifetime of the lock.
         {
@@ -253,8 +499,10 @@ mod digital_twin_registry_impl_tests {
 
         let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
 
-        let digital_twin_registry_impl =
-            DigitalTwinRegistryImpl { entity_access_info_map: entity_access_info_map.clone() };
+        let digital_twin_registry_impl = DigitalTwinRegistryImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
 
         // This block controls the lifetime of the lock.
         {
@@ -296,11 +544,14 @@ mod digital_twin_registry_impl_tests {
 
         let entity_access_info_map = Arc::new(RwLock::new(HashMap::new()));
 
-        let digital_twin_registry_impl =
-            DigitalTwinRegistryImpl { entity_access_info_map: entity_access_info_map.clone() };
+        let digital_twin_registry_impl = DigitalTwinRegistryImpl {
+            entity_access_info_map: entity_access_info_map.clone(),
+            ..Default::default()
+        };
 
         let request = tonic::Request::new(RegisterRequest {
             entity_access_info_list: vec![entity_access_info],
+            ..Default::default()
         });
         let result = digital_twin_registry_impl.register(request).await;
         assert!(result.is_ok(), "register result is not okay: {result:?}");
@@ -313,4 +564,142 @@ mod digital_twin_registry_impl_tests {
             assert_eq!(lock.len(), 1, "expected length was 1, actual length is {}", lock.len());
         }
     }
+
+    #[tokio::test]
+    async fn lease_eviction_test() {
+        let leased_entity_access_info = EntityAccessInfo {
+            provider_id: String::from("leased-provider"),
+            instance_id: String::from("1234567890"),
+            model_id: String::from("dtmi:sdv:hvac:ambient_air_temperature;1"),
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            context: String::from(""),
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+        };
+
+        let entity_access_info = EntityAccessInfo {
+            provider_id: String::from("test-provider"),
+            uri: String::from("http://[::1]:40020"), // Devskim: ignore DS137138
+            ..leased_entity_access_info.clone()
+        };
+
+        let digital_twin_registry_impl = DigitalTwinRegistryImpl::default();
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![leased_entity_access_info],
+            lease_ttl_in_seconds: 10,
+            ..Default::default()
+        });
+        let result = digital_twin_registry_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info],
+            ..Default::default()
+        });
+        let result = digital_twin_registry_impl.register(request).await;
+        assert!(result.is_ok(), "register result is not okay: {result:?}");
+
+        let request =
+            tonic::Request::new(RenewLeaseRequest { provider_id: String::from("leased-provider") });
+        let result = digital_twin_registry_impl.renew_lease(request).await;
+        assert!(result.is_ok(), "renew_lease result is not okay: {result:?}");
+
+        let evicted_provider_ids = digital_twin_registry_impl
+            .evict_expired_providers(Instant::now() + Duration::from_secs(11));
+        assert_eq!(evicted_provider_ids, vec!["leased-provider"]);
+
+        // This block controls the lifetime of the lock.
+        {
+            let lock: RwLockReadGuard<HashMap<String, Vec<EntityAccessInfo>>> =
+                digital_twin_registry_impl.entity_access_info_map.read();
+            let remaining_entity_access_info_list =
+                lock.get("dtmi:sdv:hvac:ambient_air_temperature;1").unwrap();
+            assert_eq!(remaining_entity_access_info_list.len(), 1);
+            assert_eq!(remaining_entity_access_info_list[0].provider_id, "test-provider");
+        }
+
+        let request =
+            tonic::Request::new(RenewLeaseRequest { provider_id: String::from("leased-provider") });
+        let result = digital_twin_registry_impl.renew_lease(request).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn register_invalid_model_id_test() {
+        let entity_access_info = EntityAccessInfo {
+            provider_id: String::from("test-provider"),
+            instance_id: String::from("1234567890"),
+            model_id: String::from("dtmi:sdv:hvac:ambient_air_temperature"),
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            context: String::from(""),
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+        };
+
+        let digital_twin_registry_impl = DigitalTwinRegistryImpl::default();
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info],
+            ..Default::default()
+        });
+        let status = digital_twin_registry_impl.register(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "Model id 'dtmi:sdv:hvac:ambient_air_temperature' is not a valid DTMI: it does not end with a version suffix, such as ';1'"
+        );
+        assert!(digital_twin_registry_impl.entity_access_info_map.read().is_empty());
+    }
+
+    #[tokio::test]
+    async fn register_atomic_batch_test() {
+        let entity_access_info = EntityAccessInfo {
+            provider_id: String::from("test-provider"),
+            instance_id: String::from("1234567890"),
+            model_id: String::from("dtmi:sdv:hvac:ambient_air_temperature;1"),
+            protocol: String::from("grpc"),
+            uri: String::from("http://[::1]:40010"), // Devskim: ignore DS137138
+            context: String::from(""),
+            operations: vec![String::from("Subscribe"), String::from("Unsubscribe")],
+        };
+
+        let invalid_entity_access_info =
+            EntityAccessInfo { instance_id: String::from(""), ..entity_access_info.clone() };
+
+        let digital_twin_registry_impl = DigitalTwinRegistryImpl::default();
+
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![
+                entity_access_info.clone(),
+                invalid_entity_access_info.clone(),
+            ],
+            lease_ttl_in_seconds: 10,
+            atomic: true,
+        });
+        let status = digital_twin_registry_impl.register(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.message(), "Instance id is required");
+
+        let response: RegisterResponse =
+            get_typed_details(&status, REGISTER_RESPONSE_TYPE_NAME).unwrap();
+        let codes: Vec<i32> =
+            response.entity_status_list.iter().map(|entity_status| entity_status.code).collect();
+        assert_eq!(codes, [tonic::Code::Aborted as i32, tonic::Code::InvalidArgument as i32]);
+        assert!(digital_twin_registry_impl.entity_access_info_map.read().is_empty());
+        assert!(digital_twin_registry_impl.lease_map.read().is_empty());
+
+        // Without the atomic flag, the valid entity is registered.
+        let request = tonic::Request::new(RegisterRequest {
+            entity_access_info_list: vec![entity_access_info, invalid_entity_access_info],
+            ..Default::default()
+        });
+        let status = digital_twin_registry_impl.register(request).await.unwrap_err();
+        let response: RegisterResponse =
+            get_typed_details(&status, REGISTER_RESPONSE_TYPE_NAME).unwrap();
+        let codes: Vec<i32> =
+            response.entity_status_list.iter().map(|entity_status| entity_status.code).collect();
+        assert_eq!(codes, [tonic::Code::Ok as i32, tonic::Code::InvalidArgument as i32]);
+        assert_eq!(digital_twin_registry_impl.entity_access_info_map.read().len(), 1);
+    }
 }
@@ -2,24 +2,75 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
-use common::grpc_module::GrpcModule;
+use common::dtdl_model_catalog::DtdlModelCatalog;
+use common::grpc_interceptor::AsyncGrpcInterceptor;
+use common::grpc_module::{GrpcModule, GrpcModuleHealth};
+use common::metrics;
 use core_protobuf_data_access::module::digital_twin_registry::v1::digital_twin_registry_server::DigitalTwinRegistryServer;
 
+use log::warn;
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+use std::error::Error;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tonic::server::NamedService;
 use tonic::transport::server::RoutesBuilder;
 
 use crate::digital_twin_registry_impl::DigitalTwinRegistryImpl;
+use crate::provider_identity_interceptor::ProviderIdentityInterceptor;
+
+/// The name that the module is referred to by in the In-Vehicle Digital Twin Service's settings.
+pub const MODULE_NAME: &str = "digital_twin_registry";
 
 /// Digital Twin Registry Module.
 #[derive(Clone, Debug)]
-pub struct DigitalTwinRegistryModule {}
+pub struct DigitalTwinRegistryModule {
+    /// The registry's implementation, which is shared with its gRPC service.
+    digital_twin_registry_impl: DigitalTwinRegistryImpl,
+    /// The interval between checks for expired provider leases.
+    lease_eviction_interval: Duration,
+    /// The background task that evicts the entries of providers whose leases have expired.
+    lease_eviction_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+    /// Must the providers present a client certificate to register entities and renew leases?
+    is_provider_certificate_required: bool,
+}
 
 impl DigitalTwinRegistryModule {
     /// Creates a new instance of the DigitalTwinRegistryModule.
-    pub async fn new() -> Result<Self, tonic::Status> {
-        Ok(Self {})
+    ///
+    /// # Arguments
+    /// * `dtdl_model_catalog` - The DTDL models that registrations are validated against, if any.
+    /// * `lease_eviction_interval` - The interval between checks for expired provider leases. It
+    ///                               must be greater than zero.
+    /// * `is_provider_certificate_required` - Must the providers present a client certificate to
+    ///                                        register entities and renew leases? It should be set
+    ///                                        when the server verifies client certificates.
+    pub async fn new(
+        dtdl_model_catalog: Option<Arc<DtdlModelCatalog>>,
+        lease_eviction_interval: Duration,
+        is_provider_certificate_required: bool,
+    ) -> Result<Self, tonic::Status> {
+        if lease_eviction_interval.is_zero() {
+            return Err(tonic::Status::invalid_argument(
+                "The lease eviction interval must be greater than zero",
+            ));
+        }
+
+        let digital_twin_registry_impl =
+            DigitalTwinRegistryImpl { dtdl_model_catalog, ..Default::default() };
+
+        Ok(Self {
+            digital_twin_registry_impl,
+            lease_eviction_interval,
+            lease_eviction_task: Arc::new(Mutex::new(None)),
+            is_provider_certificate_required,
+        })
     }
 }
 
+#[tonic::async_trait]
 impl GrpcModule for DigitalTwinRegistryModule {
     /// Adds the gRPC services for this module to the server builder.
     ///
@@ -28,8 +79,85 @@ impl GrpcModule for DigitalTwinRegistryModule {
     fn add_grpc_services(&self, builder: &mut RoutesBuilder) {
         // Create the gRPC services.
         let digital_twin_registry_service =
-            DigitalTwinRegistryServer::new(DigitalTwinRegistryImpl::default());
+            DigitalTwinRegistryServer::new(self.digital_twin_registry_impl.clone());
 
         builder.add_service(digital_twin_registry_service);
     }
+
+    /// The full names of the gRPC services for this module.
+    fn grpc_service_names(&self) -> Vec<String> {
+        vec![<DigitalTwinRegistryServer<DigitalTwinRegistryImpl> as NamedService>::NAME.to_string()]
+    }
+
+    /// The module's interceptors. The provider identity interceptor checks that providers that
+    /// present a client certificate only register and renew leases for their own provider id, and
+    /// that the providers present one when it is required.
+    fn interceptors(&self) -> Vec<Box<dyn AsyncGrpcInterceptor>> {
+        vec![Box::new(ProviderIdentityInterceptor::new(self.is_provider_certificate_required))]
+    }
+
+    /// Starts the task that evicts the entries of providers whose leases have expired, and reports
+    /// the number of registered entities in the metrics.
+    async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let entity_access_info_map = self.digital_twin_registry_impl.entity_access_info_map.clone();
+        if let Err(error) = metrics::register_int_gauge_fn(
+            prometheus::default_registry(),
+            "digital_twin_registry_entities",
+            "The number of entities that are registered with the Digital Twin Registry.",
+            move || entity_access_info_map.read().values().map(Vec::len).sum::<usize>() as i64,
+        ) {
+            warn!("Unable to register the digital_twin_registry_entities metric: {error}");
+        }
+
+        let lease_eviction_task =
+            self.digital_twin_registry_impl.start_lease_eviction(self.lease_eviction_interval);
+        *self.lease_eviction_task.lock() = Some(lease_eviction_task);
+
+        Ok(())
+    }
+
+    /// Stops the task that evicts the entries of providers whose leases have expired.
+    async fn shutdown(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(lease_eviction_task) = self.lease_eviction_task.lock().take() {
+            lease_eviction_task.abort();
+        }
+
+        Ok(())
+    }
+
+    /// The module is healthy while the task that evicts the entries of providers whose leases have
+    /// expired is running.
+    async fn health(&self) -> GrpcModuleHealth {
+        match self.lease_eviction_task.lock().as_ref() {
+            Some(lease_eviction_task) if !lease_eviction_task.is_finished() => {
+                GrpcModuleHealth::Healthy
+            }
+            _ => {
+                GrpcModuleHealth::Unhealthy(String::from("The lease eviction task is not running"))
+            }
+        }
+    }
+
+    /// The state is the registered entries, keyed by model id, and the remaining time-to-live of
+    /// each provider's lease, keyed by provider id.
+    async fn state(&self) -> Option<Value> {
+        let now = Instant::now();
+        let leases: Map<String, Value> = self
+            .digital_twin_registry_impl
+            .lease_map
+            .read()
+            .iter()
+            .map(|(provider_id, lease)| {
+                (
+                    provider_id.clone(),
+                    json!({ "remaining_ttl_in_seconds": lease.remaining_ttl(now).as_secs() }),
+                )
+            })
+            .collect();
+
+        Some(json!({
+            "entities": *self.digital_twin_registry_impl.entity_access_info_map.read(),
+            "leases": leases,
+        }))
+    }
 }
@@ -4,3 +4,4 @@
 
 pub mod digital_twin_registry_impl;
 pub mod digital_twin_registry_module;
+pub mod provider_identity_interceptor;
@@ -0,0 +1,308 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT license.
+// SPDX-License-Identifier: MIT
+
+use bytes::Bytes;
+use common::grpc_interceptor::{AsyncGrpcInterceptor, GrpcCallContext};
+use core_protobuf_data_access::module::digital_twin_registry::v1::{
+    RegisterRequest, RenewLeaseRequest,
+};
+use log::debug;
+use prost::Message;
+use std::error::Error;
+use tonic::Status;
+use x509_parser::parse_x509_certificate;
+
+/// Interceptor that binds a provider's identity to its provider id. When a provider presents a
+/// client certificate, it may only register entities and renew leases for the provider id that
+/// matches the certificate's subject common name. When client certificates are verified, calls
+/// without a client certificate, which are possible when client certificates are optional, are
+/// rejected, so that they cannot take over the provider id of a provider that has a certificate.
+#[derive(Clone, Default)]
+pub struct ProviderIdentityInterceptor {
+    /// Must the providers present a client certificate to register entities and renew leases?
+    is_certificate_required: bool,
+}
+
+impl ProviderIdentityInterceptor {
+    const DIGITAL_TWIN_REGISTRY_SERVICE_NAME: &str = "DigitalTwinRegistry";
+    const REGISTER_METHOD_NAME: &str = "Register";
+    const RENEW_LEASE_METHOD_NAME: &str = "RenewLease";
+
+    /// Creates a new instance of the ProviderIdentityInterceptor.
+    ///
+    /// # Arguments
+    /// * `is_certificate_required` - Must the providers present a client certificate to register
+    ///                               entities and renew leases? It should be set when the server
+    ///                               verifies client certificates.
+    pub fn new(is_certificate_required: bool) -> Self {
+        Self { is_certificate_required }
+    }
+
+    /// Get the provider identity from the client's certificate. Returns None when the client did
+    /// not present a certificate.
+    ///
+    /// # Arguments
+    /// * `context` - The gRPC call's context.
+    fn get_provider_identity(context: &GrpcCallContext) -> Result<Option<String>, Status> {
+        let Some(leaf_certificate) =
+            context.peer_certificates.as_ref().and_then(|certificates| certificates.first())
+        else {
+            return Ok(None);
+        };
+
+        let (_, certificate) =
+            parse_x509_certificate(leaf_certificate.get_ref()).map_err(|error| {
+                Status::permission_denied(format!(
+                    "Unable to parse the client certificate: {error}"
+                ))
+            })?;
+
+        let common_name = certificate
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|common_name| common_name.as_str().ok())
+            .ok_or_else(|| {
+                Status::permission_denied("The client certificate does not have a common name")
+            })?;
+
+        Ok(Some(common_name.to_string()))
+    }
+
+    /// Get the provider ids that a request refers to.
+    ///
+    /// # Arguments
+    /// * `method_name` - The gRPC call's method name.
+    /// * `protobuf_message` - The request's protobuf message.
+    fn get_provider_ids(method_name: &str, protobuf_message: &[u8]) -> Result<Vec<String>, Status> {
+        let provider_ids = match method_name {
+            Self::REGISTER_METHOD_NAME => RegisterRequest::decode(protobuf_message)
+                .map_err(|error| Status::invalid_argument(error.to_string()))?
+                .entity_access_info_list
+                .into_iter()
+                .map(|entity_access_info| entity_access_info.provider_id)
+                .collect(),
+            Self::RENEW_LEASE_METHOD_NAME => vec![
+                RenewLeaseRequest::decode(protobuf_message)
+                    .map_err(|error| Status::invalid_argument(error.to_string()))?
+                    .provider_id,
+            ],
+            _ => Vec::new(),
+        };
+
+        Ok(provider_ids)
+    }
+
+    /// Check that a request only refers to the provider's own provider id. A request without a
+    /// provider identity may not refer to any provider id when a certificate is required.
+    ///
+    /// # Arguments
+    /// * `provider_identity` - The provider's identity, if the client presented a certificate.
+    /// * `method_name` - The gRPC call's method name.
+    /// * `protobuf_message` - The request's protobuf message.
+    fn check_provider_ids(
+        &self,
+        provider_identity: Option<&str>,
+        method_name: &str,
+        protobuf_message: &[u8],
+    ) -> Result<(), Status> {
+        let provider_ids = Self::get_provider_ids(method_name, protobuf_message)?;
+
+        let Some(provider_identity) = provider_identity else {
+            if let Some(provider_id) = provider_ids.first().filter(|_| self.is_certificate_required)
+            {
+                debug!("A client without a certificate is not allowed to call {method_name} for the provider id '{provider_id}'");
+                return Err(Status::permission_denied(format!(
+                    "A client certificate is required to call {method_name} for the provider id '{provider_id}'"
+                )));
+            }
+            return Ok(());
+        };
+
+        if let Some(provider_id) =
+            provider_ids.iter().find(|provider_id| provider_id.as_str() != provider_identity)
+        {
+            debug!("'{provider_identity}' is not allowed to call {method_name} for the provider id '{provider_id}'");
+            return Err(Status::permission_denied(format!(
+                "'{provider_identity}' is not allowed to call {method_name} for the provider id '{provider_id}'"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl AsyncGrpcInterceptor for ProviderIdentityInterceptor {
+    fn is_applicable(&self, service_name: &str, method_name: &str) -> bool {
+        service_name == Self::DIGITAL_TWIN_REGISTRY_SERVICE_NAME
+            && (method_name == Self::REGISTER_METHOD_NAME
+                || method_name == Self::RENEW_LEASE_METHOD_NAME)
+    }
+
+    fn must_handle_request(&self) -> bool {
+        true
+    }
+
+    fn must_handle_response(&self) -> bool {
+        false
+    }
+
+    async fn handle_request(
+        &self,
+        _service_name: &str,
+        _method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        Ok(protobuf_message)
+    }
+
+    async fn handle_response(
+        &self,
+        _service_name: &str,
+        _method_name: &str,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        Ok(protobuf_message)
+    }
+
+    async fn handle_request_message(
+        &self,
+        context: &GrpcCallContext,
+        protobuf_message: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let provider_identity = Self::get_provider_identity(context)?;
+        self.check_provider_ids(
+            provider_identity.as_deref(),
+            &context.method_name,
+            &protobuf_message,
+        )?;
+
+        Ok(protobuf_message)
+    }
+}
+
+#[cfg(test)]
+mod provider_identity_interceptor_tests {
+    use super::*;
+    use core_protobuf_data_access::module::digital_twin_registry::v1::EntityAccessInfo;
+    use std::sync::Arc;
+    use tonic::transport::Certificate;
+
+    /// Create a register request's protobuf message.
+    ///
+    /// # Arguments
+    /// * `provider_ids` - The provider ids of the request's entities.
+    fn register_request(provider_ids: &[&str]) -> Bytes {
+        Bytes::from(
+            RegisterRequest {
+                entity_access_info_list: provider_ids
+                    .iter()
+                    .map(|provider_id| EntityAccessInfo {
+                        provider_id: provider_id.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        )
+    }
+
+    #[test]
+    fn is_applicable_test() {
+        let interceptor = ProviderIdentityInterceptor::new(true);
+        assert!(interceptor.is_applicable("DigitalTwinRegistry", "Register"));
+        assert!(interceptor.is_applicable("DigitalTwinRegistry", "RenewLease"));
+        assert!(!interceptor.is_applicable("DigitalTwinRegistry", "FindByModelId"));
+        assert!(!interceptor.is_applicable("InvehicleDigitalTwin", "Register"));
+    }
+
+    #[test]
+    fn check_provider_ids_test() {
+        let interceptor = ProviderIdentityInterceptor::new(true);
+
+        let result = interceptor.check_provider_ids(
+            Some("hvac"),
+            "Register",
+            &register_request(&["hvac", "hvac"]),
+        );
+        assert!(result.is_ok());
+
+        let status = interceptor
+            .check_provider_ids(Some("hvac"), "Register", &register_request(&["hvac", "obd"]))
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let renew_lease_request =
+            RenewLeaseRequest { provider_id: String::from("obd") }.encode_to_vec();
+        let status = interceptor
+            .check_provider_ids(Some("hvac"), "RenewLease", &renew_lease_request)
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn check_provider_ids_without_certificate_test() {
+        let renew_lease_request =
+            RenewLeaseRequest { provider_id: String::from("hvac") }.encode_to_vec();
+
+        // When a certificate is not required, a client without one can use any provider id.
+        let interceptor = ProviderIdentityInterceptor::new(false);
+        let result = interceptor.check_provider_ids(None, "Register", &register_request(&["hvac"]));
+        assert!(result.is_ok());
+        let result = interceptor.check_provider_ids(None, "RenewLease", &renew_lease_request);
+        assert!(result.is_ok());
+
+        // When a certificate is required, a client without one can neither register nor renew the
+        // lease for any provider id, including one that has not been used with a certificate yet.
+        let interceptor = ProviderIdentityInterceptor::new(true);
+        let status = interceptor
+            .check_provider_ids(None, "Register", &register_request(&["obd", "hvac"]))
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let status =
+            interceptor.check_provider_ids(None, "RenewLease", &renew_lease_request).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        // A register request without entities does not refer to a provider id.
+        let result = interceptor.check_provider_ids(None, "Register", &register_request(&[]));
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn handle_request_message_test() {
+        let interceptor = ProviderIdentityInterceptor::new(true);
+
+        // Calls without a client certificate are rejected when a certificate is required, and
+        // are allowed when it is not.
+        let context = GrpcCallContext {
+            service_name: String::from("DigitalTwinRegistry"),
+            method_name: String::from("Register"),
+            ..Default::default()
+        };
+        let error = interceptor
+            .handle_request_message(&context, register_request(&["obd"]))
+            .await
+            .unwrap_err();
+        assert_eq!(Status::from_error(error).code(), tonic::Code::PermissionDenied);
+
+        let result = ProviderIdentityInterceptor::new(false)
+            .handle_request_message(&context, register_request(&["obd"]))
+            .await;
+        assert!(result.is_ok());
+
+        // A client certificate that cannot be parsed is rejected.
+        let context = GrpcCallContext {
+            peer_certificates: Some(Arc::new(vec![Certificate::from_pem(vec![0, 1, 2])])),
+            ..context
+        };
+        let error = interceptor
+            .handle_request_message(&context, register_request(&["obd"]))
+            .await
+            .unwrap_err();
+        assert_eq!(Status::from_error(error).code(), tonic::Code::PermissionDenied);
+    }
+}
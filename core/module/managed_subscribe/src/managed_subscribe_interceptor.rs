@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: MIT
 
 use bytes::Bytes;
-use core_protobuf_data_access::invehicle_digital_twin::{self, v1::RegisterRequest};
+use core_protobuf_data_access::invehicle_digital_twin::v1::{RegisterRequest, UnregisterRequest};
 use log::{info, warn};
 use parking_lot::RwLock;
 use prost::Message;
@@ -23,48 +23,23 @@ pub struct ManagedSubscribeInterceptor {
 impl ManagedSubscribeInterceptor {
     const INVEHICLE_DIGITAL_TWIN_SERVICE_NAME: &str = "InvehicleDigitalTwin";
     const REGISTER_METHOD_NAME: &str = "Register";
-    const MANAGED_SUBSCRIBE_OPERATION: &str = "ManagedSubscribe";
+    const UNREGISTER_METHOD_NAME: &str = "Unregister";
+    pub(crate) const MANAGED_SUBSCRIBE_OPERATION: &str = "ManagedSubscribe";
 
     pub fn new(service_uri: &str, store: Arc<RwLock<ManagedSubscribeStore>>) -> Self {
         ManagedSubscribeInterceptor { service_uri: service_uri.to_string(), store }
     }
-}
 
-impl GrpcInterceptor for ManagedSubscribeInterceptor {
-    /// Is this interceptor applicable?
+    /// Handle a register request. Injects the managed subscribe endpoint for any entity that
+    /// offers the managed subscribe operation and records the provider's callback in the store.
     ///
     /// # Arguments
-    /// * `service_name` - The gRPC call's service name.
-    /// * `method_name` - The gRPC call's method name.
-    fn is_applicable(&self, service_name: &str, method_name: &str) -> bool {
-        service_name == Self::INVEHICLE_DIGITAL_TWIN_SERVICE_NAME
-            && method_name == Self::REGISTER_METHOD_NAME
-    }
-
-    /// Indicates that the request must be handled.
-    fn must_handle_request(&self) -> bool {
-        true
-    }
-
-    /// Indicates that the response must be handled.
-    fn must_handle_response(&self) -> bool {
-        false
-    }
-
-    /// Handle request. Return the new request.
-    ///
-    /// # Arguments
-    /// * `service_name` - The gRPC call's service name.
-    /// * `method_name` - The gRPC call's method name.
-    /// * `protobuf_message_bytes` - The request's protobuf messages as bytes.
-    fn handle_request(
+    /// * `protobuf_message_bytes` - The register request's protobuf message as bytes.
+    fn handle_register_request(
         &self,
-        _service_name: &str,
-        _method_name: &str,
         protobuf_message_bytes: Bytes,
     ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
-        let register_request: invehicle_digital_twin::v1::RegisterRequest =
-            Message::decode(&protobuf_message_bytes[..]).unwrap();
+        let register_request: RegisterRequest = Message::decode(&protobuf_message_bytes[..])?;
 
         info!("register_request = {:?}", register_request);
 
@@ -110,14 +85,100 @@ impl GrpcInterceptor for ManagedSubscribeInterceptor {
         }
 
         // Construct modified register request.
-        let updated_register_request = RegisterRequest { entity_access_info_list: entities };
+        let updated_register_request =
+            RegisterRequest { entity_access_info_list: entities, ..register_request };
 
         let mut new_protobuf_message_buf: Vec<u8> = Vec::new();
         new_protobuf_message_buf.reserve(updated_register_request.encoded_len());
-        updated_register_request.encode(&mut new_protobuf_message_buf).unwrap();
+        updated_register_request.encode(&mut new_protobuf_message_buf)?;
         Ok(Bytes::from(new_protobuf_message_buf))
     }
 
+    /// Handle an unregister request. When the request targets the provider's managed subscribe
+    /// callback endpoint, it is redirected to the endpoint that was injected for the entity at
+    /// registration time. The store is not changed here, because the unregistration can still
+    /// fail. The module removes the entity from the store once the In-Vehicle Digital Twin
+    /// Service reports that its managed subscribe endpoint is gone.
+    ///
+    /// # Arguments
+    /// * `protobuf_message_bytes` - The unregister request's protobuf message as bytes.
+    fn handle_unregister_request(
+        &self,
+        protobuf_message_bytes: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let unregister_request: UnregisterRequest = Message::decode(&protobuf_message_bytes[..])?;
+
+        info!("unregister_request = {:?}", unregister_request);
+
+        let mut entities = unregister_request.unregister_entity_info_list;
+
+        for entity in &mut entities {
+            let callback_uri = match self.store.read().get_entity_metadata(&entity.id) {
+                Some(entity_metadata) => entity_metadata.callback.uri.clone(),
+                None => continue,
+            };
+
+            if !entity.uri.is_empty() && entity.uri == callback_uri {
+                // The registered endpoint was replaced with the managed subscribe endpoint.
+                entity.uri = self.service_uri.clone();
+                if !entity.protocol.is_empty() {
+                    entity.protocol = "grpc".to_string();
+                }
+            }
+        }
+
+        // Construct modified unregister request.
+        let updated_unregister_request =
+            UnregisterRequest { unregister_entity_info_list: entities };
+
+        let mut new_protobuf_message_buf: Vec<u8> = Vec::new();
+        new_protobuf_message_buf.reserve(updated_unregister_request.encoded_len());
+        updated_unregister_request.encode(&mut new_protobuf_message_buf)?;
+        Ok(Bytes::from(new_protobuf_message_buf))
+    }
+}
+
+impl GrpcInterceptor for ManagedSubscribeInterceptor {
+    /// Is this interceptor applicable?
+    ///
+    /// # Arguments
+    /// * `service_name` - The gRPC call's service name.
+    /// * `method_name` - The gRPC call's method name.
+    fn is_applicable(&self, service_name: &str, method_name: &str) -> bool {
+        service_name == Self::INVEHICLE_DIGITAL_TWIN_SERVICE_NAME
+            && (method_name == Self::REGISTER_METHOD_NAME
+                || method_name == Self::UNREGISTER_METHOD_NAME)
+    }
+
+    /// Indicates that the request must be handled.
+    fn must_handle_request(&self) -> bool {
+        true
+    }
+
+    /// Indicates that the response must be handled.
+    fn must_handle_response(&self) -> bool {
+        false
+    }
+
+    /// Handle request. Return the new request.
+    ///
+    /// # Arguments
+    /// * `service_name` - The gRPC call's service name.
+    /// * `method_name` - The gRPC call's method name.
+    /// * `protobuf_message_bytes` - The request's protobuf messages as bytes.
+    fn handle_request(
+        &self,
+        _service_name: &str,
+        method_name: &str,
+        protobuf_message_bytes: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        if method_name == Self::UNREGISTER_METHOD_NAME {
+            self.handle_unregister_request(protobuf_message_bytes)
+        } else {
+            self.handle_register_request(protobuf_message_bytes)
+        }
+    }
+
     /// Handle response. Return the new response.
     ///
     /// # Arguments
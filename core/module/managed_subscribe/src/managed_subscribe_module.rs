@@ -19,14 +19,28 @@ use core_protobuf_data_access::module::managed_subscribe::v1::{
     TopicManagementRequest,
 };
 
-use common::grpc_module::GrpcModule;
-use common::utils::{execute_with_retry, get_service_uri, load_settings, ServiceUriSource};
-use log::{debug, error, info};
-use parking_lot::RwLock;
+use common::entity_events::{EntityEvent, EntityEventKind};
+use common::grpc_channel;
+use common::grpc_interceptor::{AsyncGrpcInterceptor, SyncGrpcInterceptorAdapter};
+use common::grpc_module::{GrpcModule, GrpcModuleHealth};
+use common::metrics;
+use common::utils::{
+    execute_with_retry, get_base_uri, get_service_uri, load_settings, ServiceUriSource,
+};
+use futures::future::join_all;
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use parking_lot::{Mutex, RwLock};
+use prometheus::{register_int_counter, IntCounter};
 use serde_derive::Deserialize;
+use serde_json::Value;
+use std::error::Error;
 use std::str::FromStr;
 use std::sync::Arc;
 use strum_macros::{Display, EnumString};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tonic::server::NamedService;
 use tonic::transport::server::RoutesBuilder;
 use tonic::{Request, Response, Status};
 
@@ -34,6 +48,9 @@ use crate::managed_subscribe_store::{CallbackInfo, ManagedSubscribeStore, TopicI
 
 use super::managed_subscribe_interceptor::ManagedSubscribeInterceptor;
 
+/// The name that the module is referred to by in the In-Vehicle Digital Twin Service's settings.
+pub const MODULE_NAME: &str = "managed_subscribe";
+
 const CONFIG_FILENAME: &str = "managed_subscribe_settings";
 const SERVICE_PROTOCOL: &str = "grpc";
 
@@ -44,6 +61,19 @@ const MANAGED_SUBSCRIBE_COMMUNICATION_REFERENCE: &str = "pubsub.v1.pubsub.proto"
 const PUBLISH_ACTION: &str = "PUBLISH";
 const STOP_PUBLISH_ACTION: &str = "STOP_PUBLISH";
 
+/// How long the module waits for the Managed Subscribe service to delete a topic, so that an
+/// unreachable service does not hold up the shutdown. The topics are deleted concurrently, and the
+/// wait is also bounded by the service's shutdown deadline when the module shuts down.
+const DELETE_TOPIC_TIMEOUT_IN_SECONDS: u64 = 5;
+
+lazy_static! {
+    static ref CALLBACK_FAILURES_TOTAL: IntCounter = register_int_counter!(
+        "managed_subscribe_callback_failures_total",
+        "The number of calls to the providers' topic management callbacks that failed."
+    )
+    .expect("Unable to register the managed_subscribe_callback_failures_total metric");
+}
+
 /// Actions that are returned from the Pub Sub Service.
 #[derive(Clone, EnumString, Eq, Display, Debug, PartialEq)]
 pub enum TopicAction {
@@ -64,8 +94,12 @@ pub enum TopicAction {
 /// Settings retrieved from a configuration file.
 #[derive(Debug, Deserialize)]
 pub struct ConfigSettings {
-    /// Where to host the Managed Subscribe module.
-    pub base_authority: String,
+    /// Where to host the Managed Subscribe module. It is reached over http. It is only used when
+    /// the base_uri is not provided.
+    pub base_authority: Option<String>,
+    /// The full URI of the server that hosts the Managed Subscribe module, e.g.
+    /// "https://localhost:5010" or "unix:///run/ibeji/invehicle_digital_twin.sock".
+    pub base_uri: Option<String>,
     /// Where to retrieve the Managed Subscribe Service URI from.
     pub managed_subscribe_uri_source: ServiceUriSource,
 }
@@ -81,19 +115,39 @@ pub struct ManagedSubscribeModule {
     pub service_protocol: String,
     /// Shared store for the Managed Subscribe module.
     pub store: Arc<RwLock<ManagedSubscribeStore>>,
+    /// The sender for the In-Vehicle Digital Twin Service's entity events. When it is provided,
+    /// the module removes entities from its store once they are removed from the service, or no
+    /// longer have a managed subscribe endpoint.
+    pub entity_event_sender: Option<broadcast::Sender<EntityEvent>>,
+    /// The task that handles the entity events, once the module has been started.
+    entity_event_handler: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 impl ManagedSubscribeModule {
     /// Creates a new managed subscribe module object.
     pub async fn new() -> Result<Self, Status> {
+        Self::new_with_config_filename(CONFIG_FILENAME).await
+    }
+
+    /// Creates a new managed subscribe module object that loads its settings from the specified
+    /// config file.
+    ///
+    /// # Arguments
+    /// * `config_filename` - The name of the config file.
+    pub async fn new_with_config_filename(config_filename: &str) -> Result<Self, Status> {
         // Get module information from the configuration settings.
-        let config = load_settings::<ConfigSettings>(CONFIG_FILENAME).map_err(|error| {
+        let config = load_settings::<ConfigSettings>(config_filename).map_err(|error| {
             Status::internal(format!(
                 "Unable to load 'Managed Subscribe' config with error: {error}."
             ))
         })?;
-        let endpoint = config.base_authority;
-        let service_uri = format!("http://{endpoint}"); // Devskim: ignore DS137138
+        let service_uri = get_base_uri(
+            config.base_uri.as_deref(),
+            config.base_authority.as_deref(),
+        )
+        .map_err(|error| {
+            Status::invalid_argument(format!("Invalid 'Managed Subscribe' config: {error}."))
+        })?;
 
         let store = Arc::new(RwLock::new(ManagedSubscribeStore::new()));
 
@@ -112,6 +166,8 @@ impl ManagedSubscribeModule {
             service_uri,
             service_protocol: SERVICE_PROTOCOL.to_string(),
             store,
+            entity_event_sender: None,
+            entity_event_handler: Arc::new(Mutex::new(None)),
         })
     }
 
@@ -121,6 +177,65 @@ impl ManagedSubscribeModule {
         ManagedSubscribeInterceptor::new(&self.service_uri, self.store.clone())
     }
 
+    /// Starts a task that removes entities from the store once they have been removed from the
+    /// In-Vehicle Digital Twin Service, for example because they were unregistered or their lease
+    /// expired, or once their managed subscribe endpoint has been unregistered, and deletes their
+    /// topics from the Managed Subscribe service. The events are only published for changes that
+    /// succeeded, so a failed unregistration keeps the store intact.
+    ///
+    /// # Arguments
+    /// * `entity_event_receiver` - The receiver for the core service's entity events.
+    pub fn start_entity_event_handler(
+        &self,
+        mut entity_event_receiver: broadcast::Receiver<EntityEvent>,
+    ) -> JoinHandle<()> {
+        let module = self.clone();
+        let store = self.store.clone();
+        let service_uri = self.service_uri.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match entity_event_receiver.recv().await {
+                    Ok(entity_event) => {
+                        let entity_access_info = &entity_event.entity_access_info;
+                        let has_managed_subscribe_endpoint = entity_event.kind
+                            != EntityEventKind::Removed
+                            && entity_access_info.endpoint_info_list.iter().any(|endpoint_info| {
+                                endpoint_info.uri == service_uri
+                                    && endpoint_info.operations.iter().any(|operation| {
+                                        operation
+                                            == ManagedSubscribeInterceptor::MANAGED_SUBSCRIBE_OPERATION
+                                    })
+                            });
+                        if has_managed_subscribe_endpoint {
+                            continue;
+                        }
+
+                        let entity_id = &entity_access_info.id;
+                        let Some(metadata) = store.write().remove_entity(entity_id) else {
+                            continue;
+                        };
+                        info!("removed entity metadata for entity with id: {entity_id}");
+
+                        // Delete the entity's topics in the background, so that an unreachable
+                        // Managed Subscribe service does not hold up the handling of the events.
+                        let topics: Vec<String> = metadata.topics.into_keys().collect();
+                        if !topics.is_empty() {
+                            let module = module.clone();
+                            tokio::spawn(async move {
+                                module.delete_managed_topics(&topics).await;
+                            });
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("The entity event handler missed {count} entity events.");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
     /// Calls the external managed subscription service to create a new topic.
     ///
     /// # Arguments
@@ -130,8 +245,10 @@ impl ManagedSubscribeModule {
         entity_id: &str,
     ) -> Result<Response<CreateTopicResponse>, Status> {
         // Connect to managed subscribe service.
-        let mut ms_client =
-            PubSubClient::connect(self.managed_subscribe_uri.to_string()).await.map_err(|e| {
+        let mut ms_client = grpc_channel::connect(&self.managed_subscribe_uri)
+            .await
+            .map(PubSubClient::new)
+            .map_err(|e| {
                 error!("Error connecting to pub sub client: {e:?}");
                 Status::from_error(Box::new(e))
             })?;
@@ -156,8 +273,10 @@ impl ManagedSubscribeModule {
         topic: &str,
     ) -> Result<Response<DeleteTopicResponse>, Status> {
         // Connect to managed subscribe service.
-        let mut ms_client =
-            PubSubClient::connect(self.managed_subscribe_uri.to_string()).await.map_err(|e| {
+        let mut ms_client = grpc_channel::connect(&self.managed_subscribe_uri)
+            .await
+            .map(PubSubClient::new)
+            .map_err(|e| {
                 error!("Error connecting to pub sub client: {e:?}");
                 Status::from_error(Box::new(e))
             })?;
@@ -168,8 +287,38 @@ impl ManagedSubscribeModule {
         // Call managed subscribe service.
         ms_client.delete_topic(request).await
     }
+
+    /// Calls the external managed subscription service to delete managed topics concurrently,
+    /// each within a timeout. Returns the topics that were deleted.
+    ///
+    /// # Arguments
+    /// * `topics` - The topics to delete.
+    async fn delete_managed_topics(&self, topics: &[String]) -> Vec<String> {
+        let delete_results = join_all(topics.iter().map(|topic| {
+            tokio::time::timeout(
+                tokio::time::Duration::from_secs(DELETE_TOPIC_TIMEOUT_IN_SECONDS),
+                self.delete_managed_topic(topic),
+            )
+        }))
+        .await;
+
+        let mut deleted_topics = Vec::new();
+        for (topic, delete_result) in topics.iter().zip(delete_results) {
+            match delete_result {
+                Ok(Ok(_)) => {
+                    info!("Deleted the managed topic {topic}.");
+                    deleted_topics.push(topic.clone());
+                }
+                Ok(Err(status)) => warn!("Unable to delete the managed topic {topic}: {status}"),
+                Err(_) => warn!("Timed out deleting the managed topic {topic}."),
+            }
+        }
+
+        deleted_topics
+    }
 }
 
+#[tonic::async_trait]
 impl GrpcModule for ManagedSubscribeModule {
     /// Adds the gRPC services for this module to the server builder.
     ///
@@ -184,6 +333,92 @@ impl GrpcModule for ManagedSubscribeModule {
             .add_service(managed_subscribe_service)
             .add_service(managed_subscribe_callback_service);
     }
+
+    /// The full names of the gRPC services for this module.
+    fn grpc_service_names(&self) -> Vec<String> {
+        vec![
+            <ManagedSubscribeServer<ManagedSubscribeModule> as NamedService>::NAME.to_string(),
+            <PublisherCallbackServer<ManagedSubscribeModule> as NamedService>::NAME.to_string(),
+        ]
+    }
+
+    /// The module's interceptors.
+    fn interceptors(&self) -> Vec<Box<dyn AsyncGrpcInterceptor>> {
+        vec![Box::new(SyncGrpcInterceptorAdapter::new(Box::new(self.create_interceptor())))]
+    }
+
+    /// Starts the task that keeps the store in sync with the entities that are removed from the
+    /// In-Vehicle Digital Twin Service, if the module has the service's entity event sender. The
+    /// number of managed topics is reported in the metrics.
+    async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let store = self.store.clone();
+        if let Err(error) = metrics::register_int_gauge_fn(
+            prometheus::default_registry(),
+            "managed_subscribe_topics",
+            "The number of topics that the Managed Subscribe module manages.",
+            move || store.read().topic_count() as i64,
+        ) {
+            warn!("Unable to register the managed_subscribe_topics metric: {error}");
+        }
+
+        if let Some(entity_event_sender) = &self.entity_event_sender {
+            let entity_event_handler =
+                self.start_entity_event_handler(entity_event_sender.subscribe());
+            *self.entity_event_handler.lock() = Some(entity_event_handler);
+        }
+
+        Ok(())
+    }
+
+    /// Stops the task that handles the entity events, and deletes the module's topics from the
+    /// Managed Subscribe service, so that they do not outlive the module.
+    async fn shutdown(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(entity_event_handler) = self.entity_event_handler.lock().take() {
+            entity_event_handler.abort();
+        }
+
+        let topics = self.store.read().topics();
+        let deleted_topics = self.delete_managed_topics(&topics).await;
+        for topic in &deleted_topics {
+            self.store.write().remove_topic(topic);
+        }
+
+        let failed_topic_count = topics.len() - deleted_topics.len();
+        if failed_topic_count > 0 {
+            return Err(format!("Unable to delete {failed_topic_count} managed topics").into());
+        }
+
+        Ok(())
+    }
+
+    /// The module is unhealthy when it has the service's entity event sender, but the task that
+    /// handles the entity events is not running.
+    async fn health(&self) -> GrpcModuleHealth {
+        if self.entity_event_sender.is_none() {
+            return GrpcModuleHealth::Healthy;
+        }
+
+        match self.entity_event_handler.lock().as_ref() {
+            Some(entity_event_handler) if !entity_event_handler.is_finished() => {
+                GrpcModuleHealth::Healthy
+            }
+            _ => GrpcModuleHealth::Unhealthy(String::from(
+                "The entity event handler task is not running",
+            )),
+        }
+    }
+
+    /// The state is the contents of the module's store: the managed entities, with their callbacks
+    /// and topics, and the entity of each topic.
+    async fn state(&self) -> Option<Value> {
+        match serde_json::to_value(&*self.store.read()) {
+            Ok(state) => Some(state),
+            Err(error) => {
+                warn!("Unable to serialize the Managed Subscribe store: {error}");
+                None
+            }
+        }
+    }
 }
 
 /// Calls a provider's callback endpoint with a management request.
@@ -195,16 +430,18 @@ async fn call_provider_management_cb(
     provider_cb_uri: &str,
     management_request: TopicManagementRequest,
 ) -> Result<(), Status> {
-    let mut provider_cb_client =
-        ManagedSubscribeCallbackClient::connect(provider_cb_uri.to_string()).await.map_err(
-            |e| {
-                error!("Error connecting to provider cb client: {e:?}");
-                Status::from_error(Box::new(e))
-            },
-        )?;
+    let mut provider_cb_client = grpc_channel::connect(provider_cb_uri)
+        .await
+        .map(ManagedSubscribeCallbackClient::new)
+        .map_err(|e| {
+            error!("Error connecting to provider cb client: {e:?}");
+            CALLBACK_FAILURES_TOTAL.inc();
+            Status::from_error(Box::new(e))
+        })?;
 
     let _res = provider_cb_client.topic_management_cb(management_request).await.map_err(|e| {
         error!("Error calling to provider cb client: {e:?}");
+        CALLBACK_FAILURES_TOTAL.inc();
         Status::from_error(Box::new(e))
     })?;
 
@@ -6,27 +6,28 @@ use std::collections::HashMap;
 
 use core_protobuf_data_access::module::managed_subscribe::v1::Constraint;
 use log::warn;
+use serde_derive::Serialize;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct CallbackInfo {
     pub uri: String,
     pub protocol: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct TopicInfo {
     pub uri: String,
     pub protocol: String,
     pub constraints: Vec<Constraint>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct EntityMetadata {
     pub callback: CallbackInfo,
     pub topics: HashMap<String, TopicInfo>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct ManagedSubscribeStore {
     topic_entity_map: HashMap<String, String>,
     entity_metadata_map: HashMap<String, EntityMetadata>,
@@ -56,6 +57,22 @@ impl ManagedSubscribeStore {
         self.entity_metadata_map.insert(entity_id.to_string(), metadata);
     }
 
+    /// Removes an entity id, its associated metadata and its topics from the store.
+    /// Returns the removed metadata, if the entity was in the store.
+    ///
+    /// # Arguments
+    /// * `entity_id` - The entity to remove.
+    pub fn remove_entity(&mut self, entity_id: &str) -> Option<EntityMetadata> {
+        let metadata = self.entity_metadata_map.remove(entity_id)?;
+
+        // Remove the mapping between each of the entity's topics and the entity.
+        for topic in metadata.topics.keys() {
+            self.topic_entity_map.remove(topic);
+        }
+
+        Some(metadata)
+    }
+
     /// Returns whether a specific entity is in the store.
     ///
     /// # Arguments
@@ -80,6 +97,16 @@ impl ManagedSubscribeStore {
         self.topic_entity_map.get(topic)
     }
 
+    /// Gets the number of topics in the store.
+    pub fn topic_count(&self) -> usize {
+        self.topic_entity_map.len()
+    }
+
+    /// Gets the topics in the store.
+    pub fn topics(&self) -> Vec<String> {
+        self.topic_entity_map.keys().cloned().collect()
+    }
+
     /// Adds a topic to the store.
     ///
     /// # Arguments
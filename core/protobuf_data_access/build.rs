@@ -2,15 +2,24 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+use std::env;
+use std::path::PathBuf;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The descriptor sets are used by the gRPC server reflection service.
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
     tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("invehicle_digital_twin_descriptor.bin"))
         .message_attribute("EndpointInfo", "#[derive(serde::Deserialize, serde::Serialize)]")
         .message_attribute("EntityAccessInfo", "#[derive(serde::Deserialize, serde::Serialize)]")
+        .enum_attribute("RegistrationPolicy", "#[derive(serde::Deserialize, serde::Serialize)]")
         .compile(
             &["../../interfaces/invehicle_digital_twin/v1/invehicle_digital_twin.proto"],
             &["../../interfaces/invehicle_digital_twin/v1/"],
         )?;
     tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("managed_subscribe_descriptor.bin"))
         .message_attribute("Constraint", "#[derive(serde::Deserialize, serde::Serialize)]")
         .message_attribute("CallbackPayload", "#[derive(serde::Deserialize, serde::Serialize)]")
         .message_attribute("SubscriptionInfo", "#[derive(serde::Deserialize, serde::Serialize)]")
@@ -18,34 +27,52 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             &["../../interfaces/module/managed_subscribe/v1/managed_subscribe.proto"],
             &["../../interfaces/module/managed_subscribe/v1/"],
         )?;
-    tonic_build::configure().compile(
-        &["../../interfaces/module/digital_twin_graph/v1/digital_twin_graph.proto"],
-        &["../../interfaces/module/digital_twin_graph/v1/"],
-    )?;
-    tonic_build::configure().compile(
-        &["../../interfaces/module/digital_twin_registry/v1/digital_twin_registry.proto"],
-        &["../../interfaces/module/digital_twin_registry/v1/"],
-    )?;
-    tonic_build::configure().compile(
-        &["../../external/chariott/service_discovery/proto/core/v1/service_registry.proto"],
-        &["../../external/chariott/service_discovery/proto/core/v1/"],
-    )?;
-    tonic_build::configure().compile(
-        &["../../external/agemo/proto/pubsub/v1/pubsub.proto"],
-        &["../../external/agemo/proto/pubsub/v1/"],
-    )?;
-    tonic_build::configure().compile(
-        &["../../external/agemo/proto/publisher/v1/publisher.proto"],
-        &["../../external/agemo/proto/publisher/v1/"],
-    )?;
-    tonic_build::configure().compile(
-        &["../../interfaces/async_rpc/v1/request.proto"],
-        &["../../interfaces/async_rpc/v1/"],
-    )?;
-    tonic_build::configure().compile(
-        &["../../interfaces/async_rpc/v1/respond.proto"],
-        &["../../interfaces/async_rpc/v1/"],
-    )?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("digital_twin_graph_descriptor.bin"))
+        .compile(
+            &["../../interfaces/module/digital_twin_graph/v1/digital_twin_graph.proto"],
+            &["../../interfaces/module/digital_twin_graph/v1/"],
+        )?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("digital_twin_registry_descriptor.bin"))
+        .message_attribute("EntityAccessInfo", "#[derive(serde::Deserialize, serde::Serialize)]")
+        .compile(
+            &["../../interfaces/module/digital_twin_registry/v1/digital_twin_registry.proto"],
+            &["../../interfaces/module/digital_twin_registry/v1/"],
+        )?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("admin_descriptor.bin"))
+        .compile(&["../../interfaces/admin/v1/admin.proto"], &["../../interfaces/admin/v1/"])?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("service_registry_descriptor.bin"))
+        .compile(
+            &["../../external/chariott/service_discovery/proto/core/v1/service_registry.proto"],
+            &["../../external/chariott/service_discovery/proto/core/v1/"],
+        )?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("pubsub_descriptor.bin"))
+        .compile(
+            &["../../external/agemo/proto/pubsub/v1/pubsub.proto"],
+            &["../../external/agemo/proto/pubsub/v1/"],
+        )?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("publisher_descriptor.bin"))
+        .compile(
+            &["../../external/agemo/proto/publisher/v1/publisher.proto"],
+            &["../../external/agemo/proto/publisher/v1/"],
+        )?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("request_descriptor.bin"))
+        .compile(
+            &["../../interfaces/async_rpc/v1/request.proto"],
+            &["../../interfaces/async_rpc/v1/"],
+        )?;
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("respond_descriptor.bin"))
+        .compile(
+            &["../../interfaces/async_rpc/v1/respond.proto"],
+            &["../../interfaces/async_rpc/v1/"],
+        )?;
 
     Ok(())
 }
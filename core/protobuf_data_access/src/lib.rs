@@ -2,9 +2,27 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+//! The generated gRPC code of the packages. Each package's module also has the encoded file
+//! descriptor set of the package, FILE_DESCRIPTOR_SET, for gRPC server reflection.
+
+use prost::Message;
+use prost_types::FileDescriptorSet;
+
 pub mod invehicle_digital_twin {
     pub mod v1 {
         tonic::include_proto!("invehicle_digital_twin");
+
+        pub const FILE_DESCRIPTOR_SET: &[u8] =
+            tonic::include_file_descriptor_set!("invehicle_digital_twin_descriptor");
+    }
+}
+
+pub mod admin {
+    pub mod v1 {
+        tonic::include_proto!("admin.v1.admin");
+
+        pub const FILE_DESCRIPTOR_SET: &[u8] =
+            tonic::include_file_descriptor_set!("admin_descriptor");
     }
 }
 
@@ -12,9 +30,15 @@ pub mod async_rpc {
     pub mod v1 {
         pub mod respond {
             tonic::include_proto!("async_rpc.v1.respond");
+
+            pub const FILE_DESCRIPTOR_SET: &[u8] =
+                tonic::include_file_descriptor_set!("respond_descriptor");
         }
         pub mod request {
             tonic::include_proto!("async_rpc.v1.request");
+
+            pub const FILE_DESCRIPTOR_SET: &[u8] =
+                tonic::include_file_descriptor_set!("request_descriptor");
         }
     }
 }
@@ -23,16 +47,25 @@ pub mod module {
     pub mod managed_subscribe {
         pub mod v1 {
             tonic::include_proto!("managed_subscribe");
+
+            pub const FILE_DESCRIPTOR_SET: &[u8] =
+                tonic::include_file_descriptor_set!("managed_subscribe_descriptor");
         }
     }
     pub mod digital_twin_graph {
         pub mod v1 {
             tonic::include_proto!("digital_twin_graph.v1.digital_twin_graph");
+
+            pub const FILE_DESCRIPTOR_SET: &[u8] =
+                tonic::include_file_descriptor_set!("digital_twin_graph_descriptor");
         }
     }
     pub mod digital_twin_registry {
         pub mod v1 {
             tonic::include_proto!("digital_twin_registry.v1.digital_twin_registry");
+
+            pub const FILE_DESCRIPTOR_SET: &[u8] =
+                tonic::include_file_descriptor_set!("digital_twin_registry_descriptor");
         }
     }
 }
@@ -42,6 +75,9 @@ pub mod chariott {
         pub mod core {
             pub mod v1 {
                 tonic::include_proto!("service_registry");
+
+                pub const FILE_DESCRIPTOR_SET: &[u8] =
+                    tonic::include_file_descriptor_set!("service_registry_descriptor");
             }
         }
     }
@@ -51,11 +87,75 @@ pub mod agemo {
     pub mod pubsub {
         pub mod v1 {
             tonic::include_proto!("pubsub");
+
+            pub const FILE_DESCRIPTOR_SET: &[u8] =
+                tonic::include_file_descriptor_set!("pubsub_descriptor");
         }
     }
     pub mod publisher {
         pub mod v1 {
             tonic::include_proto!("publisher");
+
+            pub const FILE_DESCRIPTOR_SET: &[u8] =
+                tonic::include_file_descriptor_set!("publisher_descriptor");
         }
     }
 }
+
+/// The encoded file descriptor sets of the packages that define services that the app server can
+/// host.
+pub const HOSTABLE_FILE_DESCRIPTOR_SETS: &[&[u8]] = &[
+    invehicle_digital_twin::v1::FILE_DESCRIPTOR_SET,
+    async_rpc::v1::respond::FILE_DESCRIPTOR_SET,
+    module::managed_subscribe::v1::FILE_DESCRIPTOR_SET,
+    module::digital_twin_graph::v1::FILE_DESCRIPTOR_SET,
+    module::digital_twin_registry::v1::FILE_DESCRIPTOR_SET,
+    agemo::publisher::v1::FILE_DESCRIPTOR_SET,
+];
+
+/// Get the encoded file descriptor sets of the packages that define any of the services, for gRPC
+/// server reflection, so that only the hosted services are described.
+///
+/// # Arguments
+/// * `service_names` - The full names of the services, e.g.
+///                     "invehicle_digital_twin.InvehicleDigitalTwin".
+pub fn get_file_descriptor_sets(service_names: &[String]) -> Vec<&'static [u8]> {
+    HOSTABLE_FILE_DESCRIPTOR_SETS
+        .iter()
+        .copied()
+        .filter(|file_descriptor_set| {
+            let Ok(file_descriptor_set) = FileDescriptorSet::decode(*file_descriptor_set) else {
+                return false;
+            };
+            file_descriptor_set.file.iter().any(|file| {
+                file.service.iter().any(|service| {
+                    let service_name = format!("{}.{}", file.package(), service.name());
+                    service_names.contains(&service_name)
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod protobuf_data_access_tests {
+    use super::*;
+
+    #[test]
+    fn get_file_descriptor_sets_test() {
+        let file_descriptor_sets = get_file_descriptor_sets(&[
+            String::from("invehicle_digital_twin.InvehicleDigitalTwin"),
+            String::from("publisher.PublisherCallback"),
+            String::from("unknown.Unknown"),
+        ]);
+
+        assert_eq!(
+            file_descriptor_sets,
+            [
+                invehicle_digital_twin::v1::FILE_DESCRIPTOR_SET,
+                agemo::publisher::v1::FILE_DESCRIPTOR_SET
+            ]
+        );
+        assert!(get_file_descriptor_sets(&[]).is_empty());
+    }
+}
@@ -45,8 +45,10 @@ async fn register_show_notification(
     let mut client = InvehicleDigitalTwinClient::connect(invehicle_digital_twin_uri.to_string())
         .await
         .map_err(|e| Status::internal(e.to_string()))?;
-    let request =
-        tonic::Request::new(RegisterRequest { entity_access_info_list: vec![entity_access_info] });
+    let request = tonic::Request::new(RegisterRequest {
+        entity_access_info_list: vec![entity_access_info],
+        ..Default::default()
+    });
     let _response = client.register(request).await?;
 
     Ok(())
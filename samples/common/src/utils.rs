@@ -17,10 +17,14 @@ use samples_protobuf_data_access::invehicle_digital_twin::v1::invehicle_digital_
 use samples_protobuf_data_access::invehicle_digital_twin::v1::{EndpointInfo, FindByIdRequest};
 use std::future::Future;
 use tokio::time::{sleep, Duration};
+use tonic::metadata::MetadataMap;
 use tonic::{Code, Request, Status};
 
 const IBEJI_HOME_VAR_NAME: &str = "IBEJI_HOME";
 
+/// The metadata keys that carry a call's trace context, in the W3C Trace Context format.
+const TRACE_CONTEXT_METADATA_KEYS: &[&str] = &["traceparent", "tracestate"];
+
 /// Load the settings.
 ///
 /// # Arguments
@@ -232,6 +236,23 @@ pub async fn retrieve_invehicle_digital_twin_uri(
     Ok(result)
 }
 
+/// Get the trace context that a call's metadata carries, so that it can be passed on in the
+/// metadata of the calls that the call leads to, e.g. from an ask to its answer, and they are all
+/// in the same trace.
+///
+/// # Arguments
+/// * `metadata` - The call's metadata.
+pub fn get_trace_context(metadata: &MetadataMap) -> MetadataMap {
+    let mut trace_context = MetadataMap::new();
+    for key in TRACE_CONTEXT_METADATA_KEYS {
+        if let Some(value) = metadata.get(*key) {
+            trace_context.insert(*key, value.clone());
+        }
+    }
+
+    trace_context
+}
+
 #[cfg(test)]
 mod ibeji_common_utils_tests {
     use super::*;
@@ -257,4 +278,19 @@ mod ibeji_common_utils_tests {
         ));
         assert!(!is_subset(&["one".to_string(), "two".to_string(), "three".to_string()], &[]));
     }
+
+    #[test]
+    fn get_trace_context_test() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".parse().unwrap(),
+        );
+        metadata.insert("authorization", "Bearer token".parse().unwrap());
+
+        let trace_context = get_trace_context(&metadata);
+        assert_eq!(trace_context.get("traceparent"), metadata.get("traceparent"));
+        assert!(trace_context.get("tracestate").is_none());
+        assert!(trace_context.get("authorization").is_none());
+    }
 }
@@ -186,6 +186,7 @@ async fn register_seat_massagers(
 
         let request = tonic::Request::new(RegisterRequest {
             entity_access_info_list: entity_access_info_list.clone(),
+            ..Default::default()
         });
 
         info!("Sending a register request to the In-Vehicle Digital Twin Service URI {invehicle_digital_twin_uri}");
@@ -7,6 +7,7 @@ use digital_twin_model::sdv_v1 as sdv;
 use log::{info, warn};
 use parking_lot::{Mutex, MutexGuard};
 use samples_common::constants::digital_twin_operation;
+use samples_common::utils::get_trace_context;
 use samples_protobuf_data_access::async_rpc::v1::request::{
     request_server::Request, AskRequest, AskResponse, NotifyRequest, NotifyResponse,
 };
@@ -17,6 +18,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
 use tokio_retry::Retry;
+use tonic::metadata::MetadataMap;
 
 /// Instance data.
 #[derive(Clone, Debug, Default)]
@@ -54,11 +56,13 @@ impl RequestImpl {
     /// * `respond_uri` - Respond URI.
     /// * `ask_id` - Ask Id.
     /// * `targeted_payload` - Targeted payload.
+    /// * `trace_context` - The ask's trace context, which the answer carries on.
     async fn get(
         &self,
         respond_uri: String,
         ask_id: String,
         targeted_payload: TargetedPayload,
+        trace_context: MetadataMap,
     ) -> Result<tonic::Response<AskResponse>, tonic::Status> {
         if !targeted_payload.payload.is_empty() {
             return Err(tonic::Status::invalid_argument(
@@ -101,10 +105,11 @@ impl RequestImpl {
                     .map_err(|err_msg| format!("Unable to connect due to: {err_msg}"))?;
 
                 // Prepare the answer request.
-                let answer_request = tonic::Request::new(AnswerRequest {
+                let mut answer_request = tonic::Request::new(AnswerRequest {
                     ask_id: ask_id.clone(),
                     payload: instance_value.clone(),
                 });
+                *answer_request.metadata_mut() = trace_context.clone();
 
                 // Send the answer to the consumer.
                 client
@@ -124,12 +129,14 @@ impl RequestImpl {
     /// * `respond_uri` - Respond URI.
     /// * `ask_id` - Ask ID.
     /// * `targeted_payload` - Targeted payload.
+    /// * `trace_context` - The ask's trace context, which the answer carries on.
     #[allow(unused_assignments)]
     async fn invoke(
         &self,
         respond_uri: String,
         ask_id: String,
         targeted_payload: TargetedPayload,
+        trace_context: MetadataMap,
     ) -> Result<tonic::Response<AskResponse>, tonic::Status> {
         if targeted_payload.payload.is_empty() {
             return Err(tonic::Status::invalid_argument(
@@ -190,10 +197,11 @@ impl RequestImpl {
                     .map_err(|err_msg| format!("Unable to connect due to: {err_msg}"))?;
 
                 // Prepare the answer request.
-                let answer_request = tonic::Request::new(AnswerRequest {
+                let mut answer_request = tonic::Request::new(AnswerRequest {
                     ask_id: ask_id.clone(),
                     payload: response_payload.clone(),
                 });
+                *answer_request.metadata_mut() = trace_context.clone();
 
                 // Send the answer to the consumer.
                 client
@@ -239,6 +247,7 @@ impl Request for RequestImpl {
         &self,
         request: tonic::Request<AskRequest>,
     ) -> Result<tonic::Response<AskResponse>, tonic::Status> {
+        let trace_context = get_trace_context(request.metadata());
         let ask_request: AskRequest = request.into_inner();
 
         info!("Received an ask request:");
@@ -254,9 +263,21 @@ impl Request for RequestImpl {
         info!("  operation: {}", targeted_payload_json.operation);
 
         if targeted_payload_json.operation == digital_twin_operation::GET {
-            self.get(ask_request.respond_uri, ask_request.ask_id, targeted_payload_json).await
+            self.get(
+                ask_request.respond_uri,
+                ask_request.ask_id,
+                targeted_payload_json,
+                trace_context,
+            )
+            .await
         } else if targeted_payload_json.operation == digital_twin_operation::INVOKE {
-            self.invoke(ask_request.respond_uri, ask_request.ask_id, targeted_payload_json).await
+            self.invoke(
+                ask_request.respond_uri,
+                ask_request.ask_id,
+                targeted_payload_json,
+                trace_context,
+            )
+            .await
         } else {
             Err(tonic::Status::invalid_argument(format!(
                 "Unexpected operation '{}'.  Expected '{}' or '{}'.",
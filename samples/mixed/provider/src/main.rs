@@ -129,7 +129,8 @@ async fn register_entities(
     let mut client = InvehicleDigitalTwinClient::connect(invehicle_digital_twin_uri.to_string())
         .await
         .map_err(|e| Status::internal(e.to_string()))?;
-    let request = tonic::Request::new(RegisterRequest { entity_access_info_list });
+    let request =
+        tonic::Request::new(RegisterRequest { entity_access_info_list, ..Default::default() });
     let _response = client.register(request).await?;
 
     Ok(())
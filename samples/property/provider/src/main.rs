@@ -2,6 +2,7 @@
 // Licensed under the MIT license.
 // SPDX-License-Identifier: MIT
 
+use common::dtmi::{Dtmi, DtmiError};
 use digital_twin_model::{sdv_v0 as sdv, Metadata};
 use env_logger::{Builder, Target};
 use log::{debug, info, warn, LevelFilter};
@@ -56,8 +57,10 @@ async fn register_ambient_air_temperature(
     let mut client = InvehicleDigitalTwinClient::connect(invehicle_digital_twin_uri.to_string())
         .await
         .map_err(|e| Status::internal(e.to_string()))?;
-    let request =
-        tonic::Request::new(RegisterRequest { entity_access_info_list: vec![entity_access_info] });
+    let request = tonic::Request::new(RegisterRequest {
+        entity_access_info_list: vec![entity_access_info],
+        ..Default::default()
+    });
     let _response = client.register(request).await?;
 
     Ok(())
@@ -161,21 +164,10 @@ fn publish_message(broker_uri: &str, topic: &str, content: &str) -> Result<(), S
 ///
 /// # Arguments
 /// `dtmi` - The DTMI.
-fn convert_dtmi_to_topic(dtmi: &str) -> Result<String, String> {
-    let parts: Vec<&str> = dtmi.split(&[':', ';']).collect();
-    if parts[0] != "dtmi" {
-        return Err("Invalid dtmi".to_string());
-    }
-    let mut topic: String = "".to_string();
-    // We will start at index 1 to skip the scheme.
-    for i in 1..parts.len() {
-        topic.push_str(parts[i]);
-        if i != (parts.len() - 1) {
-            topic.push('/');
-        }
-    }
+fn convert_dtmi_to_topic(dtmi: &str) -> Result<String, DtmiError> {
+    let dtmi = Dtmi::parse(dtmi)?;
 
-    Ok(topic)
+    Ok(format!("{}/{}", dtmi.segments().join("/"), dtmi.version()))
 }
 
 #[tokio::main]
@@ -5,6 +5,7 @@
 use digital_twin_model::sdv_v1 as sdv;
 use log::{debug, error, info, warn};
 use parking_lot::Mutex;
+use samples_common::utils::get_trace_context;
 use samples_protobuf_data_access::async_rpc::v1::request::{
     request_server::Request, AskRequest, AskResponse, NotifyRequest, NotifyResponse,
 };
@@ -32,6 +33,7 @@ impl Request for RequestImpl {
         &self,
         request: tonic::Request<AskRequest>,
     ) -> Result<tonic::Response<AskResponse>, tonic::Status> {
+        let trace_context = get_trace_context(request.metadata());
         let request_inner = request.into_inner();
         let respond_uri: String = request_inner.respond_uri.clone();
         let ask_id: String = request_inner.ask_id.clone();
@@ -99,8 +101,9 @@ impl Request for RequestImpl {
             let response_payload_json: String =
                 serde_json::to_string_pretty(&response_payload).unwrap();
 
-            let answer_request =
+            let mut answer_request =
                 tonic::Request::new(AnswerRequest { ask_id, payload: response_payload_json });
+            *answer_request.metadata_mut() = trace_context;
 
             // Send the answer.
             let response = client.answer(answer_request).await;